@@ -35,6 +35,16 @@ impl Camera {
         view_matrix.inverse()
     }
 
+    /// The position the camera views the world from.
+    pub fn eye_position(&self) -> glam::Vec3 {
+        self.view().inverse().w_axis.truncate()
+    }
+
+    /// The direction the camera is looking in, in world space.
+    pub fn look_direction(&self) -> glam::Vec3 {
+        -self.view().inverse().z_axis.truncate().normalize()
+    }
+
     pub fn projection(&self) -> glam::Mat4 {
         glam::Mat4::perspective_rh(
             self.field_of_view * PI / 180.0,
@@ -2,19 +2,52 @@ mod camera;
 mod renderer;
 mod transform;
 mod world;
-use std::{f32::consts::PI};
+use std::{f32::consts::PI, sync::Arc};
 
 use camera::Camera;
+use renderer::chunk_mesher::MeshingMode;
 use renderer::vulkan::vulkan_renderer::VulkanRenderer;
 use renderer::world_render_system::WorldRenderSystem;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 
 use transform::Transform;
 use world::{
-    world_generation_system::{self, WorldGenerationSettings},
+    block::Block,
+    block_position::BlockPosition,
+    block_registry::BlockRegistry,
+    chunk_manager::{ChunkManager, ChunkManagerSettings},
+    crop_growth::CropGrowth,
+    fluid_simulator::FluidSimulator,
+    world::World,
+    world_generation_system::ChunkGenerator,
+    world_ticker::{WorldTicker, WorldTickerSettings},
 };
 
+const BLOCK_REGISTRY_PATH: &str = "assets/blocks.ron";
+const BLOCK_TEXTURES_DIRECTORY: &str = "assets/textures";
+const WORLD_SAVE_DIRECTORY: &str = "saves/world";
+const WORLD_SEED: u64 = 10;
+const SEA_LEVEL: i32 = 64;
+
+/// How many blocks above the ground at the spawn point the camera starts.
+const SPAWN_HEIGHT_ABOVE_GROUND: i32 = 3;
+/// How far away blocks can be broken and placed.
+const BLOCK_REACH: f32 = 8.0;
+/// The blocks that can be placed, selected with the number keys.
+const PLACEABLE_BLOCKS: [&str; 9] = [
+    "vox:stone",
+    "vox:dirt",
+    "vox:grass",
+    "vox:log",
+    "vox:leaves",
+    "vox:glass",
+    "vox:glowstone",
+    "vox:water",
+    "vox:lava",
+];
+
 fn main() {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -34,7 +67,7 @@ fn main() {
 
     sdl_context.mouse().set_relative_mouse_mode(true);
     let mut camera = Camera {
-        transform: Transform::new(glam::vec3(0.0, 0.0, 3.0), glam::vec3(0.0, 0.0, 0.0)),
+        transform: Transform::new(glam::vec3(0.0, 100.0, 3.0), glam::vec3(0.0, 0.0, 0.0)),
         local_transform: Transform::default(),
         near_clipping_plane: 0.01,
         far_clipping_plane: 3000.0,
@@ -44,23 +77,51 @@ fn main() {
         ..Camera::default()
     };
 
-    let world = world_generation_system::generate_world(
-        10,
-        WorldGenerationSettings {
-            max_width: 16,
-            max_height: 16,
-            max_length: 16,
+    let block_registry =
+        Arc::new(BlockRegistry::load(BLOCK_REGISTRY_PATH).expect("failed to load block registry"));
+
+    let mut world = World::open(WORLD_SAVE_DIRECTORY, block_registry.clone());
+    let mut chunk_manager = ChunkManager::new(
+        ChunkGenerator::new(WORLD_SEED, SEA_LEVEL, &block_registry),
+        ChunkManagerSettings {
+            render_distance: 16,
+            vertical_render_distance: 8,
+            max_chunks_loaded_per_update: 64,
         },
     );
 
+    // The chunks around the spawn point are loaded up front so the camera can start above the ground.
+    chunk_manager.update(&mut world, camera.transform.position);
+    let spawn_position = camera.transform.position.floor().as_ivec3();
+    if let Some(ground_height) =
+        world.get_height_at_position(BlockPosition::new(spawn_position.x, 0, spawn_position.z))
+    {
+        camera.transform.position.y = (ground_height + SPAWN_HEIGHT_ABOVE_GROUND) as f32;
+    }
+
     let timer_subsystem = sdl_context.timer().unwrap();
     let mut current_render_tick_time = timer_subsystem.performance_counter();
     let mut last_render_tick_time = current_render_tick_time.clone();
     let mut delta_time = 0.0;
+    let mut world_ticker = WorldTicker::new(
+        WorldTickerSettings {
+            ticks_per_second: 20.0,
+            random_ticks_per_chunk: 3,
+        },
+        block_registry.clone(),
+    );
+    world_ticker.add_tick_handler(FluidSimulator::new(block_registry.clone()));
+    world_ticker.add_tick_handler(CropGrowth::new(block_registry.clone()));
 
     let camera_movement_speed = 250.0;
-    let mut world_render_system = WorldRenderSystem::new(&renderer);
-    world_render_system.build_chunk_meshes(&world);
+    let mut selected_block = 0;
+    let mut world_render_system = WorldRenderSystem::new(
+        &renderer,
+        block_registry.clone(),
+        BLOCK_TEXTURES_DIRECTORY,
+        MeshingMode::Greedy,
+    )
+    .unwrap();
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
@@ -131,6 +192,41 @@ fn main() {
                 } => {
                     camera.transform.position = glam::Vec3::default();
                 }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(slot) = hotbar_slot(keycode) {
+                        selected_block = slot;
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(hit) =
+                        world.raycast(camera.eye_position(), camera.look_direction(), BLOCK_REACH)
+                    {
+                        world.set_block_at_position(hit.block_position, Block::default());
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Right,
+                    ..
+                } => {
+                    let hit =
+                        world.raycast(camera.eye_position(), camera.look_direction(), BLOCK_REACH);
+                    let block = block_registry.block(PLACEABLE_BLOCKS[selected_block]);
+
+                    if let (Some(hit), Some(block)) = (hit, block) {
+                        let (x, y, z) = hit.face.offset();
+                        let place_position = hit.block_position.offset(x, y, z);
+
+                        if world.get_block_at_position(place_position).is_none() {
+                            world.set_block_at_position(place_position, block);
+                        }
+                    }
+                }
 
                 Event::MouseMotion { xrel, yrel, .. } => {
                     camera.rotate_yaw((xrel as f32 * delta_time * 10.0) * PI / 180.0);
@@ -140,6 +236,10 @@ fn main() {
             }
         }
 
+        chunk_manager.update(&mut world, camera.transform.position);
+        world_ticker.update(&mut world, delta_time);
+        world_render_system.update_chunk_meshes(&mut world);
+
         last_render_tick_time = current_render_tick_time;
         current_render_tick_time = timer_subsystem.performance_counter();
 
@@ -149,4 +249,24 @@ fn main() {
             / timer_subsystem.performance_frequency() as f32;
         // dbg!(delta_time);
     }
+
+    world
+        .save(WORLD_SAVE_DIRECTORY)
+        .expect("failed to save world");
+}
+
+/// The slot of [PLACEABLE_BLOCKS] selected by a number key.
+fn hotbar_slot(keycode: Keycode) -> Option<usize> {
+    match keycode {
+        Keycode::Num1 => Some(0),
+        Keycode::Num2 => Some(1),
+        Keycode::Num3 => Some(2),
+        Keycode::Num4 => Some(3),
+        Keycode::Num5 => Some(4),
+        Keycode::Num6 => Some(5),
+        Keycode::Num7 => Some(6),
+        Keycode::Num8 => Some(7),
+        Keycode::Num9 => Some(8),
+        _ => None,
+    }
 }
@@ -0,0 +1,218 @@
+use std::{collections::HashMap, fs::File, path::Path};
+
+use crate::world::{
+    block::{Axis, Block, StateFlags, StateValue},
+    block_registry::{BlockRegistry, AIR_IDENTIFIER},
+    direction::Direction,
+};
+
+/// The width and height every block texture must have, in pixels.
+pub const BLOCK_TEXTURE_SIZE: u32 = 16;
+
+const TOP: usize = 0;
+const SIDE: usize = 1;
+const BOTTOM: usize = 2;
+
+#[derive(Debug)]
+pub enum BlockTextureError {
+    Io(std::io::Error),
+    Decode(png::DecodingError),
+    WrongSize(String, u32, u32),
+    UnsupportedFormat(String, png::ColorType, png::BitDepth),
+}
+
+impl From<std::io::Error> for BlockTextureError {
+    fn from(err: std::io::Error) -> Self {
+        BlockTextureError::Io(err)
+    }
+}
+
+impl From<png::DecodingError> for BlockTextureError {
+    fn from(err: png::DecodingError) -> Self {
+        BlockTextureError::Decode(err)
+    }
+}
+
+/// The pixels of every block texture, ready to be uploaded into a texture array
+/// with one layer per texture.
+pub struct BlockTextureArray {
+    pub layer_count: u32,
+    /// RGBA8 pixels of every layer for each mip level, starting with the full size level.
+    /// The layers of a level follow each other.
+    pub mip_levels: Vec<Vec<u8>>,
+}
+
+impl BlockTextureArray {
+    pub fn mip_level_size(mip_level: usize) -> u32 {
+        (BLOCK_TEXTURE_SIZE >> mip_level).max(1)
+    }
+}
+
+/// Gives every texture named by the block types in the registry a layer in the block texture array,
+/// and maps each face of each block type to its layer.
+pub struct BlockTextures {
+    /// The texture names, in layer order.
+    texture_names: Vec<String>,
+    /// The top, side and bottom layers, indexed by block id.
+    face_layers: Vec<[u32; 3]>,
+    /// Whether the block type has an axis, indexed by block id.
+    has_axis: Vec<bool>,
+}
+
+impl BlockTextures {
+    pub fn new(block_registry: &BlockRegistry) -> Self {
+        let mut texture_names = Vec::new();
+        let mut layers_by_name = HashMap::new();
+        let mut face_layers = Vec::new();
+        let mut has_axis = Vec::new();
+
+        for block_type in block_registry.block_types() {
+            // Air is never drawn, so it doesn't get any textures.
+            if block_type.identifier == AIR_IDENTIFIER {
+                face_layers.push([0; 3]);
+                has_axis.push(false);
+                continue;
+            }
+
+            face_layers.push(block_type.face_textures().map(|texture_name| {
+                *layers_by_name
+                    .entry(texture_name.to_owned())
+                    .or_insert_with(|| {
+                        texture_names.push(texture_name.to_owned());
+                        texture_names.len() as u32 - 1
+                    })
+            }));
+            has_axis.push(block_type.declares(StateFlags::Axis));
+        }
+
+        BlockTextures {
+            texture_names,
+            face_layers,
+            has_axis,
+        }
+    }
+
+    pub fn texture_names(&self) -> &[String] {
+        self.texture_names.as_ref()
+    }
+
+    /// The texture array layer the face of the block is drawn with.
+    pub fn texture_index(&self, block: Block, direction: Direction) -> u32 {
+        let Some(face_layers) = self.face_layers.get(block.id as usize) else {
+            return 0;
+        };
+
+        let axis = match self.has_axis[block.id as usize] {
+            true => Axis::from_bits(StateFlags::Axis.get(block.state)).unwrap_or(Axis::Y),
+            false => Axis::Y,
+        };
+
+        let face = match (axis, direction) {
+            (Axis::X, Direction::West) | (Axis::Y, Direction::Up) | (Axis::Z, Direction::North) => {
+                TOP
+            }
+            (Axis::X, Direction::East)
+            | (Axis::Y, Direction::Down)
+            | (Axis::Z, Direction::South) => BOTTOM,
+            _ => SIDE,
+        };
+
+        face_layers[face]
+    }
+
+    /// Loads `<name>.png` from the directory for every texture, and generates their mip levels.
+    pub fn load_texture_array(
+        &self,
+        directory: impl AsRef<Path>,
+    ) -> Result<BlockTextureArray, BlockTextureError> {
+        let mut pixels = Vec::new();
+        for texture_name in &self.texture_names {
+            let path = directory.as_ref().join(format!("{texture_name}.png"));
+            pixels.extend(BlockTextures::load_texture(texture_name, &path)?);
+        }
+
+        let mip_level_count = BLOCK_TEXTURE_SIZE.ilog2() as usize + 1;
+        let mut mip_levels = vec![pixels];
+        for mip_level in 1..mip_level_count {
+            let next_level = BlockTextures::downsample(
+                &mip_levels[mip_level - 1],
+                BlockTextureArray::mip_level_size(mip_level - 1),
+            );
+            mip_levels.push(next_level);
+        }
+
+        Ok(BlockTextureArray {
+            layer_count: self.texture_names.len() as u32,
+            mip_levels,
+        })
+    }
+
+    fn load_texture(texture_name: &str, path: &Path) -> Result<Vec<u8>, BlockTextureError> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        // Expand palettes and grayscale so every texture comes out as RGB(A)8.
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        buffer.truncate(info.buffer_size());
+
+        if info.width != BLOCK_TEXTURE_SIZE || info.height != BLOCK_TEXTURE_SIZE {
+            return Err(BlockTextureError::WrongSize(
+                texture_name.to_owned(),
+                info.width,
+                info.height,
+            ));
+        }
+
+        match (info.color_type, info.bit_depth) {
+            (png::ColorType::Rgba, png::BitDepth::Eight) => Ok(buffer),
+            (png::ColorType::Rgb, png::BitDepth::Eight) => Ok(buffer
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect()),
+            (color_type, bit_depth) => Err(BlockTextureError::UnsupportedFormat(
+                texture_name.to_owned(),
+                color_type,
+                bit_depth,
+            )),
+        }
+    }
+
+    /// Halves the size of every layer by averaging each 2x2 block of pixels.
+    /// Colours are weighted by their alpha so fully transparent pixels don't darken the edges of cutouts.
+    fn downsample(layers: &[u8], size: u32) -> Vec<u8> {
+        let size = size as usize;
+        let half_size = (size / 2).max(1);
+        let layer_len = size * size * 4;
+
+        let mut downsampled = Vec::with_capacity(layers.len() / 4);
+        for layer in layers.chunks_exact(layer_len) {
+            for y in 0..half_size {
+                for x in 0..half_size {
+                    let mut color = [0u32; 3];
+                    let mut alpha = 0u32;
+                    for (sample_x, sample_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let sample_x = (x * 2 + sample_x).min(size - 1);
+                        let sample_y = (y * 2 + sample_y).min(size - 1);
+                        let pixel = &layer[(sample_x + sample_y * size) * 4..][..4];
+                        for channel in 0..3 {
+                            color[channel] += pixel[channel] as u32 * pixel[3] as u32;
+                        }
+                        alpha += pixel[3] as u32;
+                    }
+
+                    for channel in color {
+                        downsampled.push(match alpha {
+                            0 => 0,
+                            _ => (channel / alpha) as u8,
+                        });
+                    }
+                    downsampled.push((alpha / 4) as u8);
+                }
+            }
+        }
+
+        downsampled
+    }
+}
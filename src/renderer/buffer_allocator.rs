@@ -0,0 +1,215 @@
+use std::ops::Range;
+
+/// A range of a buffer handed out by a [BufferAllocator], in elements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferAllocation {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl BufferAllocation {
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.len
+    }
+
+    pub fn usize_range(&self) -> Range<usize> {
+        self.offset as usize..(self.offset + self.len) as usize
+    }
+}
+
+/// Hands out ranges of a fixed size buffer so that meshes can be written into it and freed
+/// independently of each other.
+///
+/// The ranges not in use are kept in a free list, and freed ranges are merged with the free
+/// ranges next to them. Allocating and freeing meshes of different sizes still fragments the
+/// buffer over time, so when no single free range is large enough but there is enough space
+/// in total, the owner of the buffer should move its contents to where [BufferAllocator::compact]
+/// reallocates them.
+pub struct BufferAllocator {
+    capacity: u64,
+    /// Sorted by offset, and never empty or touching each other.
+    free_ranges: Vec<Range<u64>>,
+}
+
+impl BufferAllocator {
+    pub fn new(capacity: u64) -> Self {
+        BufferAllocator {
+            capacity,
+            free_ranges: vec![Range {
+                start: 0,
+                end: capacity,
+            }],
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// The total number of free elements, which may be split over many ranges.
+    pub fn free_len(&self) -> u64 {
+        self.free_ranges
+            .iter()
+            .map(|free_range| free_range.end - free_range.start)
+            .sum()
+    }
+
+    /// Allocates `len` elements from the first free range large enough to hold them.
+    /// Returns `None` if there is no such range.
+    pub fn allocate(&mut self, len: u64) -> Option<BufferAllocation> {
+        let free_range_index = self
+            .free_ranges
+            .iter()
+            .position(|free_range| free_range.end - free_range.start >= len)?;
+
+        let free_range = &mut self.free_ranges[free_range_index];
+        let allocation = BufferAllocation {
+            offset: free_range.start,
+            len,
+        };
+
+        free_range.start += len;
+        if free_range.is_empty() {
+            self.free_ranges.remove(free_range_index);
+        }
+
+        Some(allocation)
+    }
+
+    /// Returns the allocation to the free list.
+    pub fn free(&mut self, allocation: BufferAllocation) {
+        if allocation.len == 0 {
+            return;
+        }
+
+        let range = allocation.range();
+        let index = self
+            .free_ranges
+            .partition_point(|free_range| free_range.start < range.start);
+
+        let merges_with_previous = index > 0 && self.free_ranges[index - 1].end == range.start;
+        let merges_with_next =
+            index < self.free_ranges.len() && self.free_ranges[index].start == range.end;
+
+        match (merges_with_previous, merges_with_next) {
+            (true, true) => {
+                self.free_ranges[index - 1].end = self.free_ranges[index].end;
+                self.free_ranges.remove(index);
+            }
+            (true, false) => self.free_ranges[index - 1].end = range.end,
+            (false, true) => self.free_ranges[index].start = range.start,
+            (false, false) => self.free_ranges.insert(index, range),
+        }
+    }
+
+    /// Frees every allocation.
+    pub fn clear(&mut self) {
+        self.free_ranges = vec![Range {
+            start: 0,
+            end: self.capacity,
+        }];
+    }
+
+    /// Frees every allocation and allocates them again in order, packed together from the start
+    /// of the buffer so all of the free space is left in one range at the end.
+    /// The allocations are updated to where they were moved to.
+    pub fn compact<'a>(&mut self, allocations: impl IntoIterator<Item = &'a mut BufferAllocation>) {
+        self.clear();
+        for allocation in allocations {
+            *allocation = self
+                .allocate(allocation.len)
+                .expect("allocations no longer fit in the buffer after compacting");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free_ranges(allocator: &BufferAllocator) -> Vec<(u64, u64)> {
+        allocator
+            .free_ranges
+            .iter()
+            .map(|free_range| (free_range.start, free_range.end))
+            .collect()
+    }
+
+    #[test]
+    fn allocations_come_from_the_first_free_range_large_enough() {
+        let mut allocator = BufferAllocator::new(10);
+        let first = allocator.allocate(3).unwrap();
+        let second = allocator.allocate(3).unwrap();
+        allocator.allocate(3).unwrap();
+        assert_eq!(first, BufferAllocation { offset: 0, len: 3 });
+        assert_eq!(second, BufferAllocation { offset: 3, len: 3 });
+
+        allocator.free(first);
+        assert_eq!(
+            allocator.allocate(2),
+            Some(BufferAllocation { offset: 0, len: 2 })
+        );
+        assert_eq!(allocator.allocate(2), None);
+        assert_eq!(
+            allocator.allocate(1),
+            Some(BufferAllocation { offset: 2, len: 1 })
+        );
+        assert_eq!(
+            allocator.allocate(1),
+            Some(BufferAllocation { offset: 9, len: 1 })
+        );
+        assert_eq!(allocator.free_len(), 0);
+    }
+
+    #[test]
+    fn freed_ranges_merge_with_the_free_ranges_next_to_them() {
+        let mut allocator = BufferAllocator::new(12);
+        let allocations: Vec<BufferAllocation> =
+            (0..4).map(|_| allocator.allocate(3).unwrap()).collect();
+
+        // Merges with the free range after it.
+        allocator.free(allocations[2]);
+        allocator.free(allocations[1]);
+        assert_eq!(free_ranges(&allocator), [(3, 9)]);
+
+        // Merges with the free range before it.
+        allocator.free(allocations[3]);
+        assert_eq!(free_ranges(&allocator), [(3, 12)]);
+
+        // Merges with the free ranges on both sides.
+        let allocation = allocator.allocate(3).unwrap();
+        allocator.free(allocations[0]);
+        assert_eq!(free_ranges(&allocator), [(0, 3), (6, 12)]);
+        allocator.free(allocation);
+        assert_eq!(free_ranges(&allocator), [(0, 12)]);
+        assert_eq!(
+            allocator.allocate(12),
+            Some(BufferAllocation { offset: 0, len: 12 })
+        );
+    }
+
+    #[test]
+    fn compact_packs_allocations_at_the_start_of_the_buffer() {
+        let mut allocator = BufferAllocator::new(10);
+        let mut allocations: Vec<BufferAllocation> = [2, 3, 1, 4]
+            .map(|len| allocator.allocate(len).unwrap())
+            .into();
+        allocator.free(allocations.remove(2));
+        allocator.free(allocations.remove(0));
+        assert_eq!(allocator.allocate(3), None);
+
+        allocator.compact(allocations.iter_mut());
+        assert_eq!(
+            allocations,
+            [
+                BufferAllocation { offset: 0, len: 3 },
+                BufferAllocation { offset: 3, len: 4 },
+            ]
+        );
+        assert_eq!(free_ranges(&allocator), [(7, 10)]);
+        assert_eq!(
+            allocator.allocate(3),
+            Some(BufferAllocation { offset: 7, len: 3 })
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::{collections::HashMap, ops::Range};
+
+use vulkano::{buffer::Subbuffer, sync::HostAccessError};
+
+use crate::world::block_position::BlockPosition;
+
+use super::{
+    buffer_allocator::{BufferAllocation, BufferAllocator},
+    mesh::Mesh,
+    staged_buffer::StagedBuffer,
+    vulkan::{default_lit_pipeline::MeshVertex, vulkan_renderer::VulkanRenderer},
+};
+
+#[derive(Debug)]
+pub enum ChunkBufferError {
+    HostAccess(HostAccessError),
+    /// The mesh doesn't fit in the buffers, even after compacting them.
+    OutOfSpace,
+}
+
+impl From<HostAccessError> for ChunkBufferError {
+    fn from(err: HostAccessError) -> Self {
+        ChunkBufferError::HostAccess(err)
+    }
+}
+
+/// A chunk's mesh, along with where it lives in the vertex and index buffers.
+/// The CPU side copy of the mesh is kept so the buffers can be compacted.
+struct ChunkMeshAllocation {
+    mesh: Mesh,
+    vertices: BufferAllocation,
+    indices: BufferAllocation,
+}
+
+/// A vertex and index buffer that the meshes of many chunks are written into,
+/// so that they can all be drawn from the same buffers.
+pub struct ChunkBuffers {
+    chunk_meshes: HashMap<BlockPosition, ChunkMeshAllocation>,
+    vertex_buffer: StagedBuffer<MeshVertex>,
+    vertex_allocator: BufferAllocator,
+    index_buffer: StagedBuffer<u32>,
+    index_allocator: BufferAllocator,
+
+    /// The ranges of the host buffers written since they were last uploaded.
+    dirty_vertex_ranges: Vec<Range<u64>>,
+    dirty_index_ranges: Vec<Range<u64>>,
+}
+
+impl ChunkBuffers {
+    pub fn new(renderer: &VulkanRenderer, vertex_capacity: u64, index_capacity: u64) -> Self {
+        ChunkBuffers {
+            chunk_meshes: Default::default(),
+            vertex_buffer: renderer.create_staged_vertex_buffer::<MeshVertex>(vertex_capacity),
+            vertex_allocator: BufferAllocator::new(vertex_capacity),
+            index_buffer: renderer.create_staged_index_buffer::<u32>(index_capacity),
+            index_allocator: BufferAllocator::new(index_capacity),
+            dirty_vertex_ranges: Vec::new(),
+            dirty_index_ranges: Vec::new(),
+        }
+    }
+
+    pub fn vertex_buffer(&self) -> &Subbuffer<[MeshVertex]> {
+        self.vertex_buffer.device_buffer()
+    }
+
+    pub fn index_buffer(&self) -> &Subbuffer<[u32]> {
+        self.index_buffer.device_buffer()
+    }
+
+    /// The range of the index buffer each chunk's mesh was written to.
+    pub fn index_ranges(&self) -> impl Iterator<Item = (BlockPosition, Range<u32>)> + '_ {
+        self.chunk_meshes
+            .iter()
+            .map(|(chunk_origin_position, allocation)| {
+                let indices = allocation.indices.range();
+                (
+                    *chunk_origin_position,
+                    indices.start as u32..indices.end as u32,
+                )
+            })
+    }
+
+    /// Frees the chunk's mesh from the buffers.
+    /// The indices are cleared so the mesh isn't drawn, the vertices are left as they are.
+    /// If the indices can't be cleared the mesh is kept, so its range is never handed to another
+    /// mesh while the old indices still point into it.
+    pub fn free_chunk_mesh(
+        &mut self,
+        chunk_origin_position: BlockPosition,
+    ) -> Result<(), HostAccessError> {
+        let Some(allocation) = self.chunk_meshes.get(&chunk_origin_position) else {
+            return Ok(());
+        };
+        let (vertices, indices) = (allocation.vertices, allocation.indices);
+
+        self.index_buffer.write()?[indices.usize_range()].fill(0);
+        self.dirty_index_ranges.push(indices.range());
+
+        self.chunk_meshes.remove(&chunk_origin_position);
+        self.vertex_allocator.free(vertices);
+        self.index_allocator.free(indices);
+
+        Ok(())
+    }
+
+    /// Replaces the chunk's mesh in the buffers.
+    pub fn write_chunk_mesh(
+        &mut self,
+        chunk_origin_position: BlockPosition,
+        mesh: Mesh,
+    ) -> Result<(), ChunkBufferError> {
+        self.free_chunk_mesh(chunk_origin_position)?;
+
+        if mesh.is_empty() {
+            return Ok(());
+        }
+
+        let allocations = match self.allocate_mesh(&mesh) {
+            Some(allocations) => allocations,
+            None => {
+                self.compact()?;
+                self.allocate_mesh(&mesh)
+                    .ok_or(ChunkBufferError::OutOfSpace)?
+            }
+        };
+
+        let allocation = ChunkMeshAllocation {
+            mesh,
+            vertices: allocations.0,
+            indices: allocations.1,
+        };
+        if let Err(err) = self.write_allocation(&allocation) {
+            self.vertex_allocator.free(allocation.vertices);
+            self.index_allocator.free(allocation.indices);
+            return Err(err.into());
+        }
+        self.chunk_meshes.insert(chunk_origin_position, allocation);
+
+        Ok(())
+    }
+
+    /// Uploads the ranges written since the last upload to the device.
+    pub fn upload(&mut self, renderer: &VulkanRenderer) {
+        self.vertex_buffer
+            .upload_ranges_to_device(renderer, &self.dirty_vertex_ranges);
+        self.index_buffer
+            .upload_ranges_to_device(renderer, &self.dirty_index_ranges);
+        self.dirty_vertex_ranges.clear();
+        self.dirty_index_ranges.clear();
+    }
+
+    fn allocate_mesh(&mut self, mesh: &Mesh) -> Option<(BufferAllocation, BufferAllocation)> {
+        let vertices = self
+            .vertex_allocator
+            .allocate(mesh.vertices().len() as u64)?;
+
+        match self.index_allocator.allocate(mesh.indicies().len() as u64) {
+            Some(indices) => Some((vertices, indices)),
+            None => {
+                self.vertex_allocator.free(vertices);
+                None
+            }
+        }
+    }
+
+    fn write_allocation(
+        &mut self,
+        allocation: &ChunkMeshAllocation,
+    ) -> Result<(), HostAccessError> {
+        let mut vertex_writer = self.vertex_buffer.write()?;
+        let mut index_writer = self.index_buffer.write()?;
+        ChunkBuffers::copy_mesh(&mut vertex_writer, &mut index_writer, allocation);
+
+        self.dirty_vertex_ranges.push(allocation.vertices.range());
+        self.dirty_index_ranges.push(allocation.indices.range());
+
+        Ok(())
+    }
+
+    fn copy_mesh(
+        vertex_writer: &mut [MeshVertex],
+        index_writer: &mut [u32],
+        allocation: &ChunkMeshAllocation,
+    ) {
+        vertex_writer[allocation.vertices.usize_range()]
+            .copy_from_slice(allocation.mesh.vertices());
+
+        // The indices are offset so they point at where the mesh's vertices were allocated,
+        // this lets every chunk be drawn from the one buffer.
+        for (existing, index) in index_writer[allocation.indices.usize_range()]
+            .iter_mut()
+            .zip(allocation.mesh.indicies())
+        {
+            *existing = *index + allocation.vertices.offset as u32;
+        }
+    }
+
+    /// Moves every mesh to the start of the buffers, merging all of the free space
+    /// left between them into one range at the end.
+    ///
+    /// Both buffers are locked before any mesh is moved, so failing to lock them leaves every mesh
+    /// where it was rather than half moved.
+    fn compact(&mut self) -> Result<(), HostAccessError> {
+        let mut vertex_writer = self.vertex_buffer.write()?;
+        let mut index_writer = self.index_buffer.write()?;
+
+        self.vertex_allocator.compact(
+            self.chunk_meshes
+                .values_mut()
+                .map(|allocation| &mut allocation.vertices),
+        );
+        self.index_allocator.compact(
+            self.chunk_meshes
+                .values_mut()
+                .map(|allocation| &mut allocation.indices),
+        );
+        for allocation in self.chunk_meshes.values() {
+            ChunkBuffers::copy_mesh(&mut vertex_writer, &mut index_writer, allocation);
+        }
+
+        // Everything past the compacted meshes is free, clear the indices left there.
+        let used_vertex_count = self.vertex_allocator.capacity() - self.vertex_allocator.free_len();
+        let used_index_count = self.index_allocator.capacity() - self.index_allocator.free_len();
+        index_writer[used_index_count as usize..].fill(0);
+        self.dirty_vertex_ranges.push(0..used_vertex_count);
+        self.dirty_index_ranges
+            .push(0..self.index_allocator.capacity());
+
+        Ok(())
+    }
+}
@@ -1,39 +1,239 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Arc,
+};
+
+use strum::IntoEnumIterator;
 
 use crate::world::{
     block::Block,
+    block_model::{BlockModel, ModelBox},
     block_position::BlockPosition,
+    block_registry::BlockRegistry,
     chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
     direction::{Direction},
+    light::{LightChannel, MAX_LIGHT_LEVEL},
 };
 
-use super::mesh::{Mesh, WindingDirection};
+use super::{
+    block_textures::BlockTextures,
+    mesh::{Mesh, QuadLighting, WindingDirection},
+};
+
+/// How a [ChunkMesher] turns the visible faces of a chunk into quads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MeshingMode {
+    /// One quad per visible block face.
+    Naive,
+    /// Adjacent faces of the same block that face the same way are merged into larger quads,
+    /// covering the same surface with far fewer vertices.
+    #[default]
+    Greedy,
+}
+
+/// The meshes of a chunk, split by how their blocks are drawn.
+#[derive(Default)]
+pub struct ChunkMeshes {
+    /// The opaque and cutout blocks.
+    pub opaque: Mesh,
+    /// The blocks that are blended with what is behind them.
+    pub translucent: Mesh,
+}
+
+impl ChunkMeshes {
+    fn mesh_for(&mut self, block_registry: &BlockRegistry, block: Block) -> &mut Mesh {
+        match block_registry.block_type_of(&block).is_translucent() {
+            true => &mut self.translucent,
+            false => &mut self.opaque,
+        }
+    }
+}
+
+/// A finished chunk mesh.
+pub struct ChunkMesh {
+    pub chunk_origin_position: BlockPosition,
+    /// The revision passed to [ChunkMesher::begin_meshing_chunk], meshes can finish out of order
+    /// so this is used to tell which mesh of a chunk is the newest.
+    pub revision: u64,
+    pub meshes: ChunkMeshes,
+}
+
+/// Corner light is averaged from up to four blocks, so it is kept in fractions of a light level.
+const CORNER_LIGHT_STEPS: u32 = 4;
+
+/// The block and sky light that falls on each corner of a face, in [CORNER_LIGHT_STEPS] of a light level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FaceLight {
+    block: [u8; 4],
+    sky: [u8; 4],
+}
+
+impl FaceLight {
+    /// Lights every corner of the face with the same light levels.
+    fn flat(block: u8, sky: u8) -> Self {
+        FaceLight {
+            block: [block * CORNER_LIGHT_STEPS as u8; 4],
+            sky: [sky * CORNER_LIGHT_STEPS as u8; 4],
+        }
+    }
+
+    fn block_brightness(&self) -> [f32; 4] {
+        self.block.map(FaceLight::brightness)
+    }
+
+    fn sky_brightness(&self) -> [f32; 4] {
+        self.sky.map(FaceLight::brightness)
+    }
+
+    /// The light as a brightness from 0 to 1.
+    fn brightness(light: u8) -> f32 {
+        light as f32 / (CORNER_LIGHT_STEPS as f32 * MAX_LIGHT_LEVEL as f32)
+    }
+}
+
+/// How far meshing a chunk looks past its edges, in blocks.
+const BORDER_THICKNESS: i32 = 1;
+const BORDER_BLOCK_WIDTH: usize = CHUNK_BLOCK_WIDTH + 2 * BORDER_THICKNESS as usize;
+const BORDER_BLOCK_HEIGHT: usize = CHUNK_BLOCK_HEIGHT + 2 * BORDER_THICKNESS as usize;
+const BORDER_BLOCK_DEPTH: usize = CHUNK_BLOCK_DEPTH + 2 * BORDER_THICKNESS as usize;
+
+/// A block of a [ChunkBorder].
+#[derive(Clone, Copy, Debug)]
+pub struct BorderBlock {
+    /// [None] for air.
+    pub block: Option<Block>,
+    pub block_light: u8,
+    pub sky_light: u8,
+}
+
+/// A copy of the blocks around a chunk that its mesh depends on, taken from its neighbor chunks.
+/// Meshing only looks [BORDER_THICKNESS] blocks past the chunk, so copying just those blocks
+/// lets the chunk be meshed in the background without copying every neighbor chunk.
+pub struct ChunkBorder {
+    chunk_origin_position: BlockPosition,
+    /// Every block in the box around the chunk, [None] for blocks in chunks that aren't loaded.
+    /// The blocks inside the chunk are left as [None] too, they are read from the chunk itself.
+    blocks: Vec<Option<BorderBlock>>,
+}
+
+impl ChunkBorder {
+    /// Copies the border around the chunk at the origin position.
+    /// `get_chunk` returns the loaded chunk with the given origin position, if there is one.
+    pub fn new<'a>(
+        chunk_origin_position: BlockPosition,
+        get_chunk: impl Fn(BlockPosition) -> Option<&'a Chunk>,
+    ) -> Self {
+        let mut chunk_border = ChunkBorder {
+            chunk_origin_position,
+            blocks: vec![None; BORDER_BLOCK_WIDTH * BORDER_BLOCK_HEIGHT * BORDER_BLOCK_DEPTH],
+        };
+
+        for x in 0..BORDER_BLOCK_WIDTH as i32 {
+            for y in 0..BORDER_BLOCK_HEIGHT as i32 {
+                for z in 0..BORDER_BLOCK_DEPTH as i32 {
+                    let position = chunk_origin_position.offset(
+                        x - BORDER_THICKNESS,
+                        y - BORDER_THICKNESS,
+                        z - BORDER_THICKNESS,
+                    );
+                    if position.to_chunk_origin() == chunk_origin_position {
+                        continue;
+                    }
+                    let Some(chunk) = get_chunk(position.to_chunk_origin()) else {
+                        continue;
+                    };
+
+                    let index = chunk_border
+                        .index(position)
+                        .expect("positions in the box around the chunk are in the border");
+                    chunk_border.blocks[index] = Some(BorderBlock {
+                        block: chunk.get_block_at_position(position).unwrap_or(None),
+                        block_light: chunk
+                            .get_light_at_position(position, LightChannel::Block)
+                            .unwrap_or(0),
+                        sky_light: chunk
+                            .get_light_at_position(position, LightChannel::Sky)
+                            .unwrap_or(0),
+                    });
+                }
+            }
+        }
+
+        chunk_border
+    }
+
+    /// Gets the block at the position, if it is in the border and its chunk is loaded.
+    pub fn get(&self, position: BlockPosition) -> Option<&BorderBlock> {
+        self.blocks[self.index(position)?].as_ref()
+    }
+
+    fn index(&self, position: BlockPosition) -> Option<usize> {
+        let x = position.x - self.chunk_origin_position.x + BORDER_THICKNESS;
+        let y = position.y - self.chunk_origin_position.y + BORDER_THICKNESS;
+        let z = position.z - self.chunk_origin_position.z + BORDER_THICKNESS;
+        if !(0..BORDER_BLOCK_WIDTH as i32).contains(&x)
+            || !(0..BORDER_BLOCK_HEIGHT as i32).contains(&y)
+            || !(0..BORDER_BLOCK_DEPTH as i32).contains(&z)
+        {
+            return None;
+        }
+
+        Some(
+            x as usize
+                + y as usize * BORDER_BLOCK_WIDTH
+                + z as usize * BORDER_BLOCK_WIDTH * BORDER_BLOCK_HEIGHT,
+        )
+    }
+}
 
 pub struct ChunkMesher {
-    ready_chunk_meshes_tx: Sender<(BlockPosition, Mesh)>,
-    ready_chunk_meshes_rx: Receiver<(BlockPosition, Mesh)>,
+    block_registry: Arc<BlockRegistry>,
+    block_textures: Arc<BlockTextures>,
+    meshing_mode: MeshingMode,
+    ready_chunk_meshes_tx: Sender<ChunkMesh>,
+    ready_chunk_meshes_rx: Receiver<ChunkMesh>,
 }
 
 impl ChunkMesher {
-    pub fn new() -> Self {
-        let (tx, rx) = channel::<(BlockPosition, Mesh)>();
+    pub fn new(
+        block_registry: Arc<BlockRegistry>,
+        block_textures: Arc<BlockTextures>,
+        meshing_mode: MeshingMode,
+    ) -> Self {
+        let (tx, rx) = channel::<ChunkMesh>();
         ChunkMesher {
+            block_registry,
+            block_textures,
+            meshing_mode,
             ready_chunk_meshes_tx: tx,
             ready_chunk_meshes_rx: rx,
         }
     }
 
-    pub fn begin_meshing_chunk(&self, chunk: Chunk, neighbor_chunks: Vec<Option<Chunk>>) {
+    /// Meshes the chunk in the background, the mesh is returned by [ChunkMesher::ready_chunk_meshes]
+    /// once it is done. Chunks without any visible faces get an empty mesh.
+    pub fn begin_meshing_chunk(&self, chunk: Chunk, chunk_border: ChunkBorder, revision: u64) {
         let tx = self.ready_chunk_meshes_tx.clone();
-        tokio_rayon::spawn(move || {
-            let chunk_mesh = ChunkMesher::mesh_chunk(&chunk, &neighbor_chunks);
-            if !chunk_mesh.is_empty() {
-                tx.send((chunk.origin_position(), chunk_mesh));
-            }
+        let block_registry = self.block_registry.clone();
+        let block_textures = self.block_textures.clone();
+        let meshing_mode = self.meshing_mode;
+        rayon::spawn(move || {
+            let meshes = ChunkMesher::mesh_chunk(
+                &chunk,
+                &chunk_border,
+                &block_registry,
+                &block_textures,
+                meshing_mode,
+            );
+            let _ = tx.send(ChunkMesh {
+                chunk_origin_position: chunk.origin_position(),
+                revision,
+                meshes,
+            });
         });
     }
 
-    pub fn ready_chunk_meshes(&self) -> Vec<(BlockPosition, Mesh)> {
+    pub fn ready_chunk_meshes(&self) -> Vec<ChunkMesh> {
         let mut ready_chunk_meshes = Vec::new();
 
         loop {
@@ -50,8 +250,30 @@ impl ChunkMesher {
         ready_chunk_meshes
     }
 
-    fn mesh_chunk(chunk: &Chunk, neighbor_chunks: &[Option<Chunk>]) -> Mesh {
-        let mut chunk_mesh = Mesh::default();
+    pub fn mesh_chunk(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_textures: &BlockTextures,
+        meshing_mode: MeshingMode,
+    ) -> ChunkMeshes {
+        match meshing_mode {
+            MeshingMode::Naive => {
+                ChunkMesher::mesh_chunk_naive(chunk, chunk_border, block_registry, block_textures)
+            }
+            MeshingMode::Greedy => {
+                ChunkMesher::mesh_chunk_greedy(chunk, chunk_border, block_registry, block_textures)
+            }
+        }
+    }
+
+    fn mesh_chunk_naive(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_textures: &BlockTextures,
+    ) -> ChunkMeshes {
+        let mut chunk_meshes = ChunkMeshes::default();
 
         let origin_position = chunk.origin_position();
 
@@ -59,11 +281,18 @@ impl ChunkMesher {
             for y in 0..CHUNK_BLOCK_HEIGHT {
                 for z in 0..CHUNK_BLOCK_DEPTH {
                     let block_position = origin_position.offset(x as i32, y as i32, z as i32);
-                    if let Some(_block) = chunk.get_block_at_position(block_position).ok() {
+                    let Ok(Some(block)) = chunk.get_block_at_position(block_position) else {
+                        continue;
+                    };
+
+                    if ChunkMesher::is_cube(block_registry, block) {
                         ChunkMesher::mesh_block(
                             chunk,
-                            neighbor_chunks,
-                            &mut chunk_mesh,
+                            chunk_border,
+                            block_registry,
+                            block_textures,
+                            chunk_meshes.mesh_for(block_registry, block),
+                            block,
                             block_position,
                         );
                     }
@@ -71,88 +300,634 @@ impl ChunkMesher {
             }
         }
 
-        chunk_mesh
+        ChunkMesher::mesh_chunk_models(
+            chunk,
+            chunk_border,
+            block_registry,
+            block_textures,
+            &mut chunk_meshes,
+        );
+
+        chunk_meshes
     }
 
     fn mesh_block(
         chunk: &Chunk,
-        neighbor_chunks: &[Option<Chunk>],
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_textures: &BlockTextures,
         mesh: &mut Mesh,
+        block: Block,
         block_position: BlockPosition,
     ) {
-        let neighbors = ChunkMesher::get_neighbors(chunk, neighbor_chunks, block_position);
+        let neighbors = ChunkMesher::get_neighbors(chunk, chunk_border, block_position);
         let block_position_vec3 = block_position.to_vec3();
 
         for (direction, neighbor) in neighbors.iter() {
-            match (direction, neighbor) {
-                (Direction::North, None) => {
-                    mesh.add_quad(
-                        [
-                            glam::vec3(1.0, 0.0, 1.0) + block_position_vec3,
-                            glam::vec3(0.0, 0.0, 1.0) + block_position_vec3,
-                            glam::vec3(0.0, 1.0, 1.0) + block_position_vec3,
-                            glam::vec3(1.0, 1.0, 1.0) + block_position_vec3,
-                        ],
-                        WindingDirection::Clockwise,
-                    );
-                }
-                (Direction::South, None) => {
-                    mesh.add_quad(
-                        [
-                            glam::vec3(0.0, 0.0, 0.0) + block_position_vec3,
-                            glam::vec3(1.0, 0.0, 0.0) + block_position_vec3,
-                            glam::vec3(1.0, 1.0, 0.0) + block_position_vec3,
-                            glam::vec3(0.0, 1.0, 0.0) + block_position_vec3,
-                        ],
-                        WindingDirection::Clockwise,
-                    );
-                }
-                (Direction::East, None) => {
-                    mesh.add_quad(
-                        [
-                            glam::vec3(0.0, 0.0, 1.0) + block_position_vec3,
-                            glam::vec3(0.0, 0.0, 0.0) + block_position_vec3,
-                            glam::vec3(0.0, 1.0, 0.0) + block_position_vec3,
-                            glam::vec3(0.0, 1.0, 1.0) + block_position_vec3,
-                        ],
-                        WindingDirection::Clockwise,
-                    );
+            if ChunkMesher::is_face_visible(block_registry, block, *neighbor, *direction) {
+                ChunkMesher::add_face(
+                    mesh,
+                    block_textures.texture_index(block, *direction),
+                    ChunkMesher::face_ambient_occlusion(
+                        chunk,
+                        chunk_border,
+                        block_registry,
+                        block_position,
+                        *direction,
+                    ),
+                    ChunkMesher::face_light(
+                        chunk,
+                        chunk_border,
+                        block_registry,
+                        block_position,
+                        *direction,
+                    ),
+                    *direction,
+                    block_position_vec3,
+                    glam::Vec3::ONE,
+                );
+            }
+        }
+    }
+
+    /// Meshes the chunk one layer at a time for each direction. The visible faces of a layer are
+    /// collected into a mask, and then merged into the largest rectangles of the same block
+    /// that fit, growing each rectangle along the layer's rows first and then its columns.
+    /// Faces are only merged when their ambient occlusion and light match too, so merging never changes the shading.
+    fn mesh_chunk_greedy(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_textures: &BlockTextures,
+    ) -> ChunkMeshes {
+        let mut chunk_meshes = ChunkMeshes::default();
+
+        let origin_position = chunk.origin_position();
+        let chunk_size = [CHUNK_BLOCK_WIDTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_DEPTH];
+
+        for direction in Direction::iter() {
+            let (normal_axis, u_axis, v_axis) = ChunkMesher::face_axes(direction);
+            let u_size = chunk_size[u_axis];
+            let v_size = chunk_size[v_axis];
+            let mut mask: Vec<Option<(Block, [u8; 4], FaceLight)>> = vec![None; u_size * v_size];
+
+            for layer in 0..chunk_size[normal_axis] {
+                for v in 0..v_size {
+                    for u in 0..u_size {
+                        let mut local_position = [0; 3];
+                        local_position[normal_axis] = layer as i32;
+                        local_position[u_axis] = u as i32;
+                        local_position[v_axis] = v as i32;
+                        let block_position = origin_position.offset(
+                            local_position[0],
+                            local_position[1],
+                            local_position[2],
+                        );
+
+                        mask[u + v * u_size] = match chunk.get_block_at_position(block_position) {
+                            Ok(Some(block)) if ChunkMesher::is_cube(block_registry, block) => {
+                                let neighbor = ChunkMesher::get_neighbor(
+                                    chunk,
+                                    chunk_border,
+                                    block_position,
+                                    direction,
+                                );
+                                let is_visible = ChunkMesher::is_face_visible(
+                                    block_registry,
+                                    block,
+                                    neighbor,
+                                    direction,
+                                );
+                                is_visible.then(|| {
+                                    let ambient_occlusion = ChunkMesher::face_ambient_occlusion(
+                                        chunk,
+                                        chunk_border,
+                                        block_registry,
+                                        block_position,
+                                        direction,
+                                    );
+                                    let light = ChunkMesher::face_light(
+                                        chunk,
+                                        chunk_border,
+                                        block_registry,
+                                        block_position,
+                                        direction,
+                                    );
+                                    (block, ambient_occlusion, light)
+                                })
+                            }
+                            _ => None,
+                        };
+                    }
                 }
-                (Direction::West, None) => {
-                    mesh.add_quad(
-                        [
-                            glam::vec3(1.0, 0.0, 0.0) + block_position_vec3,
-                            glam::vec3(1.0, 0.0, 1.0) + block_position_vec3,
-                            glam::vec3(1.0, 1.0, 1.0) + block_position_vec3,
-                            glam::vec3(1.0, 1.0, 0.0) + block_position_vec3,
-                        ],
-                        WindingDirection::Clockwise,
-                    );
+
+                for v in 0..v_size {
+                    let mut u = 0;
+                    while u < u_size {
+                        let Some(face) = mask[u + v * u_size] else {
+                            u += 1;
+                            continue;
+                        };
+
+                        let mut width = 1;
+                        while u + width < u_size && mask[u + width + v * u_size] == Some(face) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        while v + height < v_size
+                            && (0..width).all(|i| mask[u + i + (v + height) * u_size] == Some(face))
+                        {
+                            height += 1;
+                        }
+
+                        for merged_v in v..v + height {
+                            mask[u + merged_v * u_size..u + width + merged_v * u_size].fill(None);
+                        }
+
+                        let mut local_position = glam::Vec3::ZERO;
+                        local_position[normal_axis] = layer as f32;
+                        local_position[u_axis] = u as f32;
+                        local_position[v_axis] = v as f32;
+                        let mut size = glam::Vec3::ONE;
+                        size[u_axis] = width as f32;
+                        size[v_axis] = height as f32;
+
+                        let (block, ambient_occlusion, light) = face;
+                        ChunkMesher::add_face(
+                            chunk_meshes.mesh_for(block_registry, block),
+                            block_textures.texture_index(block, direction),
+                            ambient_occlusion,
+                            light,
+                            direction,
+                            origin_position.to_vec3() + local_position,
+                            size,
+                        );
+
+                        u += width;
+                    }
                 }
-                (Direction::Up, None) => {
-                    mesh.add_quad(
-                        [
-                            glam::vec3(0.0, 1.0, 0.0) + block_position_vec3,
-                            glam::vec3(1.0, 1.0, 0.0) + block_position_vec3,
-                            glam::vec3(1.0, 1.0, 1.0) + block_position_vec3,
-                            glam::vec3(0.0, 1.0, 1.0) + block_position_vec3,
-                        ],
-                        WindingDirection::Clockwise,
-                    );
+            }
+        }
+
+        ChunkMesher::mesh_chunk_models(
+            chunk,
+            chunk_border,
+            block_registry,
+            block_textures,
+            &mut chunk_meshes,
+        );
+
+        chunk_meshes
+    }
+
+    fn is_cube(block_registry: &BlockRegistry, block: Block) -> bool {
+        block_registry.block_type_of(&block).model == BlockModel::Cube
+    }
+
+    /// Meshes the blocks that aren't cubes one block at a time, they are never merged.
+    fn mesh_chunk_models(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_textures: &BlockTextures,
+        chunk_meshes: &mut ChunkMeshes,
+    ) {
+        let origin_position = chunk.origin_position();
+
+        for x in 0..CHUNK_BLOCK_WIDTH {
+            for y in 0..CHUNK_BLOCK_HEIGHT {
+                for z in 0..CHUNK_BLOCK_DEPTH {
+                    let block_position = origin_position.offset(x as i32, y as i32, z as i32);
+                    let Ok(Some(block)) = chunk.get_block_at_position(block_position) else {
+                        continue;
+                    };
+
+                    let mesh = chunk_meshes.mesh_for(block_registry, block);
+                    match block_registry.block_type_of(&block).model {
+                        BlockModel::Cube => {}
+                        BlockModel::Boxes(_) => ChunkMesher::mesh_model_boxes(
+                            chunk,
+                            chunk_border,
+                            block_registry,
+                            block_textures,
+                            mesh,
+                            block,
+                            block_position,
+                        ),
+                        BlockModel::Cross => ChunkMesher::mesh_cross(
+                            chunk,
+                            chunk_border,
+                            block_textures,
+                            mesh,
+                            block,
+                            block_position,
+                        ),
+                    }
                 }
-                (Direction::Down, None) => {
-                    mesh.add_quad(
-                        [
-                            glam::vec3(0.0, 0.0, 0.0) + block_position_vec3,
-                            glam::vec3(1.0, 0.0, 0.0) + block_position_vec3,
-                            glam::vec3(1.0, 0.0, 1.0) + block_position_vec3,
-                            glam::vec3(0.0, 0.0, 1.0) + block_position_vec3,
-                        ],
-                        WindingDirection::CounterClockwise,
-                    );
+            }
+        }
+    }
+
+    /// Adds the faces of each box in the block's model. Faces on the side of the block are culled
+    /// against the neighbouring block like a cube's, faces inside the block are always drawn.
+    fn mesh_model_boxes(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_textures: &BlockTextures,
+        mesh: &mut Mesh,
+        block: Block,
+        block_position: BlockPosition,
+    ) {
+        for model_box in block_registry.block_type_of(&block).model_boxes(&block) {
+            // Boxes that connect are only drawn when there is something to connect to,
+            // another block of the same type or a full side.
+            if let Some(connects) = model_box.connects {
+                let neighbor =
+                    ChunkMesher::get_neighbor(chunk, chunk_border, block_position, connects);
+                let is_connected = neighbor.is_some_and(|neighbor| {
+                    neighbor.id == block.id
+                        || block_registry
+                            .block_type_of(&neighbor)
+                            .has_full_side(&neighbor, connects.opposite())
+                });
+                if !is_connected {
+                    continue;
                 }
-                (_, Some(_)) => {}
             }
+
+            let min = model_box.min();
+            for direction in Direction::iter() {
+                // Faces on the side of the block are lit by the block in front of them,
+                // faces inside the block by the block itself.
+                let (ambient_occlusion, light) = match model_box.touches_side(direction) {
+                    true => {
+                        let neighbor = ChunkMesher::get_neighbor(
+                            chunk,
+                            chunk_border,
+                            block_position,
+                            direction,
+                        );
+                        if !ChunkMesher::is_box_face_visible(
+                            block_registry,
+                            block,
+                            neighbor,
+                            direction,
+                            &model_box,
+                        ) {
+                            continue;
+                        }
+
+                        (
+                            ChunkMesher::face_ambient_occlusion(
+                                chunk,
+                                chunk_border,
+                                block_registry,
+                                block_position,
+                                direction,
+                            ),
+                            ChunkMesher::face_light(
+                                chunk,
+                                chunk_border,
+                                block_registry,
+                                block_position,
+                                direction,
+                            ),
+                        )
+                    }
+                    false => (
+                        [3; 4],
+                        ChunkMesher::light_at(chunk, chunk_border, block_position),
+                    ),
+                };
+
+                ChunkMesher::add_face(
+                    mesh,
+                    block_textures.texture_index(block, direction),
+                    ambient_occlusion,
+                    light,
+                    direction,
+                    block_position.to_vec3() + min,
+                    model_box.max() - min,
+                );
+            }
+        }
+    }
+
+    /// Adds two planes crossing diagonally through the block, each drawn from both sides.
+    /// They are lit as if they face up, so both sides are shaded the same.
+    fn mesh_cross(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_textures: &BlockTextures,
+        mesh: &mut Mesh,
+        block: Block,
+        block_position: BlockPosition,
+    ) {
+        let position = block_position.to_vec3();
+        let texture_index = block_textures.texture_index(block, Direction::North);
+        let light = ChunkMesher::light_at(chunk, chunk_border, block_position);
+        let diagonals = [
+            (glam::vec3(0.0, 0.0, 0.0), glam::vec3(1.0, 0.0, 1.0)),
+            (glam::vec3(1.0, 0.0, 0.0), glam::vec3(0.0, 0.0, 1.0)),
+        ];
+
+        for (start, end) in diagonals {
+            let points = [start, end, end + glam::Vec3::Y, start + glam::Vec3::Y];
+            for winding_direction in [
+                WindingDirection::Clockwise,
+                WindingDirection::CounterClockwise,
+            ] {
+                mesh.add_quad(
+                    points.map(|point| point + position),
+                    [
+                        glam::vec2(0.0, 1.0),
+                        glam::vec2(1.0, 1.0),
+                        glam::vec2(1.0, 0.0),
+                        glam::vec2(0.0, 0.0),
+                    ],
+                    glam::Vec3::Y,
+                    texture_index,
+                    QuadLighting {
+                        ambient_occlusion: [1.0; 4],
+                        block_light: light.block_brightness(),
+                        sky_light: light.sky_brightness(),
+                    },
+                    winding_direction,
+                );
+            }
+        }
+    }
+
+    /// A face is hidden when the block next to it has an opaque face covering it, or when both blocks
+    /// are of the same transparent block type, so the inside of a lake or a glass wall has no faces.
+    fn is_face_visible(
+        block_registry: &BlockRegistry,
+        block: Block,
+        neighbor: Option<Block>,
+        direction: Direction,
+    ) -> bool {
+        ChunkMesher::is_box_face_visible(
+            block_registry,
+            block,
+            neighbor,
+            direction,
+            &ModelBox::FULL,
+        )
+    }
+
+    /// Whether the face of a box of the block's model, lying on the block's side in the direction,
+    /// can be seen past the neighbouring block. See [ChunkMesher::is_face_visible].
+    fn is_box_face_visible(
+        block_registry: &BlockRegistry,
+        block: Block,
+        neighbor: Option<Block>,
+        direction: Direction,
+        model_box: &ModelBox,
+    ) -> bool {
+        match neighbor {
+            Some(neighbor) => {
+                let neighbor_type = block_registry.block_type_of(&neighbor);
+                let is_same_transparent_type = neighbor.id == block.id && neighbor_type.transparent;
+                !neighbor_type.hides_face_of(&neighbor, direction.opposite(), model_box)
+                    && !is_same_transparent_type
+            }
+            None => true,
+        }
+    }
+
+    /// How much each corner of the block's face is occluded by the blocks in front of it,
+    /// from 0 (fully occluded) to 3 (not occluded), in the order of [ChunkMesher::face_corners].
+    ///
+    /// A corner is occluded by the two blocks beside it and the block diagonal to it,
+    /// when both blocks beside it are opaque the diagonal block can't be seen so it is fully occluded.
+    fn face_ambient_occlusion(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_position: BlockPosition,
+        direction: Direction,
+    ) -> [u8; 4] {
+        let (corners, _) = ChunkMesher::face_corners(direction);
+        let (_, u_axis, v_axis) = ChunkMesher::face_axes(direction);
+        let (x, y, z) = direction.offset();
+        let front_position = block_position.offset(x, y, z);
+
+        let is_opaque = |u_offset: i32, v_offset: i32| {
+            let mut offset = [0; 3];
+            offset[u_axis] = u_offset;
+            offset[v_axis] = v_offset;
+            ChunkMesher::get_block(
+                chunk,
+                chunk_border,
+                front_position.offset(offset[0], offset[1], offset[2]),
+            )
+            .is_some_and(|block| block_registry.block_type_of(&block).is_opaque(&block))
+        };
+
+        corners.map(|corner| {
+            let u_offset = if corner[u_axis] > 0.5 { 1 } else { -1 };
+            let v_offset = if corner[v_axis] > 0.5 { 1 } else { -1 };
+
+            let side_u = is_opaque(u_offset, 0);
+            let side_v = is_opaque(0, v_offset);
+            if side_u && side_v {
+                return 0;
+            }
+
+            3 - side_u as u8 - side_v as u8 - is_opaque(u_offset, v_offset) as u8
+        })
+    }
+
+    /// The axis a face points along, followed by the two axes that lie in the face.
+    fn face_axes(direction: Direction) -> (usize, usize, usize) {
+        match direction {
+            Direction::North | Direction::South => (2, 0, 1),
+            Direction::East | Direction::West => (0, 2, 1),
+            Direction::Up | Direction::Down => (1, 0, 2),
+        }
+    }
+
+    /// Adds the face of a box with its lowest corner at `position`, stretched by `size`.
+    /// The texture repeats once per block across the face, lined up with the block grid
+    /// so the faces of boxes smaller than a block show the matching part of the texture.
+    fn add_face(
+        mesh: &mut Mesh,
+        texture_index: u32,
+        ambient_occlusion: [u8; 4],
+        light: FaceLight,
+        direction: Direction,
+        position: glam::Vec3,
+        size: glam::Vec3,
+    ) {
+        let (corners, winding_direction) = ChunkMesher::face_corners(direction);
+        let (_, u_axis, v_axis) = ChunkMesher::face_axes(direction);
+        let (x, y, z) = direction.offset();
+        let position_in_block = position - position.floor();
+
+        mesh.add_quad(
+            corners.map(|corner| corner * size + position),
+            // The v coordinate is flipped so textures are upright on the side faces.
+            corners.map(|corner| {
+                let corner_in_block = corner * size + position_in_block;
+                glam::vec2(corner_in_block[u_axis], 1.0 - corner_in_block[v_axis])
+            }),
+            glam::vec3(x as f32, y as f32, z as f32),
+            texture_index,
+            QuadLighting {
+                ambient_occlusion: ambient_occlusion
+                    .map(|ambient_occlusion| ambient_occlusion as f32 / 3.0),
+                block_light: light.block_brightness(),
+                sky_light: light.sky_brightness(),
+            },
+            winding_direction,
+        );
+    }
+
+    /// The light that falls on each corner of the face, the average light of the blocks in front
+    /// of the face that touch the corner, so light fades smoothly across faces.
+    /// Opaque blocks are left out, as is the diagonal block when both blocks beside it are opaque,
+    /// since no light gets around them to the corner. Blocks in chunks that aren't loaded are
+    /// left out too, unless the corner has nothing else to go on.
+    fn face_light(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        block_registry: &BlockRegistry,
+        block_position: BlockPosition,
+        direction: Direction,
+    ) -> FaceLight {
+        let (corners, _) = ChunkMesher::face_corners(direction);
+        let (_, u_axis, v_axis) = ChunkMesher::face_axes(direction);
+        let (x, y, z) = direction.offset();
+        let front_position = block_position.offset(x, y, z);
+
+        let position_at = |u_offset: i32, v_offset: i32| {
+            let mut offset = [0; 3];
+            offset[u_axis] = u_offset;
+            offset[v_axis] = v_offset;
+            front_position.offset(offset[0], offset[1], offset[2])
+        };
+        let is_open = |position: BlockPosition| {
+            !ChunkMesher::get_block(chunk, chunk_border, position)
+                .is_some_and(|block| block_registry.block_type_of(&block).is_opaque(&block))
+        };
+
+        let corner_lights = corners.map(|corner| {
+            let u_offset = if corner[u_axis] > 0.5 { 1 } else { -1 };
+            let v_offset = if corner[v_axis] > 0.5 { 1 } else { -1 };
+
+            let mut positions = vec![front_position];
+            let side_u = position_at(u_offset, 0);
+            let side_v = position_at(0, v_offset);
+            let diagonal = position_at(u_offset, v_offset);
+            let (is_side_u_open, is_side_v_open) = (is_open(side_u), is_open(side_v));
+            if is_side_u_open {
+                positions.push(side_u);
+            }
+            if is_side_v_open {
+                positions.push(side_v);
+            }
+            if (is_side_u_open || is_side_v_open) && is_open(diagonal) {
+                positions.push(diagonal);
+            }
+            let loaded_positions: Vec<BlockPosition> = positions
+                .iter()
+                .copied()
+                .filter(|position| ChunkMesher::is_loaded(chunk, chunk_border, *position))
+                .collect();
+            if !loaded_positions.is_empty() {
+                positions = loaded_positions;
+            }
+
+            let average_light = |channel: LightChannel| {
+                let total: u32 = positions
+                    .iter()
+                    .map(|position| {
+                        ChunkMesher::get_light(chunk, chunk_border, *position, channel) as u32
+                    })
+                    .sum();
+                let count = positions.len() as u32;
+                ((total * CORNER_LIGHT_STEPS + count / 2) / count) as u8
+            };
+
+            (
+                average_light(LightChannel::Block),
+                average_light(LightChannel::Sky),
+            )
+        });
+
+        FaceLight {
+            block: corner_lights.map(|(block, _)| block),
+            sky: corner_lights.map(|(_, sky)| sky),
+        }
+    }
+
+    /// The light of the block at the position on every corner, for faces lit by a single block.
+    fn light_at(chunk: &Chunk, chunk_border: &ChunkBorder, position: BlockPosition) -> FaceLight {
+        FaceLight::flat(
+            ChunkMesher::get_light(chunk, chunk_border, position, LightChannel::Block),
+            ChunkMesher::get_light(chunk, chunk_border, position, LightChannel::Sky),
+        )
+    }
+
+    /// The corners of a unit cube's face, in the order they are added to the mesh.
+    fn face_corners(direction: Direction) -> ([glam::Vec3; 4], WindingDirection) {
+        match direction {
+            Direction::North => (
+                [
+                    glam::vec3(1.0, 0.0, 1.0),
+                    glam::vec3(0.0, 0.0, 1.0),
+                    glam::vec3(0.0, 1.0, 1.0),
+                    glam::vec3(1.0, 1.0, 1.0),
+                ],
+                WindingDirection::Clockwise,
+            ),
+            Direction::South => (
+                [
+                    glam::vec3(0.0, 0.0, 0.0),
+                    glam::vec3(1.0, 0.0, 0.0),
+                    glam::vec3(1.0, 1.0, 0.0),
+                    glam::vec3(0.0, 1.0, 0.0),
+                ],
+                WindingDirection::Clockwise,
+            ),
+            Direction::East => (
+                [
+                    glam::vec3(0.0, 0.0, 1.0),
+                    glam::vec3(0.0, 0.0, 0.0),
+                    glam::vec3(0.0, 1.0, 0.0),
+                    glam::vec3(0.0, 1.0, 1.0),
+                ],
+                WindingDirection::Clockwise,
+            ),
+            Direction::West => (
+                [
+                    glam::vec3(1.0, 0.0, 0.0),
+                    glam::vec3(1.0, 0.0, 1.0),
+                    glam::vec3(1.0, 1.0, 1.0),
+                    glam::vec3(1.0, 1.0, 0.0),
+                ],
+                WindingDirection::Clockwise,
+            ),
+            Direction::Up => (
+                [
+                    glam::vec3(0.0, 1.0, 0.0),
+                    glam::vec3(1.0, 1.0, 0.0),
+                    glam::vec3(1.0, 1.0, 1.0),
+                    glam::vec3(0.0, 1.0, 1.0),
+                ],
+                WindingDirection::Clockwise,
+            ),
+            Direction::Down => (
+                [
+                    glam::vec3(0.0, 0.0, 0.0),
+                    glam::vec3(1.0, 0.0, 0.0),
+                    glam::vec3(1.0, 0.0, 1.0),
+                    glam::vec3(0.0, 0.0, 1.0),
+                ],
+                WindingDirection::CounterClockwise,
+            ),
         }
     }
 
@@ -161,49 +936,531 @@ impl ChunkMesher {
     /// This will always be in [Direction] order.
     pub fn get_neighbors(
         chunk: &Chunk,
-        neighbor_chunks: &[Option<Chunk>],
+        chunk_border: &ChunkBorder,
         position: BlockPosition,
     ) -> Vec<(Direction, Option<Block>)> {
-        let offsets: Vec<(Direction, (i32, i32, i32))> = vec![
-            (Direction::North, (0, 0, 1)),
-            (Direction::South, (0, 0, -1)),
-            (Direction::East, (-1, 0, 0)),
-            (Direction::West, (1, 0, 0)),
-            (Direction::Up, (0, 1, 0)),
-            (Direction::Down, (0, -1, 0)),
+        Direction::iter()
+            .map(|direction| {
+                (
+                    direction,
+                    ChunkMesher::get_neighbor(chunk, chunk_border, position, direction),
+                )
+            })
+            .collect()
+    }
+
+    /// Gets the block next to the position in the direction, looking in the neighbor chunks
+    /// if it is outside of the chunk. Air, and blocks in chunks that aren't loaded, are [None].
+    pub fn get_neighbor(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        position: BlockPosition,
+        direction: Direction,
+    ) -> Option<Block> {
+        let (x, y, z) = direction.offset();
+        ChunkMesher::get_block(chunk, chunk_border, position.offset(x, y, z))
+    }
+
+    /// Gets the block at the position from the chunk, or the border around it.
+    /// Air, and blocks in chunks that aren't loaded, are [None].
+    pub fn get_block(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        position: BlockPosition,
+    ) -> Option<Block> {
+        if let Ok(block) = chunk.get_block_at_position(position) {
+            return block;
+        }
+
+        chunk_border
+            .get(position)
+            .and_then(|border_block| border_block.block)
+    }
+
+    /// Whether the chunk or the loaded part of the border around it holds the position.
+    fn is_loaded(chunk: &Chunk, chunk_border: &ChunkBorder, position: BlockPosition) -> bool {
+        chunk.is_world_position_within(position) || chunk_border.get(position).is_some()
+    }
+
+    /// Gets the light of the channel at the position from the chunk, or the border around it.
+    /// Blocks in chunks that aren't loaded have no block light, but are lit as if they were open
+    /// to the sky, so the tops of the highest loaded chunks aren't left in the dark.
+    pub fn get_light(
+        chunk: &Chunk,
+        chunk_border: &ChunkBorder,
+        position: BlockPosition,
+        channel: LightChannel,
+    ) -> u8 {
+        if let Ok(light) = chunk.get_light_at_position(position, channel) {
+            return light;
+        }
+
+        match (chunk_border.get(position), channel) {
+            (Some(border_block), LightChannel::Block) => border_block.block_light,
+            (Some(border_block), LightChannel::Sky) => border_block.sky_light,
+            (None, LightChannel::Block) => 0,
+            (None, LightChannel::Sky) => MAX_LIGHT_LEVEL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        block::{HorizontalFacingProperty, StateFlags},
+        block_registry::test_registry,
+        seeded_random::SeededRandom,
+    };
+
+    const TEST_BLOCKS: &str = r#"
+        (identifier: "test:dirt", display_name: "Dirt"),
+        (identifier: "test:glass", display_name: "Glass", transparent: true),
+        (
+            identifier: "test:slab",
+            display_name: "Slab",
+            state_properties: [Half],
+            model: Boxes([(from: (0, 0, 0), to: (16, 8, 16))]),
+        ),
+        (
+            identifier: "test:stairs",
+            display_name: "Stairs",
+            state_properties: [HorizontalFacing],
+            model: Boxes([
+                (from: (0, 0, 0), to: (16, 8, 16)),
+                (from: (0, 8, 8), to: (16, 16, 16)),
+            ]),
+        ),
+        (
+            identifier: "test:fence",
+            display_name: "Fence",
+            model: Boxes([
+                (from: (6, 0, 6), to: (10, 16, 10)),
+                (from: (7, 6, 10), to: (9, 15, 16), connects: North),
+            ]),
+        ),
+        (identifier: "test:plant", display_name: "Plant", solid: false, model: Cross),
+        (identifier: "test:tinted_glass", display_name: "Tinted Glass", transparent: true),
+        (identifier: "test:water", display_name: "Water", solid: false, transparent: true),
+        (identifier: "test:leaves", display_name: "Leaves", transparent: true, cutout: true),"#;
+
+    fn registry() -> BlockRegistry {
+        test_registry(TEST_BLOCKS)
+    }
+
+    /// The total area covered by the mesh's quads.
+    fn surface_area(mesh: &Mesh) -> f32 {
+        mesh.vertices()
+            .chunks_exact(4)
+            .map(|quad| {
+                let corners: Vec<glam::Vec3> = quad
+                    .iter()
+                    .map(|vertex| glam::Vec3::from(vertex.position))
+                    .collect();
+                (corners[1] - corners[0])
+                    .cross(corners[3] - corners[0])
+                    .length()
+            })
+            .sum()
+    }
+
+    fn quad_count(mesh: &Mesh) -> usize {
+        mesh.vertices().len() / 4
+    }
+
+    fn mesh_both_layers(
+        chunk: &Chunk,
+        neighbor_chunks: &[Option<Chunk>],
+    ) -> (ChunkMeshes, ChunkMeshes) {
+        let registry = registry();
+        let block_textures = BlockTextures::new(&registry);
+        let chunk_border = ChunkBorder::new(chunk.origin_position(), |chunk_origin_position| {
+            neighbor_chunks
+                .iter()
+                .flatten()
+                .find(|neighbor_chunk| neighbor_chunk.origin_position() == chunk_origin_position)
+        });
+        let mesh = |meshing_mode| {
+            ChunkMesher::mesh_chunk(
+                chunk,
+                &chunk_border,
+                &registry,
+                &block_textures,
+                meshing_mode,
+            )
+        };
+        (mesh(MeshingMode::Naive), mesh(MeshingMode::Greedy))
+    }
+
+    /// The opaque meshes from both meshing modes.
+    fn mesh_both(chunk: &Chunk, neighbor_chunks: &[Option<Chunk>]) -> (Mesh, Mesh) {
+        let (naive, greedy) = mesh_both_layers(chunk, neighbor_chunks);
+        (naive.opaque, greedy.opaque)
+    }
+
+    fn assert_same_surface_area(naive: &Mesh, greedy: &Mesh) {
+        assert!((surface_area(naive) - surface_area(greedy)).abs() < 0.001);
+        assert!(quad_count(greedy) <= quad_count(naive));
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        let stone = registry().block("test:stone").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(3, 4, 5), stone);
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+
+        assert_eq!(quad_count(&naive), 6);
+        assert_eq!(quad_count(&greedy), 6);
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    #[test]
+    fn flat_floor_merges_into_one_quad_per_side() {
+        let stone = registry().block("test:stone").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(-16, 0, 16));
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                chunk.set_block_at_position(BlockPosition::new(-16 + x, 0, 16 + z), stone);
+            }
+        }
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+
+        assert_eq!(quad_count(&naive), 16 * 16 * 2 + 16 * 4);
+        assert_eq!(quad_count(&greedy), 6);
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    #[test]
+    fn different_blocks_are_not_merged() {
+        let registry = registry();
+        let stone = registry.block("test:stone").unwrap();
+        let dirt = registry.block("test:dirt").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            let block = if x % 2 == 0 { stone } else { dirt };
+            chunk.set_block_at_position(BlockPosition::new(x, 0, 0), block);
+        }
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+
+        // Neighbouring blocks always differ, so there is nothing to merge.
+        assert_eq!(quad_count(&greedy), quad_count(&naive));
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    #[test]
+    fn random_chunks_cover_the_same_surface() {
+        let registry = registry();
+        let blocks = [
+            registry.block("test:stone").unwrap(),
+            registry.block("test:dirt").unwrap(),
+            registry.block("test:glass").unwrap(),
         ];
 
-        let mut neighbors = vec![];
-        'outer: for (direction, offset) in offsets {
-            match chunk.get_block_at_position(position.offset(offset.0, offset.1, offset.2)) {
-                Ok(block) => {
-                    neighbors.push((direction, block));
-                    continue;
+        for seed in 0..8 {
+            let mut random = SeededRandom::new(seed);
+            let mut chunk = Chunk::empty(BlockPosition::new(0, -16, 0));
+            for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+                for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                    for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                        // Mostly stone towards the bottom so there are large faces to merge.
+                        if random.next_f64() < 0.7 - y as f64 * 0.04 {
+                            let block = blocks[random.range_i32(0, 3) as usize];
+                            chunk.set_block_at_position(BlockPosition::new(x, y - 16, z), block);
+                        }
+                    }
                 }
-                Err(_) => {}
             }
 
-            for neighbor_chunk in neighbor_chunks {
-                if let None = neighbor_chunk {
-                    continue;
-                }
+            let (naive, greedy) = mesh_both_layers(&chunk, &[]);
 
-                match neighbor_chunk
-                    .as_ref()
-                    .unwrap()
-                    .get_block_at_position(position.offset(offset.0, offset.1, offset.2))
-                {
-                    Ok(block) => {
-                        neighbors.push((direction, block));
-                        continue 'outer;
-                    }
-                    Err(_) => continue,
+            assert_same_surface_area(&naive.opaque, &greedy.opaque);
+            assert_same_surface_area(&naive.translucent, &greedy.translucent);
+        }
+    }
+
+    #[test]
+    fn faces_against_opaque_neighbor_chunks_are_hidden() {
+        let stone = registry().block("test:stone").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 16, 0));
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                chunk.set_block_at_position(BlockPosition::new(x, 16, z), stone);
+            }
+        }
+        let below = Chunk::filled(BlockPosition::new(0, 0, 0), stone);
+
+        let (naive, greedy) = mesh_both(&chunk, &[Some(below)]);
+
+        assert_eq!(quad_count(&greedy), 5);
+        assert!((surface_area(&naive) - (16.0 * 16.0 + 16.0 * 4.0)).abs() < 0.001);
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    /// The ambient occlusion of the upward facing vertices at the position, from both meshing modes.
+    fn top_ambient_occlusion(meshes: &[&Mesh], position: glam::Vec3) -> Vec<f32> {
+        meshes
+            .iter()
+            .flat_map(|mesh| mesh.vertices())
+            .filter(|vertex| {
+                vertex.normal == [0.0, 1.0, 0.0] && glam::Vec3::from(vertex.position) == position
+            })
+            .map(|vertex| vertex.ambient_occlusion)
+            .collect()
+    }
+
+    #[test]
+    fn corners_next_to_blocks_are_occluded() {
+        let stone = registry().block("test:stone").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        for x in 0..4 {
+            for z in 0..4 {
+                chunk.set_block_at_position(BlockPosition::new(x, 0, z), stone);
+            }
+        }
+        chunk.set_block_at_position(BlockPosition::new(1, 1, 1), stone);
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+        let meshes = [&naive, &greedy];
+
+        for corner in [(1.0, 1.0), (2.0, 1.0), (1.0, 2.0), (2.0, 2.0)] {
+            let occlusion = top_ambient_occlusion(&meshes, glam::vec3(corner.0, 1.0, corner.1));
+            assert!(!occlusion.is_empty());
+            assert!(occlusion.iter().all(|occlusion| *occlusion < 1.0));
+        }
+        for corner in [(0.0, 0.0), (4.0, 4.0)] {
+            let occlusion = top_ambient_occlusion(&meshes, glam::vec3(corner.0, 1.0, corner.1));
+            assert!(!occlusion.is_empty());
+            assert!(occlusion.iter().all(|occlusion| *occlusion == 1.0));
+        }
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    #[test]
+    fn blocks_in_neighbor_chunks_occlude_corners() {
+        let stone = registry().block("test:stone").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(15, 0, 15), stone);
+        // Only touches the block's top face diagonally, across both the x and z borders.
+        let mut diagonal_chunk = Chunk::empty(BlockPosition::new(16, 0, 16));
+        diagonal_chunk.set_block_at_position(BlockPosition::new(16, 1, 16), stone);
+
+        let (naive, greedy) = mesh_both(&chunk, &[Some(diagonal_chunk)]);
+        let meshes = [&naive, &greedy];
+
+        let occlusion = top_ambient_occlusion(&meshes, glam::vec3(16.0, 1.0, 16.0));
+        assert_eq!(occlusion, vec![2.0 / 3.0; 2]);
+        let occlusion = top_ambient_occlusion(&meshes, glam::vec3(15.0, 1.0, 15.0));
+        assert_eq!(occlusion, vec![1.0; 2]);
+    }
+
+    #[test]
+    fn corner_light_is_averaged_from_the_open_blocks_around_it() {
+        let stone = registry().block("test:stone").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(1, 0, 1), stone);
+        chunk.set_block_at_position(BlockPosition::new(0, 1, 1), stone);
+        chunk.set_light_at_position(BlockPosition::new(1, 1, 1), LightChannel::Block, 12);
+        chunk.set_light_at_position(BlockPosition::new(2, 1, 1), LightChannel::Block, 8);
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+        let top_block_light = |position: glam::Vec3| -> Vec<f32> {
+            [&naive, &greedy]
+                .iter()
+                .flat_map(|mesh| mesh.vertices())
+                .filter(|vertex| {
+                    vertex.normal == [0.0, 1.0, 0.0]
+                        && glam::Vec3::from(vertex.position) == position
+                })
+                .map(|vertex| vertex.block_light)
+                .collect()
+        };
+
+        // The stone beside the block is left out of the average of the corners it touches.
+        for (corner, light) in [
+            (glam::vec3(1.0, 1.0, 1.0), 4.0 / 15.0),
+            (glam::vec3(1.0, 1.0, 2.0), 4.0 / 15.0),
+            (glam::vec3(2.0, 1.0, 1.0), 5.0 / 15.0),
+            (glam::vec3(2.0, 1.0, 2.0), 5.0 / 15.0),
+        ] {
+            assert_eq!(top_block_light(corner), vec![light; 2]);
+        }
+    }
+
+    #[test]
+    fn transparent_blocks_are_meshed_separately() {
+        let registry = registry();
+        let stone = registry.block("test:stone").unwrap();
+        let glass = registry.block("test:glass").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    chunk.set_block_at_position(BlockPosition::new(x, y, z), glass);
                 }
             }
+        }
+        chunk.set_block_at_position(BlockPosition::new(2, 0, 0), stone);
+
+        let (naive, greedy) = mesh_both_layers(&chunk, &[]);
 
-            neighbors.push((direction, None))
+        // The faces between the glass blocks, and the one against the stone, are hidden.
+        assert_eq!(quad_count(&naive.translucent), 6 * 4 - 1);
+        assert!((surface_area(&naive.translucent) - 23.0).abs() < 0.001);
+        // The stone's face against the glass can be seen through it.
+        assert_eq!(quad_count(&naive.opaque), 6);
+        assert_same_surface_area(&naive.opaque, &greedy.opaque);
+        assert_same_surface_area(&naive.translucent, &greedy.translucent);
+    }
+
+    #[test]
+    fn slabs_only_hide_the_faces_they_cover() {
+        let registry = registry();
+        let stone = registry.block("test:stone").unwrap();
+        let slab = registry.block("test:slab").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(4, 4, 4), slab);
+        chunk.set_block_at_position(BlockPosition::new(4, 5, 4), stone);
+        chunk.set_block_at_position(BlockPosition::new(4, 3, 4), stone);
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+
+        // The stone above the slab keeps its bottom face, the stone below loses its top face,
+        // and the slab's top face is drawn inside the block so nothing hides it.
+        assert_eq!(quad_count(&naive), 6 + 5 + 5);
+        assert!((surface_area(&naive) - (6.0 + 5.0 + 1.0 + 0.5 * 4.0)).abs() < 0.001);
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    #[test]
+    fn stairs_turn_with_their_facing() {
+        let registry = registry();
+        let stairs = registry.block("test:stairs").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        for (x, facing) in [(0, Direction::North), (2, Direction::East)] {
+            let block = registry
+                .block_type_of(&stairs)
+                .with_state::<HorizontalFacingProperty>(stairs, facing)
+                .unwrap();
+            chunk.set_block_at_position(BlockPosition::new(x, 0, 0), block);
         }
 
-        neighbors
+        let (naive, _) = mesh_both(&chunk, &[]);
+
+        let top_faces: Vec<glam::Vec3> = naive
+            .vertices()
+            .iter()
+            .filter(|vertex| vertex.normal == [0.0, 1.0, 0.0] && vertex.position[1] == 1.0)
+            .map(|vertex| glam::Vec3::from(vertex.position))
+            .collect();
+        // The north facing stairs are tall on their north half, the east facing stairs on their east half.
+        assert!(top_faces.contains(&glam::vec3(0.0, 1.0, 0.5)));
+        assert!(top_faces.contains(&glam::vec3(0.0, 1.0, 1.0)));
+        assert!(top_faces.contains(&glam::vec3(2.5, 1.0, 0.0)));
+        assert!(top_faces.contains(&glam::vec3(2.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn fences_connect_to_full_sides() {
+        let registry = registry();
+        let stone = registry.block("test:stone").unwrap();
+        let fence = registry.block("test:fence").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(0, 0, 0), fence);
+        chunk.set_block_at_position(BlockPosition::new(4, 0, 0), fence);
+        chunk.set_block_at_position(BlockPosition::new(4, 0, 1), stone);
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+
+        // The lone post has 6 faces, the connected post 6 more and an arm with its end hidden by the stone.
+        assert_eq!(quad_count(&naive), 6 + 6 + 5 + 6);
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    #[test]
+    fn crosses_are_two_double_sided_planes() {
+        let registry = registry();
+        let stone = registry.block("test:stone").unwrap();
+        let plant = registry.block("test:plant").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(1, 1, 1), plant);
+        chunk.set_block_at_position(BlockPosition::new(1, 0, 1), stone);
+
+        let (naive, greedy) = mesh_both(&chunk, &[]);
+
+        // The plant doesn't hide the top of the stone it stands on.
+        assert_eq!(quad_count(&naive), 4 + 6);
+        assert_same_surface_area(&naive, &greedy);
+    }
+
+    /// Meshes `block` at the east edge of one chunk and `neighbor` at the west edge of the chunk
+    /// next to it, and counts the faces each of them has on the border between the chunks.
+    fn border_face_counts(block: Block, neighbor: Block) -> (usize, usize) {
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(15, 0, 0), block);
+        let mut neighbor_chunk = Chunk::empty(BlockPosition::new(16, 0, 0));
+        neighbor_chunk.set_block_at_position(BlockPosition::new(16, 0, 0), neighbor);
+
+        let border_faces = |meshes: &ChunkMeshes, normal: [f32; 3]| {
+            [&meshes.opaque, &meshes.translucent]
+                .iter()
+                .flat_map(|mesh| mesh.vertices().chunks_exact(4))
+                .filter(|quad| {
+                    quad.iter()
+                        .all(|vertex| vertex.normal == normal && vertex.position[0] == 16.0)
+                })
+                .count()
+        };
+
+        let (naive, greedy) = mesh_both_layers(&chunk, &[Some(neighbor_chunk.clone())]);
+        let block_faces = border_faces(&naive, [1.0, 0.0, 0.0]);
+        assert_eq!(block_faces, border_faces(&greedy, [1.0, 0.0, 0.0]));
+
+        let (naive, greedy) = mesh_both_layers(&neighbor_chunk, &[Some(chunk)]);
+        let neighbor_faces = border_faces(&naive, [-1.0, 0.0, 0.0]);
+        assert_eq!(neighbor_faces, border_faces(&greedy, [-1.0, 0.0, 0.0]));
+
+        (block_faces, neighbor_faces)
+    }
+
+    #[test]
+    fn faces_are_culled_by_opacity_across_chunk_borders() {
+        let registry = registry();
+        let block = |identifier| registry.block(identifier).unwrap();
+        let stone = block("test:stone");
+        let non_solid_stone = Block {
+            state: StateFlags::Solid.set(stone.state, 0),
+            ..stone
+        };
+
+        let cases = [
+            // Solid blocks hide each other.
+            (stone, block("test:dirt"), (0, 0)),
+            (stone, stone, (0, 0)),
+            // Transparent blocks only hide the faces of transparent blocks of the same type.
+            (stone, block("test:glass"), (1, 0)),
+            (block("test:glass"), block("test:glass"), (0, 0)),
+            (block("test:glass"), block("test:tinted_glass"), (1, 1)),
+            (block("test:leaves"), block("test:leaves"), (0, 0)),
+            (block("test:leaves"), block("test:glass"), (1, 1)),
+            // Non-solid blocks never hide anything, whether that comes from their type or their state.
+            (stone, block("test:water"), (1, 0)),
+            (block("test:water"), block("test:water"), (0, 0)),
+            (block("test:glass"), block("test:water"), (1, 1)),
+            (stone, non_solid_stone, (1, 0)),
+        ];
+
+        for (block, neighbor, expected) in cases {
+            assert_eq!(
+                border_face_counts(block, neighbor),
+                expected,
+                "{:?} next to {:?}",
+                registry.block_type_of(&block).identifier,
+                registry.block_type_of(&neighbor).identifier,
+            );
+        }
     }
 }
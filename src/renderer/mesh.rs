@@ -4,31 +4,86 @@ pub enum WindingDirection {
     CounterClockwise,
 }
 
+/// How lit each point of a quad is, each from 0 to 1.
+#[derive(Clone, Copy)]
+pub struct QuadLighting {
+    /// How much light reaches the point past the blocks around it, 0 being fully occluded.
+    pub ambient_occlusion: [f32; 4],
+    /// How brightly emissive blocks light the point.
+    pub block_light: [f32; 4],
+    /// How brightly the sky lights the point.
+    pub sky_light: [f32; 4],
+}
+
 #[derive(Default)]
 pub struct Mesh {
     vertices: Vec<MeshVertex>,
-    normals: Vec<[f32; 3]>,
     indicies: Vec<u32>,
 }
 
 impl Mesh {
-    pub fn add_quad(&mut self, points: [glam::Vec3; 4], winding_direction: WindingDirection) {
+    /// Adds a quad where every vertex shares the face's normal and texture.
+    /// `uvs` are the texture coordinates of each of the points, and `lighting` how lit each of them is.
+    ///
+    /// The quad is split into triangles along the diagonal that keeps the occlusion symmetric,
+    /// otherwise one darkened corner bleeds across half of the quad depending on which way it was split.
+    pub fn add_quad(
+        &mut self,
+        points: [glam::Vec3; 4],
+        uvs: [glam::Vec2; 4],
+        normal: glam::Vec3,
+        texture_index: u32,
+        lighting: QuadLighting,
+        winding_direction: WindingDirection,
+    ) {
+        let QuadLighting {
+            ambient_occlusion,
+            block_light,
+            sky_light,
+        } = lighting;
+
         let vertex_start_pos = self.vertices.len() as u32;
         let vertices: Vec<MeshVertex> = points
             .iter()
-            .map(|point| MeshVertex {
-                position: point.to_array(),
-            })
+            .zip(uvs.iter())
+            .zip(ambient_occlusion.iter())
+            .zip(block_light.iter())
+            .zip(sky_light.iter())
+            .map(
+                |((((point, uv), ambient_occlusion), block_light), sky_light)| MeshVertex {
+                    position: point.to_array(),
+                    normal: normal.to_array(),
+                    uv: uv.to_array(),
+                    texture_index,
+                    ambient_occlusion: *ambient_occlusion,
+                    block_light: *block_light,
+                    sky_light: *sky_light,
+                },
+            )
             .collect();
         self.vertices.append(vertices.to_owned().as_mut());
 
-        let normal = (points[1] - points[0])
-            .cross(points[2] - points[0])
-            .to_array();
-        self.normals.push(normal);
+        let flip_diagonal = ambient_occlusion[0] + ambient_occlusion[2]
+            < ambient_occlusion[1] + ambient_occlusion[3];
 
-        let indicies = match winding_direction {
-            WindingDirection::Clockwise => vec![
+        let indicies = match (winding_direction, flip_diagonal) {
+            (WindingDirection::Clockwise, true) => vec![
+                vertex_start_pos + 3,
+                vertex_start_pos + 2,
+                vertex_start_pos + 1,
+                vertex_start_pos + 1,
+                vertex_start_pos + 0,
+                vertex_start_pos + 3,
+            ],
+            (WindingDirection::CounterClockwise, true) => vec![
+                vertex_start_pos + 1,
+                vertex_start_pos + 2,
+                vertex_start_pos + 3,
+                vertex_start_pos + 1,
+                vertex_start_pos + 3,
+                vertex_start_pos + 0,
+            ],
+            (WindingDirection::Clockwise, false) => vec![
                 vertex_start_pos + 2,
                 vertex_start_pos + 1,
                 vertex_start_pos + 0,
@@ -36,7 +91,7 @@ impl Mesh {
                 vertex_start_pos + 3,
                 vertex_start_pos + 2,
             ],
-            WindingDirection::CounterClockwise => vec![
+            (WindingDirection::CounterClockwise, false) => vec![
                 vertex_start_pos + 0,
                 vertex_start_pos + 1,
                 vertex_start_pos + 2,
@@ -2,5 +2,8 @@ pub mod mesh;
 pub mod renderer;
 pub mod vulkan;
 pub mod world_render_system;
-mod chunk_mesher;
+mod buffer_allocator;
+mod chunk_buffers;
+pub mod block_textures;
+pub mod chunk_mesher;
 mod staged_buffer;
@@ -1,5 +1,5 @@
 use std::{
-    ops::{Deref},
+    ops::{Deref, Range},
     sync::Arc,
 };
 
@@ -64,6 +64,38 @@ where
         })
     }
 
+    /// Copies only the ranges (in elements) of the host buffer to the device buffer.
+    pub fn upload_ranges_to_device(&self, renderer: &VulkanRenderer, ranges: &[Range<u64>]) {
+        let mut ranges = ranges.to_vec();
+        ranges.sort_by_key(|range| range.start);
+
+        // Merge overlapping and touching ranges so each part of the buffer is copied once.
+        let mut merged_ranges: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+        for range in ranges.into_iter().filter(|range| !range.is_empty()) {
+            match merged_ranges.last_mut() {
+                Some(last_range) if last_range.end >= range.start => {
+                    last_range.end = last_range.end.max(range.end)
+                }
+                _ => merged_ranges.push(range),
+            }
+        }
+
+        if merged_ranges.is_empty() {
+            return;
+        }
+
+        renderer.immediate_submit(|cbb| {
+            for range in merged_ranges.iter() {
+                cbb.copy_buffer(CopyBufferInfo::buffers(
+                    self.host_buffer.clone().slice(range.clone()),
+                    self.device_buffer.clone().slice(range.clone()),
+                ))?;
+            }
+
+            Ok(cbb)
+        })
+    }
+
     pub fn device_buffer(&self) -> &Subbuffer<[T]> {
         &self.device_buffer
     }
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
 
 use vulkano::{
     buffer::{
@@ -18,8 +18,8 @@ use vulkano::{
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     pipeline::{
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{DepthState, DepthStencilState},
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
             input_assembly::InputAssemblyState,
             multisample::MultisampleState,
             rasterization::{CullMode, FrontFace, PolygonMode, RasterizationState},
@@ -35,7 +35,7 @@ use vulkano::{
     Validated, VulkanError,
 };
 
-use super::mvp::MVP;
+use super::{mvp::MVP, texture::Texture};
 
 pub mod vs {
     vulkano_shaders::shader! {
@@ -56,6 +56,40 @@ pub mod fs {
 pub struct MeshVertex {
     #[format(R32G32B32_SFLOAT)]
     pub position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+    /// The layer of the block texture array the face is drawn with.
+    #[format(R32_UINT)]
+    pub texture_index: u32,
+    /// How much light reaches the vertex past the blocks around it, from 0 (fully occluded) to 1.
+    #[format(R32_SFLOAT)]
+    pub ambient_occlusion: f32,
+    /// How brightly emissive blocks light the vertex, from 0 (unlit) to 1.
+    #[format(R32_SFLOAT)]
+    pub block_light: f32,
+    /// How brightly the sky lights the vertex, from 0 (unlit) to 1.
+    #[format(R32_SFLOAT)]
+    pub sky_light: f32,
+}
+
+/// How the default lit pipeline shades faces by the direction they face the sun.
+#[derive(Clone, Copy, Debug)]
+pub struct Lighting {
+    /// The direction towards the sun.
+    pub sun_direction: glam::Vec3,
+    /// How lit faces the sky reaches are when they face away from the sun, from 0 to 1.
+    pub ambient: f32,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting {
+            sun_direction: glam::vec3(1.0, 4.0, 1.0),
+            ambient: 0.35,
+        }
+    }
 }
 
 #[derive(BufferContents)]
@@ -67,6 +101,9 @@ pub struct DefaultLitIndex {
 pub struct DefaultLitPipeline {
     layout: Arc<PipelineLayout>,
     graphics_pipeline: Arc<GraphicsPipeline>,
+    /// Draws translucent faces over the opaque ones, blending them with what is behind them.
+    /// It tests against depth, but doesn't write it, so faces behind other translucent faces still show.
+    translucent_graphics_pipeline: Arc<GraphicsPipeline>,
     framebuffers: Vec<Arc<Framebuffer>>,
 }
 
@@ -107,7 +144,7 @@ impl DefaultLitPipeline {
             device.clone(),
             None,
             GraphicsPipelineCreateInfo {
-                stages: stages.into_iter().collect(),
+                stages: stages.iter().cloned().collect(),
                 vertex_input_state: Some(vertex_input_state.clone()),
                 input_assembly_state: Some(InputAssemblyState {
                     ..Default::default()
@@ -117,7 +154,7 @@ impl DefaultLitPipeline {
                     ..Default::default()
                 }),
                 rasterization_state: Some(RasterizationState {
-                    polygon_mode: PolygonMode::Line,
+                    polygon_mode: PolygonMode::Fill,
                     cull_mode: CullMode::Back,
                     front_face: FrontFace::Clockwise,
                     ..Default::default()
@@ -135,6 +172,43 @@ impl DefaultLitPipeline {
                 ..GraphicsPipelineCreateInfo::layout(layout.clone())
             },
         )?;
+
+        let translucent_graphics_pipeline = GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState {
+                    polygon_mode: PolygonMode::Fill,
+                    cull_mode: CullMode::Back,
+                    front_face: FrontFace::Clockwise,
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState::default()),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState {
+                        blend: Some(AttachmentBlend::alpha()),
+                        ..Default::default()
+                    },
+                )),
+                depth_stencil_state: Some(DepthStencilState {
+                    depth: Some(DepthState {
+                        write_enable: false,
+                        compare_op: CompareOp::Less,
+                    }),
+                    ..Default::default()
+                }),
+                subpass: Some(subpass.clone().into()),
+                ..GraphicsPipelineCreateInfo::layout(layout.clone())
+            },
+        )?;
         let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
         let framebuffers = DefaultLitPipeline::create_framebuffers(
             &memory_allocator,
@@ -146,6 +220,7 @@ impl DefaultLitPipeline {
         Ok(DefaultLitPipeline {
             layout,
             graphics_pipeline,
+            translucent_graphics_pipeline,
             framebuffers,
         })
     }
@@ -156,6 +231,10 @@ impl DefaultLitPipeline {
         queue: Arc<Queue>,
         verticies: &Subbuffer<[MeshVertex]>,
         indicies: &Subbuffer<[u32]>,
+        index_ranges: &[Range<u32>],
+        translucent_verticies: &Subbuffer<[MeshVertex]>,
+        translucent_indicies: &Subbuffer<[u32]>,
+        translucent_index_ranges: &[Range<u32>],
         descriptor_set: Arc<PersistentDescriptorSet>,
     ) -> Result<Vec<Arc<PrimaryAutoCommandBuffer>>, Validated<VulkanError>> {
         self.framebuffers
@@ -193,11 +272,29 @@ impl DefaultLitPipeline {
                     )
                     .unwrap()
                     .bind_index_buffer(indicies.clone())
+                    .unwrap();
+                // Only the ranges holding meshes are drawn, not the free space between them.
+                for index_range in index_ranges {
+                    builder
+                        .draw_indexed(index_range.len() as u32, 1, index_range.start, 0, 0)
+                        .unwrap();
+                }
+
+                // The translucent ranges are drawn one at a time, in the order they were given.
+                builder
+                    .bind_pipeline_graphics(self.translucent_graphics_pipeline.clone())
                     .unwrap()
-                    .draw_indexed(indicies.len() as u32, 1, 0, 0, 0)
+                    .bind_vertex_buffers(0, translucent_verticies.clone())
                     .unwrap()
-                    .end_render_pass(Default::default())
+                    .bind_index_buffer(translucent_indicies.clone())
                     .unwrap();
+                for index_range in translucent_index_ranges {
+                    builder
+                        .draw_indexed(index_range.len() as u32, 1, index_range.start, 0, 0)
+                        .unwrap();
+                }
+
+                builder.end_render_pass(Default::default()).unwrap();
 
                 builder.build()
             })
@@ -275,8 +372,10 @@ impl DefaultLitPipeline {
         memory_allocator: &Arc<StandardMemoryAllocator>,
         descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
         mvp: MVP,
+        lighting: Lighting,
+        block_textures: &Texture,
     ) -> Result<Arc<PersistentDescriptorSet>, Validated<VulkanError>> {
-        let mvp_buffer = SubbufferAllocator::new(
+        let uniform_buffer = SubbufferAllocator::new(
             memory_allocator.clone(),
             SubbufferAllocatorCreateInfo {
                 buffer_usage: BufferUsage::UNIFORM_BUFFER,
@@ -293,17 +392,37 @@ impl DefaultLitPipeline {
                 clip_space: clip_space.to_cols_array_2d(),
             };
 
-            let subbuffer = mvp_buffer.allocate_sized().unwrap();
+            let subbuffer = uniform_buffer.allocate_sized().unwrap();
             *subbuffer.write().unwrap() = mvp_data;
 
             subbuffer
         };
 
+        let lighting_subbuffer = {
+            let lighting_data = fs::Lighting_Data {
+                sun_direction: lighting.sun_direction.normalize().to_array(),
+                ambient: lighting.ambient,
+            };
+
+            let subbuffer = uniform_buffer.allocate_sized().unwrap();
+            *subbuffer.write().unwrap() = lighting_data;
+
+            subbuffer
+        };
+
         let descriptor_set_layout = self.layout().set_layouts().get(0).unwrap();
         PersistentDescriptorSet::new(
             descriptor_set_allocator,
             descriptor_set_layout.clone(),
-            [WriteDescriptorSet::buffer(0, mvp_buffer_subbuffer)],
+            [
+                WriteDescriptorSet::buffer(0, mvp_buffer_subbuffer),
+                WriteDescriptorSet::image_view_sampler(
+                    1,
+                    block_textures.image_view.clone(),
+                    block_textures.sampler.clone(),
+                ),
+                WriteDescriptorSet::buffer(2, lighting_subbuffer),
+            ],
             [],
         )
     }
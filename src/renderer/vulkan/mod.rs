@@ -1,3 +1,4 @@
 pub mod default_lit_pipeline;
 pub mod mvp;
+pub mod texture;
 pub mod vulkan_renderer;
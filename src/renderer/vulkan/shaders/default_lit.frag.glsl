@@ -1,13 +1,44 @@
 #version 450
 
-layout(location = 0) in vec3 v_color;
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec2 v_uv;
+layout(location = 2) flat in uint v_texture_index;
+layout(location = 3) in float v_ambient_occlusion;
+layout(location = 4) in float v_block_light;
+layout(location = 5) in float v_sky_light;
 
 layout(location = 0) out vec4 f_color;
 
-const vec3 LIGHT = vec3(1.0, 4.0, 1.0);
+layout(set = 0, binding = 1) uniform sampler2DArray block_textures;
+
+layout(set = 0, binding = 2) uniform Lighting_Data {
+    // Normalized, pointing towards the sun.
+    vec3 sun_direction;
+    float ambient;
+} lighting;
+
+// Pixels more see-through than this are not drawn at all, so cutout blocks (e.g. leaves)
+// can be drawn with the opaque blocks.
+const float ALPHA_CUTOFF = 0.5;
+// How dark a fully occluded corner gets.
+const float AMBIENT_OCCLUSION_STRENGTH = 0.6;
+// Block light is a little warmer than sunlight.
+const vec3 BLOCK_LIGHT_COLOR = vec3(1.0, 0.9, 0.75);
+// How bright faces that neither the sky nor any block light reaches are, so caves aren't pitch black.
+const float MIN_LIGHT = 0.04;
 
 void main() {
-    vec3 dark_color = vec3(1.0, 0.0, 0.0);
+    vec4 color = texture(block_textures, vec3(v_uv, float(v_texture_index)));
+    if (color.a < ALPHA_CUTOFF) {
+        discard;
+    }
+
+    float diffuse = max(dot(normalize(v_normal), lighting.sun_direction), 0.0);
+    float sun_light = lighting.ambient + (1.0 - lighting.ambient) * diffuse;
+    sun_light = max(sun_light * v_sky_light, MIN_LIGHT);
+    vec3 light = max(vec3(sun_light), BLOCK_LIGHT_COLOR * v_block_light);
+    light *= mix(1.0 - AMBIENT_OCCLUSION_STRENGTH, 1.0, v_ambient_occlusion);
 
-    f_color = vec4(mix(dark_color, v_color, vec3(1.0, 1.0, 1.0)), 1.0);
+    // Alpha is only used by the translucent pass, the opaque pass doesn't blend.
+    f_color = vec4(color.rgb * light, color.a);
 }
\ No newline at end of file
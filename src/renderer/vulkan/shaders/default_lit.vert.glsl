@@ -1,15 +1,31 @@
 #version 450
 
 layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in uint texture_index;
+layout(location = 4) in float ambient_occlusion;
+layout(location = 5) in float block_light;
+layout(location = 6) in float sky_light;
 
-layout(location = 0) out vec3 v_color;
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec2 v_uv;
+layout(location = 2) flat out uint v_texture_index;
+layout(location = 3) out float v_ambient_occlusion;
+layout(location = 4) out float v_block_light;
+layout(location = 5) out float v_sky_light;
 
 layout(set = 0, binding = 0) uniform MVP_Data {
     mat4 clip_space;
 } mvp;
 
 void main() {
-    v_color = vec3(1.0, 0.0, 0.0);
+    v_normal = normal;
+    v_uv = uv;
+    v_texture_index = texture_index;
+    v_ambient_occlusion = ambient_occlusion;
+    v_block_light = block_light;
+    v_sky_light = sky_light;
     gl_Position = mvp.clip_space * vec4(position, 1.0);
 
 }
\ No newline at end of file
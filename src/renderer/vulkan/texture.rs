@@ -0,0 +1,10 @@
+use std::sync::Arc;
+
+use vulkano::image::{sampler::Sampler, view::ImageView};
+
+/// An image on the device along with the sampler shaders read it with.
+#[derive(Clone)]
+pub struct Texture {
+    pub image_view: Arc<ImageView>,
+    pub sampler: Arc<Sampler>,
+}
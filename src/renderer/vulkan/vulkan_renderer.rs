@@ -1,19 +1,27 @@
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
 
 use sdl2::video::Window;
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
-        CommandBufferExecFuture, CommandBufferUsage, PrimaryAutoCommandBuffer,
-        PrimaryCommandBufferAbstract,
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
+        CommandBufferExecFuture, CommandBufferUsage, CopyBufferToImageInfo,
+        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
     },
     descriptor_set::allocator::StandardDescriptorSetAllocator,
     device::{
         physical::{PhysicalDevice, PhysicalDeviceType},
         Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
     },
-    image::Image,
+    format::Format,
+    image::{
+        sampler::{
+            Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode,
+            LOD_CLAMP_NONE,
+        },
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageAspects, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage,
+    },
     instance::{Instance, InstanceCreateInfo, InstanceExtensions},
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
     swapchain::{
@@ -28,11 +36,15 @@ use vulkano::{
     Handle, Validated, ValidationError, VulkanLibrary, VulkanObject,
 };
 
-use crate::renderer::staged_buffer::StagedBuffer;
+use crate::renderer::{
+    block_textures::{BlockTextureArray, BLOCK_TEXTURE_SIZE},
+    staged_buffer::StagedBuffer,
+};
 
 use super::{
-    default_lit_pipeline::{DefaultLitPipeline, MeshVertex},
+    default_lit_pipeline::{DefaultLitPipeline, Lighting, MeshVertex},
     mvp::MVP,
+    texture::Texture,
 };
 
 const REQUIRED_DEVICE_EXTENSIONS: DeviceExtensions = DeviceExtensions {
@@ -366,13 +378,25 @@ impl VulkanRenderer {
     pub fn default_lit(
         &mut self,
         mvp: MVP,
+        lighting: Lighting,
+        block_textures: &Texture,
         vertex_buffer: &Subbuffer<[MeshVertex]>,
         index_buffer: &Subbuffer<[u32]>,
+        index_ranges: &[Range<u32>],
+        translucent_vertex_buffer: &Subbuffer<[MeshVertex]>,
+        translucent_index_buffer: &Subbuffer<[u32]>,
+        translucent_index_ranges: &[Range<u32>],
     ) {
         let descriptor_set = self
             .pipelines
             .default_lit
-            .create_descriptor_set(&self.memory_allocator, &self.descriptor_set_allocator, mvp)
+            .create_descriptor_set(
+                &self.memory_allocator,
+                &self.descriptor_set_allocator,
+                mvp,
+                lighting,
+                block_textures,
+            )
             .unwrap();
 
         // TODO (Michael) call pipeline
@@ -384,6 +408,10 @@ impl VulkanRenderer {
                 self.queue.clone(),
                 vertex_buffer,
                 index_buffer,
+                index_ranges,
+                translucent_vertex_buffer,
+                translucent_index_buffer,
+                translucent_index_ranges,
                 descriptor_set,
             )
             .unwrap();
@@ -487,4 +515,94 @@ impl VulkanRenderer {
         )
         .unwrap()
     }
+
+    /// Uploads the block textures into a texture array image with one layer per texture,
+    /// along with all of their mip levels.
+    pub fn create_block_texture_array(&self, texture_array: &BlockTextureArray) -> Texture {
+        let image = Image::new(
+            self.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8G8B8A8_SRGB,
+                extent: [BLOCK_TEXTURE_SIZE, BLOCK_TEXTURE_SIZE, 1],
+                array_layers: texture_array.layer_count,
+                mip_levels: texture_array.mip_levels.len() as u32,
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let staging_buffer = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            texture_array.mip_levels.concat(),
+        )
+        .unwrap();
+
+        let mut buffer_offset = 0;
+        let mut regions = Vec::new();
+        for (mip_level, pixels) in texture_array.mip_levels.iter().enumerate() {
+            let size = BlockTextureArray::mip_level_size(mip_level);
+            regions.push(BufferImageCopy {
+                buffer_offset,
+                image_subresource: ImageSubresourceLayers {
+                    aspects: ImageAspects::COLOR,
+                    mip_level: mip_level as u32,
+                    array_layers: 0..texture_array.layer_count,
+                },
+                image_extent: [size, size, 1],
+                ..Default::default()
+            });
+            buffer_offset += pixels.len() as u64;
+        }
+
+        self.immediate_submit(|builder| {
+            builder.copy_buffer_to_image(CopyBufferToImageInfo {
+                regions: regions.iter().cloned().collect(),
+                ..CopyBufferToImageInfo::buffer_image(staging_buffer.clone(), image.clone())
+            })
+        });
+
+        let image_view = ImageView::new(
+            image.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Dim2dArray,
+                ..ImageViewCreateInfo::from_image(&image)
+            },
+        )
+        .unwrap();
+
+        // Textures stay pixelated up close, and blend between mip levels further away
+        // so they don't shimmer.
+        let sampler = Sampler::new(
+            self.logical_device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Linear,
+                mipmap_mode: SamplerMipmapMode::Linear,
+                address_mode: [SamplerAddressMode::Repeat; 3],
+                lod: 0.0..=LOD_CLAMP_NONE,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        Texture {
+            image_view,
+            sampler,
+        }
+    }
 }
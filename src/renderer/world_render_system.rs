@@ -1,6 +1,11 @@
 
 
-use std::{collections::HashMap};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::Path,
+    sync::Arc,
+};
 
 use glam::vec3;
 use rayon::iter::{
@@ -12,6 +17,7 @@ use rayon::iter::{
 use vulkano::sync::HostAccessError;
 
 
+use crate::world::block_registry::BlockRegistry;
 use crate::world::chunk::{CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH};
 
 use crate::{
@@ -19,106 +25,284 @@ use crate::{
     world::world::World,
 };
 
-use super::chunk_mesher::ChunkMesher;
-use super::staged_buffer::StagedBuffer;
-use super::{
-    mesh::{Mesh},
-    vulkan::{default_lit_pipeline::MeshVertex, mvp::MVP, vulkan_renderer::VulkanRenderer},
+use super::block_textures::{BlockTextureError, BlockTextures};
+use super::chunk_buffers::{ChunkBufferError, ChunkBuffers};
+use super::chunk_mesher::{ChunkBorder, ChunkMesh, ChunkMesher, MeshingMode};
+use super::vulkan::{
+    default_lit_pipeline::Lighting, mvp::MVP, texture::Texture, vulkan_renderer::VulkanRenderer,
 };
 
+const OPAQUE_CHUNK_VERTEX_BUFFER_SIZE: u64 = 32 << 20;
+const OPAQUE_CHUNK_INDEX_BUFFER_SIZE: u64 = 48 << 20;
+const TRANSLUCENT_CHUNK_VERTEX_BUFFER_SIZE: u64 = 4 << 20;
+const TRANSLUCENT_CHUNK_INDEX_BUFFER_SIZE: u64 = 6 << 20;
+
+/// The newest mesh started for a chunk.
+struct MeshedChunk {
+    revision: u64,
+    /// The neighbouring chunks that were loaded when the mesh was built.
+    /// The mesher treats the ones that weren't as air, so the mesh is rebuilt once they load.
+    neighbor_chunk_origins: HashSet<BlockPosition>,
+}
+
 pub struct WorldRenderSystem {
     chunk_mesher: ChunkMesher,
+    block_texture_array: Texture,
+    lighting: Lighting,
+
+    /// The chunks that should have a mesh, along with their newest mesh.
+    /// Meshes that finish for chunks that were dropped while they were being built,
+    /// or that were replaced by a newer mesh, are thrown away.
+    meshed_chunks: HashMap<BlockPosition, MeshedChunk>,
+    next_mesh_revision: u64,
+    /// Chunks whose mesh couldn't be written or dropped because the buffers couldn't be written to,
+    /// they are remeshed, or dropped again if they were unloaded, in the next update.
+    chunks_to_retry: HashSet<BlockPosition>,
 
-    opaque_chunk_meshes: HashMap<BlockPosition, Mesh>,
-    opaque_chunk_vertex_buffer: StagedBuffer<MeshVertex>,
-    opaque_chunk_index_buffer: StagedBuffer<u32>,
+    opaque_chunk_buffers: ChunkBuffers,
+    translucent_chunk_buffers: ChunkBuffers,
 }
 
 impl WorldRenderSystem {
-    pub fn new(renderer: &VulkanRenderer) -> Self {
-        WorldRenderSystem {
-            chunk_mesher: ChunkMesher::new(),
-            opaque_chunk_meshes: Default::default(),
-            opaque_chunk_vertex_buffer: renderer
-                .create_staged_vertex_buffer::<MeshVertex>(32 << 20),
-            opaque_chunk_index_buffer: renderer.create_staged_index_buffer::<u32>(48 << 20),
-        }
+    /// Loads the textures of every block type in the registry from the directory.
+    pub fn new(
+        renderer: &VulkanRenderer,
+        block_registry: Arc<BlockRegistry>,
+        block_textures_directory: impl AsRef<Path>,
+        meshing_mode: MeshingMode,
+    ) -> Result<Self, BlockTextureError> {
+        let block_textures = BlockTextures::new(&block_registry);
+        let block_texture_array = renderer.create_block_texture_array(
+            &block_textures.load_texture_array(block_textures_directory)?,
+        );
+
+        Ok(WorldRenderSystem {
+            chunk_mesher: ChunkMesher::new(block_registry, Arc::new(block_textures), meshing_mode),
+            block_texture_array,
+            lighting: Lighting::default(),
+            meshed_chunks: Default::default(),
+            next_mesh_revision: 0,
+            chunks_to_retry: HashSet::new(),
+            opaque_chunk_buffers: ChunkBuffers::new(
+                renderer,
+                OPAQUE_CHUNK_VERTEX_BUFFER_SIZE,
+                OPAQUE_CHUNK_INDEX_BUFFER_SIZE,
+            ),
+            translucent_chunk_buffers: ChunkBuffers::new(
+                renderer,
+                TRANSLUCENT_CHUNK_VERTEX_BUFFER_SIZE,
+                TRANSLUCENT_CHUNK_INDEX_BUFFER_SIZE,
+            ),
+        })
     }
-    pub fn build_chunk_meshes(&mut self, world: &World) {
-        for (chunk_origin_position, chunk) in world.chunks.iter() {
-            let neighbor_chunks = vec![
-                world
-                    .chunks
-                    .get(&chunk_origin_position.offset(0, 0, CHUNK_BLOCK_DEPTH as i32))
-                    .copied(),
-                world
-                    .chunks
-                    .get(&chunk_origin_position.offset(0, 0, -(CHUNK_BLOCK_DEPTH as i32)))
-                    .copied(),
-                world
-                    .chunks
-                    .get(&chunk_origin_position.offset(CHUNK_BLOCK_WIDTH as i32, 0, 0))
-                    .copied(),
-                world
-                    .chunks
-                    .get(&chunk_origin_position.offset(-(CHUNK_BLOCK_WIDTH as i32), 0, 0))
-                    .copied(),
-                world
-                    .chunks
-                    .get(&chunk_origin_position.offset(0, CHUNK_BLOCK_HEIGHT as i32, 0))
-                    .copied(),
-                world
-                    .chunks
-                    .get(&chunk_origin_position.offset(0, -(CHUNK_BLOCK_HEIGHT as i32), 0))
-                    .copied(),
-            ];
-            self.chunk_mesher
-                .begin_meshing_chunk(chunk.to_owned(), neighbor_chunks)
-        }
+
+    pub fn lighting(&self) -> Lighting {
+        self.lighting
     }
 
-    pub fn write_meshes(&mut self) -> Result<(), HostAccessError> {
-        let mut vertex_writer = self.opaque_chunk_vertex_buffer.write()?;
-        let mut index_writer = self.opaque_chunk_index_buffer.write()?;
+    /// Sets how the world is lit by the sun, from the next frame on.
+    pub fn set_lighting(&mut self, lighting: Lighting) {
+        self.lighting = lighting;
+    }
 
-        let mut vertex_writer_iter = vertex_writer.iter_mut();
-        let mut index_writer_iter = index_writer.iter_mut();
+    /// Remeshes the chunks the world marked dirty, and drops the meshes of the dirty chunks
+    /// that are no longer loaded.
+    ///
+    /// Meshes built while a neighbouring chunk wasn't loaded have faces along the border with it,
+    /// so they are remeshed when it loads. Unloading a chunk doesn't remesh the chunks around it,
+    /// but they are remeshed if it loads again.
+    pub fn update_chunk_meshes(&mut self, world: &mut World) {
+        let mut chunks_to_mesh = HashSet::new();
 
-        let mut index_offset = 0;
+        for chunk_origin_position in std::mem::take(&mut self.chunks_to_retry) {
+            if world.chunks.contains_key(&chunk_origin_position) {
+                chunks_to_mesh.insert(chunk_origin_position);
+            } else if self.drop_chunk_mesh(chunk_origin_position).is_err() {
+                self.chunks_to_retry.insert(chunk_origin_position);
+            }
+        }
+
+        for chunk_origin_position in world.take_dirty_chunks() {
+            let is_loaded = world.chunks.contains_key(&chunk_origin_position);
+            if is_loaded {
+                chunks_to_mesh.insert(chunk_origin_position);
+            } else if self.drop_chunk_mesh(chunk_origin_position).is_err() {
+                self.chunks_to_retry.insert(chunk_origin_position);
+            }
+
+            for neighbor_chunk_origin in
+                WorldRenderSystem::neighbor_chunk_origins(chunk_origin_position)
+            {
+                let Some(meshed_chunk) = self.meshed_chunks.get_mut(&neighbor_chunk_origin) else {
+                    continue;
+                };
 
-        for (_, ocm) in self.opaque_chunk_meshes.iter() {
-            for vertex in ocm.vertices() {
-                let existing =  vertex_writer_iter.next().expect("tried to write mesh to vertex buffer, but vertex buffer has no room available!");
-                *existing = *vertex;
+                if !is_loaded {
+                    meshed_chunk
+                        .neighbor_chunk_origins
+                        .remove(&chunk_origin_position);
+                } else if !meshed_chunk
+                    .neighbor_chunk_origins
+                    .contains(&chunk_origin_position)
+                {
+                    chunks_to_mesh.insert(neighbor_chunk_origin);
+                }
             }
+        }
+
+        for chunk_origin_position in chunks_to_mesh {
+            self.build_chunk_mesh(world, chunk_origin_position);
+        }
+    }
+
+    /// The origins of the 26 chunks around the chunk, not just the ones sharing a face with it,
+    /// ambient occlusion looks at the blocks diagonal to the chunk's edges and corners too.
+    fn neighbor_chunk_origins(chunk_origin_position: BlockPosition) -> Vec<BlockPosition> {
+        let mut neighbor_chunk_origins = Vec::new();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if (x, y, z) == (0, 0, 0) {
+                        continue;
+                    }
 
-            for index in ocm.indicies() {
-                let existing = index_writer_iter.next().expect(
-                    "tried to write mesh to index buffer, but index buffer has no room available!",
-                );
-                *existing = *index + index_offset;
+                    neighbor_chunk_origins.push(chunk_origin_position.offset(
+                        x * CHUNK_BLOCK_WIDTH as i32,
+                        y * CHUNK_BLOCK_HEIGHT as i32,
+                        z * CHUNK_BLOCK_DEPTH as i32,
+                    ));
+                }
             }
+        }
+
+        neighbor_chunk_origins
+    }
+
+    /// Starts building the mesh of the chunk, it replaces the chunk's current mesh once it is ready.
+    pub fn build_chunk_mesh(&mut self, world: &World, chunk_origin_position: BlockPosition) {
+        let Some(chunk) = world.chunks.get(&chunk_origin_position) else {
+            return;
+        };
+
+        let neighbor_chunk_origins: HashSet<BlockPosition> =
+            WorldRenderSystem::neighbor_chunk_origins(chunk_origin_position)
+                .into_iter()
+                .filter(|neighbor_chunk_origin| world.chunks.contains_key(neighbor_chunk_origin))
+                .collect();
+        let chunk_border = ChunkBorder::new(chunk_origin_position, |neighbor_chunk_origin| {
+            world.chunks.get(&neighbor_chunk_origin)
+        });
+
+        let revision = self.next_mesh_revision;
+        self.next_mesh_revision += 1;
+        self.meshed_chunks.insert(
+            chunk_origin_position,
+            MeshedChunk {
+                revision,
+                neighbor_chunk_origins,
+            },
+        );
+        self.chunk_mesher
+            .begin_meshing_chunk(chunk.to_owned(), chunk_border, revision)
+    }
+
+    pub fn drop_chunk_mesh(
+        &mut self,
+        chunk_origin_position: BlockPosition,
+    ) -> Result<(), HostAccessError> {
+        self.meshed_chunks.remove(&chunk_origin_position);
+        self.opaque_chunk_buffers
+            .free_chunk_mesh(chunk_origin_position)?;
+        self.translucent_chunk_buffers
+            .free_chunk_mesh(chunk_origin_position)
+    }
 
-            index_offset += ocm.vertices().len() as u32;
+    /// Replaces the chunk's meshes in the buffers with the finished meshes.
+    fn write_chunk_mesh(&mut self, chunk_mesh: ChunkMesh) -> Result<(), ChunkBufferError> {
+        let chunk_origin_position = chunk_mesh.chunk_origin_position;
+        self.opaque_chunk_buffers
+            .write_chunk_mesh(chunk_origin_position, chunk_mesh.meshes.opaque)?;
+        self.translucent_chunk_buffers
+            .write_chunk_mesh(chunk_origin_position, chunk_mesh.meshes.translucent)
+    }
+
+    /// The index ranges of the opaque chunk meshes, with ranges that touch merged
+    /// so meshes allocated next to each other are drawn together.
+    fn opaque_index_ranges(&self) -> Vec<Range<u32>> {
+        let mut index_ranges: Vec<Range<u32>> = self
+            .opaque_chunk_buffers
+            .index_ranges()
+            .map(|(_, index_range)| index_range)
+            .collect();
+        index_ranges.sort_by_key(|index_range| index_range.start);
+
+        let mut merged_index_ranges: Vec<Range<u32>> = Vec::with_capacity(index_ranges.len());
+        for index_range in index_ranges {
+            match merged_index_ranges.last_mut() {
+                Some(last_range) if last_range.end == index_range.start => {
+                    last_range.end = index_range.end
+                }
+                _ => merged_index_ranges.push(index_range),
+            }
         }
 
-        Ok(())
+        merged_index_ranges
+    }
+
+    /// The index ranges of the translucent chunk meshes, furthest from the camera first.
+    /// Translucent faces don't write depth, so they have to be drawn back to front to blend correctly.
+    fn sorted_translucent_index_ranges(&self, camera: &Camera) -> Vec<Range<u32>> {
+        let eye_position = camera.eye_position();
+        let chunk_half_size = vec3(
+            CHUNK_BLOCK_WIDTH as f32,
+            CHUNK_BLOCK_HEIGHT as f32,
+            CHUNK_BLOCK_DEPTH as f32,
+        ) / 2.0;
+
+        let mut index_ranges: Vec<(f32, Range<u32>)> = self
+            .translucent_chunk_buffers
+            .index_ranges()
+            .map(|(chunk_origin_position, index_range)| {
+                let chunk_center = chunk_origin_position.to_vec3() + chunk_half_size;
+                (chunk_center.distance_squared(eye_position), index_range)
+            })
+            .collect();
+        index_ranges.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        index_ranges
+            .into_iter()
+            .map(|(_, index_range)| index_range)
+            .collect()
     }
 
     pub fn render_world(&mut self, renderer: &mut VulkanRenderer, camera: &Camera) {
-        let chunk_meshes = self.chunk_mesher.ready_chunk_meshes();
+        for chunk_mesh in self.chunk_mesher.ready_chunk_meshes() {
+            let is_newest = self
+                .meshed_chunks
+                .get(&chunk_mesh.chunk_origin_position)
+                .is_some_and(|meshed_chunk| meshed_chunk.revision == chunk_mesh.revision);
+            if !is_newest {
+                continue;
+            }
 
-        if !chunk_meshes.is_empty() {
-            for (chunk_origin_pos, chunk_mesh) in chunk_meshes {
-                self.opaque_chunk_meshes
-                    .insert(chunk_origin_pos, chunk_mesh);
+            let chunk_origin_position = chunk_mesh.chunk_origin_position;
+            match self.write_chunk_mesh(chunk_mesh) {
+                Ok(()) => {}
+                Err(ChunkBufferError::HostAccess(_)) => {
+                    self.chunks_to_retry.insert(chunk_origin_position);
+                }
+                // The chunk is left without a mesh until it changes and is remeshed.
+                Err(ChunkBufferError::OutOfSpace) => eprintln!(
+                    "the chunk buffers have no room for the mesh of the chunk at {:?}",
+                    chunk_origin_position
+                ),
             }
-            // TODO (Michael): Instead of having meshes and then copying to a vertex buffer, we should just create the mesh directly in the vertex buffer memory
-            self.write_meshes();
-            self.opaque_chunk_vertex_buffer.upload_to_device(renderer);
-            self.opaque_chunk_index_buffer.upload_to_device(renderer);
         }
 
+        // TODO (Michael): Instead of having meshes and then copying to a vertex buffer, we should just create the mesh directly in the vertex buffer memory
+        self.opaque_chunk_buffers.upload(renderer);
+        self.translucent_chunk_buffers.upload(renderer);
+
         let mvp = MVP {
             model: vec3(0.0, 0.0, 0.0),
             view: camera.view(),
@@ -127,8 +311,14 @@ impl WorldRenderSystem {
 
         renderer.default_lit(
             mvp,
-            &self.opaque_chunk_vertex_buffer.device_buffer(),
-            &self.opaque_chunk_index_buffer.device_buffer(),
+            self.lighting,
+            &self.block_texture_array,
+            self.opaque_chunk_buffers.vertex_buffer(),
+            self.opaque_chunk_buffers.index_buffer(),
+            &self.opaque_index_ranges(),
+            self.translucent_chunk_buffers.vertex_buffer(),
+            self.translucent_chunk_buffers.index_buffer(),
+            &self.sorted_translucent_index_ranges(camera),
         );
     }
 }
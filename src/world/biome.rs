@@ -0,0 +1,205 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, FromRepr};
+
+const CLIMATE_NOISE_FREQUENCY: f64 = 1.0 / 1024.0;
+const CLIMATE_NOISE_OCTAVES: usize = 4;
+
+/// How far (in climate space) a biome's influence reaches when blending terrain between biomes.
+/// Larger values give wider, smoother transitions.
+const BIOME_BLEND_SPREAD: f64 = 0.12;
+
+/// Features that a biome can decorate its terrain with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecorationKind {
+    Tree,
+    Boulder,
+    Ruin,
+    Cactus,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Decoration {
+    pub kind: DecorationKind,
+    /// The chance that a single column is the origin of this decoration.
+    pub chance_per_column: f64,
+}
+
+/// The parameters a [Biome] applies to world generation.
+pub struct BiomeParameters {
+    pub base_height: f64,
+    pub height_amplitude: f64,
+    pub surface_block: &'static str,
+    pub subsurface_block: &'static str,
+    pub decorations: &'static [Decoration],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default, EnumIter, FromRepr)]
+#[repr(u8)]
+pub enum Biome {
+    #[default]
+    Plains = 0,
+    Ocean = 1,
+    Desert = 2,
+    Forest = 3,
+    Mountains = 4,
+    Tundra = 5,
+}
+
+impl Biome {
+    /// The (temperature, humidity) this biome is most likely to appear at.
+    fn climate(&self) -> (f64, f64) {
+        match self {
+            Biome::Plains => (0.0, 0.0),
+            Biome::Ocean => (0.1, 0.5),
+            Biome::Desert => (0.45, -0.35),
+            Biome::Forest => (0.15, 0.25),
+            Biome::Mountains => (-0.2, -0.3),
+            Biome::Tundra => (-0.45, 0.1),
+        }
+    }
+
+    pub fn parameters(&self) -> BiomeParameters {
+        match self {
+            Biome::Plains => BiomeParameters {
+                base_height: 68.0,
+                height_amplitude: 8.0,
+                surface_block: "vox:grass",
+                subsurface_block: "vox:dirt",
+                decorations: &[
+                    Decoration {
+                        kind: DecorationKind::Tree,
+                        chance_per_column: 0.002,
+                    },
+                    Decoration {
+                        kind: DecorationKind::Ruin,
+                        chance_per_column: 0.0001,
+                    },
+                ],
+            },
+            Biome::Ocean => BiomeParameters {
+                base_height: 40.0,
+                height_amplitude: 12.0,
+                surface_block: "vox:sand",
+                subsurface_block: "vox:sand",
+                decorations: &[],
+            },
+            Biome::Desert => BiomeParameters {
+                base_height: 70.0,
+                height_amplitude: 10.0,
+                surface_block: "vox:sand",
+                subsurface_block: "vox:sand",
+                decorations: &[
+                    Decoration {
+                        kind: DecorationKind::Cactus,
+                        chance_per_column: 0.004,
+                    },
+                    Decoration {
+                        kind: DecorationKind::Ruin,
+                        chance_per_column: 0.0002,
+                    },
+                ],
+            },
+            Biome::Forest => BiomeParameters {
+                base_height: 72.0,
+                height_amplitude: 16.0,
+                surface_block: "vox:grass",
+                subsurface_block: "vox:dirt",
+                decorations: &[Decoration {
+                    kind: DecorationKind::Tree,
+                    chance_per_column: 0.03,
+                }],
+            },
+            Biome::Mountains => BiomeParameters {
+                base_height: 100.0,
+                height_amplitude: 64.0,
+                surface_block: "vox:stone",
+                subsurface_block: "vox:stone",
+                decorations: &[Decoration {
+                    kind: DecorationKind::Boulder,
+                    chance_per_column: 0.003,
+                }],
+            },
+            Biome::Tundra => BiomeParameters {
+                base_height: 70.0,
+                height_amplitude: 12.0,
+                surface_block: "vox:snow",
+                subsurface_block: "vox:dirt",
+                decorations: &[Decoration {
+                    kind: DecorationKind::Boulder,
+                    chance_per_column: 0.001,
+                }],
+            },
+        }
+    }
+}
+
+/// Picks biomes from temperature and humidity noise.
+pub struct BiomeSource {
+    temperature_noise: Fbm<Perlin>,
+    humidity_noise: Fbm<Perlin>,
+}
+
+impl BiomeSource {
+    pub fn new(temperature_seed: u32, humidity_seed: u32) -> Self {
+        BiomeSource {
+            temperature_noise: Fbm::<Perlin>::new(temperature_seed)
+                .set_octaves(CLIMATE_NOISE_OCTAVES)
+                .set_frequency(CLIMATE_NOISE_FREQUENCY),
+            humidity_noise: Fbm::<Perlin>::new(humidity_seed)
+                .set_octaves(CLIMATE_NOISE_OCTAVES)
+                .set_frequency(CLIMATE_NOISE_FREQUENCY),
+        }
+    }
+
+    /// The (temperature, humidity) of the column.
+    pub fn climate(&self, x: i32, z: i32) -> (f64, f64) {
+        let point = [x as f64, z as f64];
+
+        (
+            self.temperature_noise.get(point),
+            self.humidity_noise.get(point),
+        )
+    }
+
+    /// How much each biome contributes to the column, the weights always sum to 1.
+    ///
+    /// The weights fall off smoothly with the distance between the column's climate and
+    /// each biome's climate, so anything blended with them is continuous across biome borders.
+    pub fn biome_weights(&self, x: i32, z: i32) -> Vec<(Biome, f64)> {
+        let (temperature, humidity) = self.climate(x, z);
+
+        let mut weights: Vec<(Biome, f64)> = Biome::iter()
+            .map(|biome| {
+                let (biome_temperature, biome_humidity) = biome.climate();
+                let distance_squared =
+                    (temperature - biome_temperature).powi(2) + (humidity - biome_humidity).powi(2);
+
+                (
+                    biome,
+                    (-distance_squared / (2.0 * BIOME_BLEND_SPREAD.powi(2))).exp(),
+                )
+            })
+            .collect();
+
+        let total_weight: f64 = weights.iter().map(|(_, weight)| weight).sum();
+        for (_, weight) in weights.iter_mut() {
+            *weight /= total_weight;
+        }
+
+        weights
+    }
+
+    /// The biome with the most influence over the column.
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        BiomeSource::dominant_biome(&self.biome_weights(x, z))
+    }
+
+    pub fn dominant_biome(biome_weights: &[(Biome, f64)]) -> Biome {
+        biome_weights
+            .iter()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(biome, _)| *biome)
+            .unwrap_or_default()
+    }
+}
@@ -1,48 +1,223 @@
-enum StateFlags {
+use serde::Deserialize;
+
+use super::{block_registry::BlockType, direction::Direction};
+
+/// The properties that can be packed into [Block::state].
+///
+/// Every flag owns a fixed range of bits. Flags that describe the same thing in a different
+/// way (e.g. [StateFlags::Facing] and [StateFlags::HorizontalFacing]) share their bits,
+/// so a block type can only ever declare one of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum StateFlags {
     Solid,
-    Rotation,
+    Facing,
+    HorizontalFacing,
+    Axis,
+    Half,
+    Waterlogged,
+    GrowthStage,
+    FluidLevel,
+    FluidFalling,
 }
 
 impl StateFlags {
     fn least_significant_bit_pos(&self) -> u32 {
         match self {
             StateFlags::Solid => 0,
-            StateFlags::Rotation => 1,
+            StateFlags::Facing | StateFlags::HorizontalFacing => 1,
+            StateFlags::Axis => 4,
+            StateFlags::Half => 6,
+            StateFlags::Waterlogged => 7,
+            StateFlags::GrowthStage => 8,
+            StateFlags::FluidLevel => 11,
+            StateFlags::FluidFalling => 14,
+        }
+    }
+
+    fn bit_width(&self) -> u32 {
+        match self {
+            StateFlags::Solid => 1,
+            StateFlags::Facing | StateFlags::HorizontalFacing => 3,
+            StateFlags::Axis => 2,
+            StateFlags::Half => 1,
+            StateFlags::Waterlogged => 1,
+            StateFlags::GrowthStage => 3,
+            StateFlags::FluidLevel => 3,
+            StateFlags::FluidFalling => 1,
         }
     }
 
     fn mask(&self) -> u32 {
+        (1 << self.bit_width()) - 1
+    }
+
+    /// The number of valid values for this flag, valid values are always `0..value_count`.
+    pub fn value_count(&self) -> u32 {
         match self {
-            StateFlags::Solid => (1 << 1) - 1,
-            StateFlags::Rotation => (1 << 2) - 1,
+            StateFlags::Facing => 6,
+            StateFlags::HorizontalFacing => 4,
+            StateFlags::Axis => 3,
+            _ => 1 << self.bit_width(),
         }
     }
 
+    pub fn overlaps(&self, other: &StateFlags) -> bool {
+        let self_bits = self.mask() << self.least_significant_bit_pos();
+        let other_bits = other.mask() << other.least_significant_bit_pos();
+
+        self_bits & other_bits != 0
+    }
+
     pub fn get(&self, state: u32) -> u32 {
         state >> self.least_significant_bit_pos() & self.mask()
     }
+
+    pub fn set(&self, state: u32, value: u32) -> u32 {
+        let shifted_mask = self.mask() << self.least_significant_bit_pos();
+
+        (state & !shifted_mask) | ((value & self.mask()) << self.least_significant_bit_pos())
+    }
+}
+
+#[derive(Debug)]
+pub enum BlockStateError {
+    UndeclaredProperty(StateFlags),
+    ValueOutOfRange(StateFlags, u32),
 }
 
-#[derive(Copy, Clone, Debug)]
+/// A value that can be stored in the bits of a [StateFlags].
+pub trait StateValue: Sized {
+    fn to_bits(self) -> u32;
+    fn from_bits(bits: u32) -> Option<Self>;
+}
+
+/// A typed view of a [StateFlags], see [BlockType::get] and [BlockType::with_state].
+pub trait StateProperty {
+    type Value: StateValue;
+    const FLAG: StateFlags;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X = 0,
+    Y = 1,
+    Z = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Half {
+    Bottom = 0,
+    Top = 1,
+}
+
+impl StateValue for Direction {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        Direction::from_repr(bits as u8)
+    }
+}
+
+impl StateValue for Axis {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(Axis::X),
+            1 => Some(Axis::Y),
+            2 => Some(Axis::Z),
+            _ => None,
+        }
+    }
+}
+
+impl StateValue for Half {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(Half::Bottom),
+            1 => Some(Half::Top),
+            _ => None,
+        }
+    }
+}
+
+impl StateValue for bool {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        Some(bits != 0)
+    }
+}
+
+impl StateValue for u8 {
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        u8::try_from(bits).ok()
+    }
+}
+
+/// Declares a [StateProperty] for each flag and generates a getter for it on [BlockType].
+macro_rules! state_properties {
+    ($($property:ident: $value:ty = $flag:ident => $getter:ident),* $(,)?) => {
+        $(
+            pub struct $property;
+
+            impl StateProperty for $property {
+                type Value = $value;
+                const FLAG: StateFlags = StateFlags::$flag;
+            }
+        )*
+
+        impl BlockType {
+            $(
+                pub fn $getter(&self, block: &Block) -> Option<$value> {
+                    self.get::<$property>(block)
+                }
+            )*
+        }
+    };
+}
+
+state_properties! {
+    FacingProperty: Direction = Facing => facing,
+    HorizontalFacingProperty: Direction = HorizontalFacing => horizontal_facing,
+    AxisProperty: Axis = Axis => axis,
+    HalfProperty: Half = Half => half,
+    WaterloggedProperty: bool = Waterlogged => is_waterlogged,
+    GrowthStageProperty: u8 = GrowthStage => growth_stage,
+    FluidLevelProperty: u8 = FluidLevel => fluid_level,
+    FluidFallingProperty: bool = FluidFalling => is_fluid_falling,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Block {
     pub id: u16,
     pub state: u32,
 }
 
 impl Block {
+    /// The id `vox:air` is always registered under in the [BlockRegistry](super::block_registry::BlockRegistry).
+    pub const AIR_ID: u16 = 0;
+
     pub fn is_solid(&self) -> bool {
         return StateFlags::Solid.get(self.state) != 0;
     }
 
-    // pub fn facing_direction(&self) -> Direction {
-    //     Direction::try_from(value)
-    //     match StateFlags::Rotation.get(self.state) {
-
-    //     }
-    // }
-
     pub fn is_air(&self) -> bool {
-        self.id == 0
+        self.id == Block::AIR_ID
     }
 }
 
@@ -0,0 +1,150 @@
+use serde::Deserialize;
+
+use super::{block::Half, direction::Direction};
+
+/// Model coordinates go from 0 to this along each axis of the block.
+pub const MODEL_SIZE: u8 = 16;
+
+/// The shape a block type is drawn with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub enum BlockModel {
+    /// A full block.
+    #[default]
+    Cube,
+    /// Axis aligned boxes, modelled facing north and the right way up.
+    /// They are turned to face the block's facing, and flipped upside down for top halves.
+    Boxes(Vec<ModelBox>),
+    /// Two planes crossing diagonally through the block, for plants.
+    Cross,
+}
+
+/// A box in a [BlockModel], in model coordinates (see [MODEL_SIZE]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct ModelBox {
+    pub from: [u8; 3],
+    pub to: [u8; 3],
+    /// The box is only part of the model when the block connects to its neighbour
+    /// in this direction, e.g. the arms of a fence.
+    #[serde(default)]
+    pub connects: Option<Direction>,
+}
+
+impl ModelBox {
+    pub const FULL: ModelBox = ModelBox {
+        from: [0, 0, 0],
+        to: [MODEL_SIZE, MODEL_SIZE, MODEL_SIZE],
+        connects: None,
+    };
+
+    /// The lowest corner of the box, in blocks.
+    pub fn min(&self) -> glam::Vec3 {
+        glam::Vec3::from(self.from.map(|from| from as f32)) / MODEL_SIZE as f32
+    }
+
+    /// The highest corner of the box, in blocks.
+    pub fn max(&self) -> glam::Vec3 {
+        glam::Vec3::from(self.to.map(|to| to as f32)) / MODEL_SIZE as f32
+    }
+
+    /// Whether the box's face in the direction lies on the side of the block.
+    pub fn touches_side(&self, direction: Direction) -> bool {
+        let (x, y, z) = direction.offset();
+        let (axis, is_positive) = match (x, y, z) {
+            (0, 0, z) => (2, z > 0),
+            (0, y, _) => (1, y > 0),
+            (x, _, _) => (0, x > 0),
+        };
+
+        match is_positive {
+            true => self.to[axis] == MODEL_SIZE,
+            false => self.from[axis] == 0,
+        }
+    }
+
+    /// Whether the box's face on the block's side in the direction covers the face of `other`,
+    /// a box in the block next to it on that side, so that `other`'s face can't be seen.
+    pub fn covers_face_of(&self, other: &ModelBox, direction: Direction) -> bool {
+        let in_side_axes = match direction {
+            Direction::North | Direction::South => [0, 1],
+            Direction::East | Direction::West => [1, 2],
+            Direction::Up | Direction::Down => [0, 2],
+        };
+
+        self.touches_side(direction)
+            && other.touches_side(direction.opposite())
+            && in_side_axes
+                .iter()
+                .all(|&axis| self.from[axis] <= other.from[axis] && self.to[axis] >= other.to[axis])
+    }
+
+    /// Turns the box a quarter turn around the middle of the block, so that a box on the
+    /// north side ends up on the west side.
+    fn rotated_quarter_turn(self) -> ModelBox {
+        let rotate = |[x, y, z]: [u8; 3]| [z, y, MODEL_SIZE - x];
+        let (from, to) = (rotate(self.from), rotate(self.to));
+
+        ModelBox {
+            from: [from[0].min(to[0]), from[1], from[2].min(to[2])],
+            to: [from[0].max(to[0]), to[1], from[2].max(to[2])],
+            connects: self.connects.map(rotated_quarter_turn),
+        }
+    }
+
+    fn flipped_upside_down(self) -> ModelBox {
+        ModelBox {
+            from: [self.from[0], MODEL_SIZE - self.to[1], self.from[2]],
+            to: [self.to[0], MODEL_SIZE - self.from[1], self.to[2]],
+            connects: self.connects.map(|connects| match connects {
+                Direction::Up => Direction::Down,
+                Direction::Down => Direction::Up,
+                horizontal => horizontal,
+            }),
+        }
+    }
+}
+
+/// North turns to west, west to south, south to east and east back to north.
+fn rotated_quarter_turn(direction: Direction) -> Direction {
+    match direction {
+        Direction::North => Direction::West,
+        Direction::West => Direction::South,
+        Direction::South => Direction::East,
+        Direction::East => Direction::North,
+        vertical => vertical,
+    }
+}
+
+impl BlockModel {
+    /// The boxes of the model turned to face `facing` (only horizontal facings turn the model),
+    /// and flipped upside down if `half` is the top half.
+    /// Cubes are a single full box, and crosses have no boxes.
+    pub fn oriented_boxes(&self, facing: Direction, half: Half) -> Vec<ModelBox> {
+        let boxes = match self {
+            BlockModel::Cube => return vec![ModelBox::FULL],
+            BlockModel::Boxes(boxes) => boxes,
+            BlockModel::Cross => return Vec::new(),
+        };
+
+        let quarter_turns = match facing {
+            Direction::West => 1,
+            Direction::South => 2,
+            Direction::East => 3,
+            _ => 0,
+        };
+
+        boxes
+            .iter()
+            .map(|model_box| {
+                let mut model_box = *model_box;
+                for _ in 0..quarter_turns {
+                    model_box = model_box.rotated_quarter_turn();
+                }
+
+                match half {
+                    Half::Top => model_box.flipped_upside_down(),
+                    Half::Bottom => model_box,
+                }
+            })
+            .collect()
+    }
+}
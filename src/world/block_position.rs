@@ -29,9 +29,9 @@ impl BlockPosition {
 
     pub fn to_chunk_local_position(self) -> Self {
         BlockPosition::new(
-            self.x.abs() % CHUNK_BLOCK_WIDTH as i32,
-            self.y.abs() % CHUNK_BLOCK_HEIGHT as i32,
-            self.z.abs() % CHUNK_BLOCK_DEPTH as i32,
+            self.x.rem_euclid(CHUNK_BLOCK_WIDTH as i32),
+            self.y.rem_euclid(CHUNK_BLOCK_HEIGHT as i32),
+            self.z.rem_euclid(CHUNK_BLOCK_DEPTH as i32),
         )
     }
 
@@ -71,3 +71,35 @@ impl Sub for BlockPosition {
         BlockPosition::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_positions_are_in_the_chunk_below_zero() {
+        let position = BlockPosition::new(-1, -16, -17);
+
+        assert_eq!(
+            position.to_chunk_origin(),
+            BlockPosition::new(-16, -16, -32)
+        );
+        assert_eq!(
+            position.to_chunk_local_position(),
+            BlockPosition::new(15, 0, 15)
+        );
+    }
+
+    #[test]
+    fn local_position_is_relative_to_the_chunk_origin() {
+        for position in [
+            BlockPosition::new(0, 0, 0),
+            BlockPosition::new(15, 16, 31),
+            BlockPosition::new(-1, -15, -33),
+        ] {
+            let origin = position.to_chunk_origin();
+            let local = position.to_chunk_local_position();
+            assert_eq!(origin + local, position);
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use super::{
+    block::{Block, BlockStateError, Half, StateFlags, StateProperty, StateValue},
+    block_model::{BlockModel, ModelBox},
+    direction::Direction,
+};
+
+pub const AIR_IDENTIFIER: &str = "vox:air";
+
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    DuplicateIdentifier(String),
+    ReservedIdentifier(String),
+    ConflictingStateProperties(String, StateFlags, StateFlags),
+    UndeclaredFluidLevel(String),
+    UndeclaredFluidFalling(String),
+    TooManyBlockTypes,
+}
+
+impl From<std::io::Error> for BlockRegistryError {
+    fn from(err: std::io::Error) -> Self {
+        BlockRegistryError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for BlockRegistryError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        BlockRegistryError::Parse(err)
+    }
+}
+
+/// The properties shared by every [Block] with the same id.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockType {
+    pub identifier: String,
+    pub display_name: String,
+    #[serde(default = "BlockType::default_solid")]
+    pub solid: bool,
+    #[serde(default)]
+    pub transparent: bool,
+    /// Whether a transparent block type's texture is either fully see-through or fully opaque (e.g. leaves),
+    /// so it can be drawn along with the opaque blocks instead of being blended.
+    #[serde(default)]
+    pub cutout: bool,
+    #[serde(default)]
+    pub light_emission: u8,
+    #[serde(default)]
+    pub hardness: f32,
+    #[serde(default)]
+    pub state_properties: Vec<StateFlags>,
+    #[serde(default)]
+    pub textures: BlockFaceTextures,
+    #[serde(default)]
+    pub model: BlockModel,
+    /// Whether blocks of this type are picked for random ticks,
+    /// see [WorldTicker](super::world_ticker::WorldTicker).
+    #[serde(default)]
+    pub random_ticks: bool,
+    /// Set for liquids, which flow with the [FluidSimulator](super::fluid_simulator::FluidSimulator).
+    #[serde(default)]
+    pub fluid: Option<FluidProperties>,
+}
+
+/// How a liquid block type flows.
+///
+/// A liquid's level is stored in [StateFlags::FluidLevel], level 0 is a source block and every block
+/// the liquid flows sideways raises it by 1. Liquid falling from above is marked by
+/// [StateFlags::FluidFalling] instead, and spreads like a source once it lands.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FluidProperties {
+    /// How many blocks the liquid flows sideways from a source, at most 7.
+    pub flow_distance: u8,
+    /// How many ticks the liquid waits before flowing on after the blocks around it change.
+    pub tick_delay: u32,
+    /// Whether a new source forms where the liquid flows in between two sources,
+    /// on top of something solid or another source.
+    #[serde(default)]
+    pub forms_sources: bool,
+    /// The identifier of the block the liquid turns into where it touches a different liquid,
+    /// e.g. `vox:stone` for lava.
+    #[serde(default)]
+    pub hardens_into: Option<String>,
+}
+
+/// The names of the textures drawn on each face of a block type, e.g. `grass_top`.
+///
+/// Faces without a texture of their own use `all`, and if that is missing too,
+/// the name of the block type's identifier (`stone` for `vox:stone`).
+/// For blocks with an axis, the top and bottom textures are drawn on the faces along the axis.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BlockFaceTextures {
+    #[serde(default)]
+    pub all: Option<String>,
+    #[serde(default)]
+    pub top: Option<String>,
+    #[serde(default)]
+    pub side: Option<String>,
+    #[serde(default)]
+    pub bottom: Option<String>,
+}
+
+impl BlockType {
+    fn default_solid() -> bool {
+        true
+    }
+
+    fn air() -> Self {
+        BlockType {
+            identifier: AIR_IDENTIFIER.to_owned(),
+            display_name: "Air".to_owned(),
+            solid: false,
+            transparent: true,
+            cutout: false,
+            light_emission: 0,
+            hardness: 0.0,
+            state_properties: Vec::new(),
+            textures: BlockFaceTextures::default(),
+            model: BlockModel::Cube,
+            random_ticks: false,
+            fluid: None,
+        }
+    }
+
+    /// The texture names of the top, side and bottom faces.
+    pub fn face_textures(&self) -> [&str; 3] {
+        let name = self
+            .identifier
+            .split_once(':')
+            .map_or(self.identifier.as_str(), |(_, name)| name);
+        let all = self.textures.all.as_deref().unwrap_or(name);
+
+        [
+            self.textures.top.as_deref().unwrap_or(all),
+            self.textures.side.as_deref().unwrap_or(all),
+            self.textures.bottom.as_deref().unwrap_or(all),
+        ]
+    }
+
+    /// The state every block of this type starts with.
+    pub fn default_state(&self) -> u32 {
+        StateFlags::Solid.set(0, self.solid as u32)
+    }
+
+    pub fn declares(&self, flag: StateFlags) -> bool {
+        self.state_properties.contains(&flag)
+    }
+
+    /// Unpacks the value of the property from the block's state.
+    /// Returns `None` if this block type does not declare the property,
+    /// or the stored bits are not a valid value of it.
+    pub fn get<P: StateProperty>(&self, block: &Block) -> Option<P::Value> {
+        if !self.declares(P::FLAG) {
+            return None;
+        }
+
+        let bits = P::FLAG.get(block.state);
+        if bits >= P::FLAG.value_count() {
+            return None;
+        }
+
+        P::Value::from_bits(bits)
+    }
+
+    /// Returns the block with the property set to the value.
+    /// Fails if this block type does not declare the property, or the value is not valid for it.
+    pub fn with_state<P: StateProperty>(
+        &self,
+        block: Block,
+        value: P::Value,
+    ) -> Result<Block, BlockStateError> {
+        if !self.declares(P::FLAG) {
+            return Err(BlockStateError::UndeclaredProperty(P::FLAG));
+        }
+
+        let bits = value.to_bits();
+        if bits >= P::FLAG.value_count() {
+            return Err(BlockStateError::ValueOutOfRange(P::FLAG, bits));
+        }
+
+        Ok(Block {
+            state: P::FLAG.set(block.state, bits),
+            ..block
+        })
+    }
+
+    /// Whether the block completely hides the faces of the blocks around it.
+    /// Solidity comes from the block's state, which starts out as the block type's `solid`.
+    pub fn is_opaque(&self, block: &Block) -> bool {
+        block.is_solid() && !self.transparent && self.model == BlockModel::Cube
+    }
+
+    /// The boxes of the block's model, turned to the block's facing and flipped if it is a top half.
+    pub fn model_boxes(&self, block: &Block) -> Vec<ModelBox> {
+        let facing = self.horizontal_facing(block).or_else(|| self.facing(block));
+        let half = self.half(block);
+
+        self.model.oriented_boxes(
+            facing.unwrap_or(Direction::North),
+            half.unwrap_or(Half::Bottom),
+        )
+    }
+
+    /// The boxes of the block's model that hide the faces of the blocks next to them.
+    /// Transparent and non-solid blocks don't hide anything, and neither do boxes that are
+    /// only there when the block connects to a neighbour.
+    pub fn occluding_boxes(&self, block: &Block) -> Vec<ModelBox> {
+        if !block.is_solid() || self.transparent {
+            return Vec::new();
+        }
+
+        self.model_boxes(block)
+            .into_iter()
+            .filter(|model_box| model_box.connects.is_none())
+            .collect()
+    }
+
+    /// Whether the block's side in the direction hides the face of `model_box`,
+    /// a box of the block next to it on that side.
+    pub fn hides_face_of(&self, block: &Block, direction: Direction, model_box: &ModelBox) -> bool {
+        // Most blocks are cubes, they are checked without building their boxes.
+        if self.model == BlockModel::Cube {
+            return self.is_opaque(block) && ModelBox::FULL.covers_face_of(model_box, direction);
+        }
+
+        self.occluding_boxes(block)
+            .iter()
+            .any(|occluding_box| occluding_box.covers_face_of(model_box, direction))
+    }
+
+    /// Whether the block's side in the direction is completely covered by an opaque face.
+    pub fn has_full_side(&self, block: &Block, direction: Direction) -> bool {
+        self.hides_face_of(block, direction, &ModelBox::FULL)
+    }
+
+    /// Whether this block type has to be blended with what is behind it when drawn.
+    pub fn is_translucent(&self) -> bool {
+        self.transparent && !self.cutout
+    }
+}
+
+/// Maps block identifiers (e.g. `vox:stone`) to the numeric ids stored in [Block::id]
+/// and holds the [BlockType] for every id.
+///
+/// Id 0 is always reserved for `vox:air`, the remaining ids are handed out in the order
+/// the block types are registered.
+pub struct BlockRegistry {
+    block_types: Vec<BlockType>,
+    ids_by_identifier: HashMap<String, u16>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        BlockRegistry {
+            block_types: vec![BlockType::air()],
+            ids_by_identifier: HashMap::from([(AIR_IDENTIFIER.to_owned(), Block::AIR_ID)]),
+        }
+    }
+}
+
+impl BlockRegistry {
+    /// Loads the registry from a RON file containing a list of [BlockType]s.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BlockRegistryError> {
+        BlockRegistry::from_ron(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, BlockRegistryError> {
+        let block_types: Vec<BlockType> = ron::from_str(source)?;
+
+        let mut registry = BlockRegistry::default();
+        for block_type in block_types {
+            registry.register(block_type)?;
+        }
+
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, block_type: BlockType) -> Result<u16, BlockRegistryError> {
+        if block_type.identifier == AIR_IDENTIFIER {
+            return Err(BlockRegistryError::ReservedIdentifier(
+                block_type.identifier,
+            ));
+        }
+
+        if self.ids_by_identifier.contains_key(&block_type.identifier) {
+            return Err(BlockRegistryError::DuplicateIdentifier(
+                block_type.identifier,
+            ));
+        }
+
+        for (i, flag) in block_type.state_properties.iter().enumerate() {
+            for other_flag in &block_type.state_properties[i + 1..] {
+                if flag.overlaps(other_flag) {
+                    return Err(BlockRegistryError::ConflictingStateProperties(
+                        block_type.identifier,
+                        *flag,
+                        *other_flag,
+                    ));
+                }
+            }
+        }
+
+        if block_type.fluid.is_some() && !block_type.declares(StateFlags::FluidLevel) {
+            return Err(BlockRegistryError::UndeclaredFluidLevel(
+                block_type.identifier,
+            ));
+        }
+
+        if block_type.fluid.is_some() && !block_type.declares(StateFlags::FluidFalling) {
+            return Err(BlockRegistryError::UndeclaredFluidFalling(
+                block_type.identifier,
+            ));
+        }
+
+        let id = u16::try_from(self.block_types.len())
+            .map_err(|_| BlockRegistryError::TooManyBlockTypes)?;
+        self.ids_by_identifier
+            .insert(block_type.identifier.clone(), id);
+        self.block_types.push(block_type);
+
+        Ok(id)
+    }
+
+    pub fn id(&self, identifier: &str) -> Option<u16> {
+        self.ids_by_identifier.get(identifier).copied()
+    }
+
+    /// Gets the default state [Block] of the block type with the identifier.
+    pub fn block(&self, identifier: &str) -> Option<Block> {
+        self.id(identifier).map(|id| Block {
+            id,
+            state: self.block_types[id as usize].default_state(),
+        })
+    }
+
+    pub fn block_type(&self, id: u16) -> Option<&BlockType> {
+        self.block_types.get(id as usize)
+    }
+
+    /// Gets the [BlockType] of the block.
+    /// Blocks with an id that was never registered are treated as air.
+    pub fn block_type_of(&self, block: &Block) -> &BlockType {
+        self.block_type(block.id)
+            .unwrap_or(&self.block_types[Block::AIR_ID as usize])
+    }
+
+    pub fn block_types(&self) -> &[BlockType] {
+        self.block_types.as_ref()
+    }
+}
+
+/// A registry for tests of `test:stone` followed by the extra block definitions,
+/// so `test:stone` has the same id in every test.
+#[cfg(test)]
+pub fn test_registry(extra_blocks: &str) -> BlockRegistry {
+    BlockRegistry::from_ron(&format!(
+        r#"#![enable(implicit_some)]
+        [
+            (identifier: "test:stone", display_name: "Stone"),
+            {extra_blocks}
+        ]"#
+    ))
+    .unwrap()
+}
+
+/// The game's own blocks, for tests of world generation which places them by identifier.
+#[cfg(test)]
+pub fn game_registry() -> BlockRegistry {
+    BlockRegistry::from_ron(include_str!("../../assets/blocks.ron")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::{Axis, AxisProperty};
+
+    #[test]
+    fn get_only_reads_declared_properties() {
+        let registry = test_registry(
+            r#"(identifier: "test:log", display_name: "Log", state_properties: [Axis]),"#,
+        );
+        let stone = registry.block("test:stone").unwrap();
+        let log = registry.block("test:log").unwrap();
+        let stone_type = registry.block_type_of(&stone);
+        let log_type = registry.block_type_of(&log);
+
+        assert_eq!(log_type.axis(&log), Some(Axis::X));
+        let log = log_type.with_state::<AxisProperty>(log, Axis::Z).unwrap();
+        assert_eq!(log_type.axis(&log), Some(Axis::Z));
+
+        assert_eq!(stone_type.axis(&stone), None);
+        assert!(stone_type
+            .with_state::<AxisProperty>(stone, Axis::Z)
+            .is_err());
+        // Bits of an undeclared property set by hand still aren't read.
+        let stone = Block {
+            state: StateFlags::Axis.set(stone.state, Axis::Z as u32),
+            ..stone
+        };
+        assert_eq!(stone_type.axis(&stone), None);
+    }
+
+    #[test]
+    fn liquids_declare_their_level_and_falling_flag() {
+        let liquid = |state_properties: &str| {
+            BlockRegistry::from_ron(&format!(
+                r#"#![enable(implicit_some)]
+                [
+                    (
+                        identifier: "test:water",
+                        display_name: "Water",
+                        state_properties: [{state_properties}],
+                        fluid: (flow_distance: 7, tick_delay: 5),
+                    ),
+                ]"#
+            ))
+        };
+
+        assert!(liquid("FluidLevel, FluidFalling").is_ok());
+        assert!(matches!(
+            liquid("FluidFalling"),
+            Err(BlockRegistryError::UndeclaredFluidLevel(_))
+        ));
+        assert!(matches!(
+            liquid("FluidLevel"),
+            Err(BlockRegistryError::UndeclaredFluidFalling(_))
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+use super::block::Block;
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// Palette compressed storage for a fixed number of blocks.
+///
+/// Each distinct [Block] is stored once in a palette, and every position stores
+/// a bit packed index into that palette. Indices never straddle two words,
+/// so a word holds `64 / bits_per_index` indices.
+/// Storage where every position holds the same block skips the index data entirely.
+#[derive(Clone, Debug)]
+pub enum BlockStorage {
+    Single {
+        block: Block,
+        len: usize,
+    },
+    Paletted {
+        palette: Vec<Block>,
+        bits_per_index: usize,
+        data: Vec<u64>,
+        len: usize,
+    },
+}
+
+impl BlockStorage {
+    /// Creates storage of `len` positions all holding `block`.
+    pub fn filled(block: Block, len: usize) -> Self {
+        BlockStorage::Single { block, len }
+    }
+
+    /// Creates paletted storage from its raw parts, as written by a previous [BlockStorage].
+    /// Returns `None` if the parts do not describe valid storage of `len` positions.
+    pub fn from_parts(
+        palette: Vec<Block>,
+        bits_per_index: usize,
+        data: Vec<u64>,
+        len: usize,
+    ) -> Option<Self> {
+        if palette.is_empty()
+            || !(1..=BITS_PER_WORD).contains(&bits_per_index)
+            || data.len() != BlockStorage::words_needed(bits_per_index, len)
+        {
+            return None;
+        }
+
+        let all_indices_in_palette = (0..len)
+            .all(|index| BlockStorage::read_index(&data, bits_per_index, index) < palette.len());
+        if !all_indices_in_palette {
+            return None;
+        }
+
+        Some(BlockStorage::Paletted {
+            palette,
+            bits_per_index,
+            data,
+            len,
+        })
+    }
+
+    pub fn palette(&self) -> &[Block] {
+        match self {
+            BlockStorage::Single { block, .. } => std::slice::from_ref(block),
+            BlockStorage::Paletted { palette, .. } => palette,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Block {
+        match self {
+            BlockStorage::Single { block, .. } => *block,
+            BlockStorage::Paletted {
+                palette,
+                bits_per_index,
+                data,
+                ..
+            } => palette[BlockStorage::read_index(data, *bits_per_index, index)],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, block: Block) {
+        // Drops the blocks no longer in use before widening the indices to fit another.
+        if self.is_palette_full() && !self.palette().contains(&block) {
+            self.compact();
+        }
+
+        self.set_without_compacting(index, block);
+    }
+
+    fn set_without_compacting(&mut self, index: usize, block: Block) {
+        if let BlockStorage::Single {
+            block: existing,
+            len,
+        } = *self
+        {
+            if existing == block {
+                return;
+            }
+
+            *self = BlockStorage::Paletted {
+                palette: vec![existing],
+                bits_per_index: 1,
+                data: vec![0; BlockStorage::words_needed(1, len)],
+                len,
+            };
+        }
+
+        let palette_index = match self.palette().iter().position(|b| *b == block) {
+            Some(palette_index) => palette_index,
+            None => self.push_to_palette(block),
+        };
+
+        if let BlockStorage::Paletted {
+            bits_per_index,
+            data,
+            ..
+        } = self
+        {
+            BlockStorage::write_index(data, *bits_per_index, index, palette_index);
+        }
+    }
+
+    /// Rebuilds the palette from the blocks that are actually in use,
+    /// collapsing back to a single block when only one remains.
+    /// [BlockStorage::set] compacts a full palette before growing it, so removed blocks don't take up space.
+    pub fn compact(&mut self) {
+        let BlockStorage::Paletted { len, .. } = *self else {
+            return;
+        };
+
+        let mut compacted = BlockStorage::filled(self.get(0), len);
+        for index in 1..len {
+            compacted.set_without_compacting(index, self.get(index));
+        }
+
+        *self = compacted;
+    }
+
+    fn is_palette_full(&self) -> bool {
+        match self {
+            BlockStorage::Single { .. } => false,
+            BlockStorage::Paletted {
+                palette,
+                bits_per_index,
+                ..
+            } => palette.len() >= 1 << *bits_per_index,
+        }
+    }
+
+    fn push_to_palette(&mut self, block: Block) -> usize {
+        let BlockStorage::Paletted {
+            palette,
+            bits_per_index,
+            data,
+            len,
+        } = self
+        else {
+            unreachable!("single block storage is always converted before growing its palette");
+        };
+
+        palette.push(block);
+        if palette.len() > 1 << *bits_per_index {
+            let new_bits_per_index = *bits_per_index + 1;
+            let mut new_data = vec![0; BlockStorage::words_needed(new_bits_per_index, *len)];
+            for index in 0..*len {
+                let palette_index = BlockStorage::read_index(data, *bits_per_index, index);
+                BlockStorage::write_index(&mut new_data, new_bits_per_index, index, palette_index);
+            }
+
+            *bits_per_index = new_bits_per_index;
+            *data = new_data;
+        }
+
+        palette.len() - 1
+    }
+
+    #[inline]
+    fn words_needed(bits_per_index: usize, len: usize) -> usize {
+        len.div_ceil(BITS_PER_WORD / bits_per_index)
+    }
+
+    #[inline]
+    fn read_index(data: &[u64], bits_per_index: usize, index: usize) -> usize {
+        let indices_per_word = BITS_PER_WORD / bits_per_index;
+        let word = data[index / indices_per_word];
+        let shift = (index % indices_per_word) * bits_per_index;
+
+        ((word >> shift) & ((1 << bits_per_index) - 1)) as usize
+    }
+
+    #[inline]
+    fn write_index(data: &mut [u64], bits_per_index: usize, index: usize, palette_index: usize) {
+        let indices_per_word = BITS_PER_WORD / bits_per_index;
+        let word = &mut data[index / indices_per_word];
+        let shift = (index % indices_per_word) * bits_per_index;
+        let mask = ((1u64 << bits_per_index) - 1) << shift;
+
+        *word = (*word & !mask) | ((palette_index as u64) << shift);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEN: usize = 4096;
+
+    fn block(id: u16) -> Block {
+        Block { id, state: 0 }
+    }
+
+    fn bits_per_index(storage: &BlockStorage) -> usize {
+        match storage {
+            BlockStorage::Single { .. } => 0,
+            BlockStorage::Paletted { bits_per_index, .. } => *bits_per_index,
+        }
+    }
+
+    #[test]
+    fn setting_a_different_block_promotes_single_to_paletted() {
+        let mut storage = BlockStorage::filled(block(1), LEN);
+        storage.set(10, block(1));
+        assert!(matches!(storage, BlockStorage::Single { .. }));
+
+        storage.set(10, block(2));
+        assert!(matches!(storage, BlockStorage::Paletted { .. }));
+        assert_eq!(storage.palette(), &[block(1), block(2)]);
+        assert_eq!(bits_per_index(&storage), 1);
+        assert_eq!(storage.get(10), block(2));
+        assert!((0..LEN)
+            .filter(|index| *index != 10)
+            .all(|index| storage.get(index) == block(1)));
+    }
+
+    #[test]
+    fn palette_grows_past_each_bit_width() {
+        let mut storage = BlockStorage::filled(block(0), LEN);
+        for id in 1..=256u16 {
+            storage.set(id as usize, block(id));
+
+            let palette_len = id as usize + 1;
+            let expected_bits = usize::BITS - (palette_len - 1).leading_zeros();
+            assert_eq!(bits_per_index(&storage), expected_bits.max(1) as usize);
+        }
+
+        assert_eq!(bits_per_index(&storage), 9);
+        for index in 0..LEN {
+            let expected_id = if index <= 256 { index as u16 } else { 0 };
+            assert_eq!(storage.get(index), block(expected_id));
+        }
+    }
+
+    #[test]
+    fn compact_drops_unused_blocks() {
+        let mut storage = BlockStorage::filled(block(0), LEN);
+        for id in 1..=4u16 {
+            storage.set(id as usize, block(id));
+        }
+        assert_eq!(bits_per_index(&storage), 3);
+
+        storage.set(1, block(0));
+        storage.set(2, block(0));
+        storage.compact();
+        assert_eq!(storage.palette(), &[block(0), block(3), block(4)]);
+        assert_eq!(bits_per_index(&storage), 2);
+        assert_eq!(storage.get(3), block(3));
+        assert_eq!(storage.get(4), block(4));
+
+        storage.set(3, block(0));
+        storage.set(4, block(0));
+        storage.compact();
+        assert!(matches!(storage, BlockStorage::Single { .. }));
+        assert_eq!(storage.get(4), block(0));
+    }
+
+    #[test]
+    fn set_compacts_a_full_palette_before_growing_it() {
+        let mut storage = BlockStorage::filled(block(0), LEN);
+        storage.set(0, block(1));
+        assert_eq!(storage.palette(), &[block(0), block(1)]);
+
+        // Block 0 is no longer used anywhere, so block 2 takes its place instead of widening the indices.
+        for index in 1..LEN {
+            storage.set(index, block(1));
+        }
+        storage.set(0, block(2));
+        assert_eq!(bits_per_index(&storage), 1);
+        assert_eq!(storage.palette(), &[block(1), block(2)]);
+        assert_eq!(storage.get(0), block(2));
+        assert_eq!(storage.get(1), block(1));
+    }
+}
@@ -0,0 +1,363 @@
+use std::f64::consts::PI;
+
+use glam::DVec3;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::{
+    block::Block,
+    block_position::BlockPosition,
+    block_storage::BlockStorage,
+    chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    seeded_random::SeededRandom,
+};
+
+const CAVE_NOISE_FREQUENCY: f64 = 1.0 / 48.0;
+const CAVE_NOISE_OCTAVES: usize = 2;
+/// Blocks where the cave noise is above this are carved out.
+const CAVE_NOISE_THRESHOLD: f64 = 0.45;
+/// Squashes the cave noise vertically so caves are wider than they are tall.
+const CAVE_NOISE_VERTICAL_SCALE: f64 = 2.0;
+/// Noise caves stay this far below the surface so they don't riddle the ground with holes.
+const CAVE_NOISE_SURFACE_MARGIN: i32 = 8;
+
+/// The chance that a chunk is the start of a worm tunnel.
+const WORM_CHANCE_PER_CHUNK: f64 = 0.05;
+const WORM_STEPS: i32 = 64;
+const WORM_MIN_RADIUS: f64 = 1.2;
+const WORM_MAX_RADIUS: f64 = 3.0;
+/// How far a worm can carve from where it starts, it moves a block every step.
+const WORM_REACH: f64 = WORM_STEPS as f64 + WORM_MAX_RADIUS;
+/// How many chunks away from its starting chunk a worm can reach along an axis.
+/// Every chunk within [WORM_REACH] of the chunk being carved has to be checked for worms passing through.
+const WORM_CHUNK_RANGE: i32 = (WORM_REACH as usize).div_ceil(CHUNK_BLOCK_WIDTH) as i32;
+
+/// Underwater columns are never carved within this many blocks of the surface,
+/// so caves don't open up into the bottom of oceans.
+const UNDERWATER_SURFACE_MARGIN: i32 = 4;
+
+const WORM_SALT: u64 = 0x5707_C4BE;
+
+/// Carves caves into generated terrain.
+///
+/// Carving a chunk only depends on the seed and the chunk's position. Worm tunnels are
+/// simulated from the chunk they start in, and every chunk a worm could reach replays it
+/// and carves the part that falls inside of it, so tunnels line up across chunk borders
+/// no matter which chunk is generated first.
+pub struct CaveCarver {
+    seed: u64,
+    sea_level: i32,
+    cave_noise: Fbm<Perlin>,
+    water: Block,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u64, cave_noise_seed: u32, sea_level: i32, water: Block) -> Self {
+        CaveCarver {
+            seed,
+            sea_level,
+            cave_noise: Fbm::<Perlin>::new(cave_noise_seed)
+                .set_octaves(CAVE_NOISE_OCTAVES)
+                .set_frequency(CAVE_NOISE_FREQUENCY),
+            water,
+        }
+    }
+
+    /// Carves the caves passing through the chunk.
+    /// `surface_heights` is the terrain surface height of every column in the chunk,
+    /// indexed by `x + z * CHUNK_BLOCK_WIDTH`.
+    pub fn carve_chunk(&self, chunk: &mut Chunk, surface_heights: &[i32]) {
+        let is_all_air =
+            matches!(chunk.blocks(), BlockStorage::Single { block, .. } if block.is_air());
+        if is_all_air {
+            return;
+        }
+
+        self.carve_noise_caves(chunk, surface_heights);
+        self.carve_worm_tunnels(chunk, surface_heights);
+    }
+
+    fn carve_noise_caves(&self, chunk: &mut Chunk, surface_heights: &[i32]) {
+        let origin_position = chunk.origin_position();
+
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                let surface_height = surface_heights[(x + z * CHUNK_BLOCK_WIDTH as i32) as usize];
+
+                for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                    let position = origin_position.offset(x, y, z);
+                    if position.y > surface_height - CAVE_NOISE_SURFACE_MARGIN {
+                        break;
+                    }
+
+                    let noise = self.cave_noise.get([
+                        position.x as f64,
+                        position.y as f64 * CAVE_NOISE_VERTICAL_SCALE,
+                        position.z as f64,
+                    ]);
+
+                    if noise > CAVE_NOISE_THRESHOLD {
+                        self.carve_block(chunk, position, surface_height);
+                    }
+                }
+            }
+        }
+    }
+
+    fn carve_worm_tunnels(&self, chunk: &mut Chunk, surface_heights: &[i32]) {
+        let origin_position = chunk.origin_position();
+        let chunk_min = origin_position.to_vec3().as_dvec3();
+        let chunk_max = chunk_min
+            + DVec3::new(
+                CHUNK_BLOCK_WIDTH as f64,
+                CHUNK_BLOCK_HEIGHT as f64,
+                CHUNK_BLOCK_DEPTH as f64,
+            );
+
+        for chunk_x in -WORM_CHUNK_RANGE..=WORM_CHUNK_RANGE {
+            for chunk_y in -WORM_CHUNK_RANGE..=WORM_CHUNK_RANGE {
+                for chunk_z in -WORM_CHUNK_RANGE..=WORM_CHUNK_RANGE {
+                    if !CaveCarver::is_within_worm_reach(chunk_x, chunk_y, chunk_z) {
+                        continue;
+                    }
+
+                    let start_chunk_origin = origin_position.offset(
+                        chunk_x * CHUNK_BLOCK_WIDTH as i32,
+                        chunk_y * CHUNK_BLOCK_HEIGHT as i32,
+                        chunk_z * CHUNK_BLOCK_DEPTH as i32,
+                    );
+
+                    let mut random =
+                        SeededRandom::at_position(self.seed, start_chunk_origin, WORM_SALT);
+                    if random.next_f64() >= WORM_CHANCE_PER_CHUNK {
+                        continue;
+                    }
+
+                    self.carve_worm(
+                        chunk,
+                        surface_heights,
+                        start_chunk_origin,
+                        &mut random,
+                        (chunk_min, chunk_max),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Whether a worm starting in the chunk this many chunks away can reach the chunk being carved,
+    /// going by the gap between the closest sides of the two chunks.
+    fn is_within_worm_reach(chunk_x: i32, chunk_y: i32, chunk_z: i32) -> bool {
+        let gap =
+            |chunks: i32, chunk_size: usize| ((chunks.abs() - 1).max(0) * chunk_size as i32) as f64;
+        let gap = DVec3::new(
+            gap(chunk_x, CHUNK_BLOCK_WIDTH),
+            gap(chunk_y, CHUNK_BLOCK_HEIGHT),
+            gap(chunk_z, CHUNK_BLOCK_DEPTH),
+        );
+
+        gap.length_squared() <= WORM_REACH * WORM_REACH
+    }
+
+    /// Walks a worm from its starting chunk, carving every sphere along its path
+    /// that overlaps the chunk being generated.
+    fn carve_worm(
+        &self,
+        chunk: &mut Chunk,
+        surface_heights: &[i32],
+        start_chunk_origin: BlockPosition,
+        random: &mut SeededRandom,
+        (chunk_min, chunk_max): (DVec3, DVec3),
+    ) {
+        CaveCarver::walk_worm(start_chunk_origin, random, |position, radius| {
+            let sphere_min = position - DVec3::splat(radius);
+            let sphere_max = position + DVec3::splat(radius);
+            if sphere_max.cmplt(chunk_min).any() || sphere_min.cmpge(chunk_max).any() {
+                return;
+            }
+
+            self.carve_sphere(chunk, surface_heights, position, radius);
+        });
+    }
+
+    /// Walks a worm from its starting chunk, calling `carve` with the center and radius
+    /// of every sphere along its path.
+    fn walk_worm(
+        start_chunk_origin: BlockPosition,
+        random: &mut SeededRandom,
+        mut carve: impl FnMut(DVec3, f64),
+    ) {
+        let mut position = start_chunk_origin.to_vec3().as_dvec3()
+            + DVec3::new(
+                random.range_f64(0.0, CHUNK_BLOCK_WIDTH as f64),
+                random.range_f64(0.0, CHUNK_BLOCK_HEIGHT as f64),
+                random.range_f64(0.0, CHUNK_BLOCK_DEPTH as f64),
+            );
+        let mut yaw = random.range_f64(0.0, 2.0 * PI);
+        let mut pitch = random.range_f64(-0.25, 0.25);
+        let mut yaw_change = 0.0;
+        let mut pitch_change = 0.0;
+        let radius_scale = random.range_f64(WORM_MIN_RADIUS, WORM_MAX_RADIUS);
+
+        for step in 0..WORM_STEPS {
+            let radius = (1.0 + (step as f64 * PI / WORM_STEPS as f64).sin()) * radius_scale * 0.5;
+
+            position += DVec3::new(
+                yaw.cos() * pitch.cos(),
+                pitch.sin(),
+                yaw.sin() * pitch.cos(),
+            );
+
+            // Draw the random numbers before carving so the path never depends
+            // on which chunk is being carved.
+            yaw += yaw_change * 0.1;
+            pitch = pitch * 0.7 + pitch_change * 0.1;
+            yaw_change = yaw_change * 0.75 + random.range_f64(-2.0, 2.0);
+            pitch_change = pitch_change * 0.9 + random.range_f64(-1.0, 1.0);
+
+            carve(position, radius);
+        }
+    }
+
+    fn carve_sphere(&self, chunk: &mut Chunk, surface_heights: &[i32], center: DVec3, radius: f64) {
+        let origin_position = chunk.origin_position();
+        let min = (center - DVec3::splat(radius)).floor().as_ivec3();
+        let max = (center + DVec3::splat(radius)).ceil().as_ivec3();
+
+        for x in
+            min.x.max(origin_position.x)..max.x.min(origin_position.x + CHUNK_BLOCK_WIDTH as i32)
+        {
+            for y in min.y.max(origin_position.y)
+                ..max.y.min(origin_position.y + CHUNK_BLOCK_HEIGHT as i32)
+            {
+                for z in min.z.max(origin_position.z)
+                    ..max.z.min(origin_position.z + CHUNK_BLOCK_DEPTH as i32)
+                {
+                    let block_center = DVec3::new(x as f64, y as f64, z as f64) + 0.5;
+                    if block_center.distance_squared(center) > radius * radius {
+                        continue;
+                    }
+
+                    let local_x = x - origin_position.x;
+                    let local_z = z - origin_position.z;
+                    let surface_height =
+                        surface_heights[(local_x + local_z * CHUNK_BLOCK_WIDTH as i32) as usize];
+                    self.carve_block(chunk, BlockPosition::new(x, y, z), surface_height);
+                }
+            }
+        }
+    }
+
+    fn carve_block(&self, chunk: &mut Chunk, position: BlockPosition, surface_height: i32) {
+        // The bottom of the world is never carved so there is always a floor.
+        if position.y <= 0 {
+            return;
+        }
+
+        let is_underwater = surface_height < self.sea_level;
+        if is_underwater && position.y > surface_height - UNDERWATER_SURFACE_MARGIN {
+            return;
+        }
+
+        match chunk.get_block_at_position(position) {
+            Ok(Some(block)) if block != self.water => {
+                chunk.set_block_at_position(position, Block::default())
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::world::chunk::CHUNK_COLUMN_COUNT;
+
+    const STONE: Block = Block { id: 1, state: 0 };
+    const WATER: Block = Block { id: 2, state: 0 };
+
+    /// Carves the worm tunnels into stone chunks, returning every carved position.
+    /// The surface is far above the chunks so every block a worm reaches is carved.
+    fn carved_positions(
+        cave_carver: &CaveCarver,
+        chunk_origins: &[BlockPosition],
+    ) -> HashSet<BlockPosition> {
+        let surface_heights = vec![i32::MAX; CHUNK_COLUMN_COUNT];
+        let mut carved_positions = HashSet::new();
+
+        for chunk_origin in chunk_origins {
+            let mut chunk = Chunk::filled(*chunk_origin, STONE);
+            cave_carver.carve_worm_tunnels(&mut chunk, &surface_heights);
+
+            for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+                for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                    for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                        let position = chunk_origin.offset(x, y, z);
+                        if chunk.get_block_at_position(position).unwrap().is_none() {
+                            carved_positions.insert(position);
+                        }
+                    }
+                }
+            }
+        }
+
+        carved_positions
+    }
+
+    #[test]
+    fn worm_tunnels_line_up_across_chunk_borders() {
+        let cave_carver = CaveCarver::new(3, 3, 0, WATER);
+        let chunk_origins: Vec<BlockPosition> = (0..4)
+            .flat_map(|x| (0..4).map(move |z| BlockPosition::new(x * 16, 256, z * 16)))
+            .collect();
+
+        // Walks every worm that can reach the chunks once, without splitting it up by chunk.
+        let region_min = DVec3::new(0.0, 256.0, 0.0);
+        let region_max = DVec3::new(64.0, 272.0, 64.0);
+        let mut expected_positions = HashSet::new();
+        for chunk_x in -WORM_CHUNK_RANGE..4 + WORM_CHUNK_RANGE {
+            for chunk_y in -WORM_CHUNK_RANGE..=WORM_CHUNK_RANGE {
+                for chunk_z in -WORM_CHUNK_RANGE..4 + WORM_CHUNK_RANGE {
+                    let start_chunk_origin =
+                        BlockPosition::new(chunk_x * 16, 256 + chunk_y * 16, chunk_z * 16);
+                    let mut random =
+                        SeededRandom::at_position(cave_carver.seed, start_chunk_origin, WORM_SALT);
+                    if random.next_f64() >= WORM_CHANCE_PER_CHUNK {
+                        continue;
+                    }
+
+                    CaveCarver::walk_worm(start_chunk_origin, &mut random, |center, radius| {
+                        let min = (center - DVec3::splat(radius)).floor().as_ivec3();
+                        let max = (center + DVec3::splat(radius)).ceil().as_ivec3();
+                        for x in min.x..max.x {
+                            for y in min.y..max.y {
+                                for z in min.z..max.z {
+                                    let block_center =
+                                        DVec3::new(x as f64, y as f64, z as f64) + 0.5;
+                                    let is_in_region = block_center.cmpge(region_min).all()
+                                        && block_center.cmplt(region_max).all();
+                                    if is_in_region
+                                        && block_center.distance_squared(center) <= radius * radius
+                                    {
+                                        expected_positions.insert(BlockPosition::new(x, y, z));
+                                    }
+                                }
+                            }
+                        }
+                    });
+                }
+            }
+        }
+
+        let carved_positions = carved_positions(&cave_carver, &chunk_origins);
+        assert!(!carved_positions.is_empty());
+        assert_eq!(carved_positions, expected_positions);
+
+        // At least one tunnel crosses from one chunk into the next.
+        assert!(carved_positions.iter().any(|position| {
+            (position.x % 16 == 15 && carved_positions.contains(&position.offset_x(1)))
+                || (position.z % 16 == 15 && carved_positions.contains(&position.offset_z(1)))
+        }));
+    }
+}
@@ -2,36 +2,75 @@
 
 
 
+use std::collections::HashMap;
+
 use super::{
+    biome::Biome,
     block::Block,
     block_position::{BlockPosition},
+    block_storage::BlockStorage,
     block_position_range::{BlockPositionRange, RangeType},
     direction::Direction,
+    light::{LightArray, LightChannel},
 };
 
 pub const CHUNK_BLOCK_WIDTH: usize = 16;
 pub const CHUNK_BLOCK_HEIGHT: usize = 16;
 pub const CHUNK_BLOCK_DEPTH: usize = 16;
-const CHUNK_SIZE: usize = CHUNK_BLOCK_WIDTH * CHUNK_BLOCK_HEIGHT * CHUNK_BLOCK_DEPTH;
+pub const CHUNK_SIZE: usize = CHUNK_BLOCK_WIDTH * CHUNK_BLOCK_HEIGHT * CHUNK_BLOCK_DEPTH;
+pub const CHUNK_COLUMN_COUNT: usize = CHUNK_BLOCK_WIDTH * CHUNK_BLOCK_DEPTH;
 #[derive(Debug)]
 pub enum ChunkAccessorError {
     PositionNotWithinChunk(BlockPosition),
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Chunk {
     origin_position: BlockPosition,
-    pub blocks: [Block; CHUNK_SIZE],
+    blocks: BlockStorage,
+    biomes: [Biome; CHUNK_COLUMN_COUNT],
+    /// The block and sky light of every block, indexed like the blocks.
+    /// They aren't saved, the [World](super::world::World) lights chunks again when they are loaded.
+    block_light: LightArray,
+    sky_light: LightArray,
+    /// The tick each block with a scheduled tick is due in, see [WorldTicker](super::world_ticker::WorldTicker).
+    scheduled_ticks: HashMap<BlockPosition, u64>,
 }
 
 impl Chunk {
     pub fn new(origin_position: BlockPosition) -> Self {
+        Chunk::filled(origin_position, Block { id: 1, state: 0 })
+    }
+
+    /// Creates a chunk filled with air.
+    pub fn empty(origin_position: BlockPosition) -> Self {
+        Chunk::filled(origin_position, Block::default())
+    }
+
+    pub fn filled(origin_position: BlockPosition, block: Block) -> Self {
+        Chunk::with_blocks(origin_position, BlockStorage::filled(block, CHUNK_SIZE))
+    }
+
+    pub fn with_blocks(origin_position: BlockPosition, blocks: BlockStorage) -> Self {
         Chunk {
             origin_position,
-            blocks: [Block { id: 1, state: 0 }; CHUNK_SIZE],
+            blocks,
+            biomes: [Biome::default(); CHUNK_COLUMN_COUNT],
+            block_light: LightArray::new(CHUNK_SIZE),
+            sky_light: LightArray::new(CHUNK_SIZE),
+            scheduled_ticks: HashMap::new(),
         }
     }
 
+    pub fn blocks(&self) -> &BlockStorage {
+        &self.blocks
+    }
+
+    /// Shrinks the block palette down to the blocks still present in the chunk.
+    pub fn compact(&mut self) {
+        self.blocks.compact()
+    }
+
     pub fn origin_position(&self) -> BlockPosition {
         self.origin_position
     }
@@ -48,19 +87,29 @@ impl Chunk {
     }
 
     #[inline]
-    fn world_block_position(&self, block_index: usize) -> BlockPosition {
-        Chunk::local_block_position(block_index) + self.origin_position
-    }
-
-    pub fn set_block_at_position(&mut self, world_position: BlockPosition, block: Block) {
+    fn block_index(world_position: BlockPosition) -> usize {
         let chunk_local_position = world_position.to_chunk_local_position(); // 0, 0, 0
 
-        let position_idx = (chunk_local_position.x
+        (chunk_local_position.x
             + (chunk_local_position.y * CHUNK_BLOCK_WIDTH as i32)
             + (chunk_local_position.z * CHUNK_BLOCK_WIDTH as i32 * CHUNK_BLOCK_HEIGHT as i32))
-            as usize;
+            as usize
+    }
+
+    #[inline]
+    fn column_index(world_position: BlockPosition) -> usize {
+        let chunk_local_position = world_position.to_chunk_local_position();
+
+        (chunk_local_position.x + (chunk_local_position.z * CHUNK_BLOCK_WIDTH as i32)) as usize
+    }
+
+    #[inline]
+    fn world_block_position(&self, block_index: usize) -> BlockPosition {
+        Chunk::local_block_position(block_index) + self.origin_position
+    }
 
-        self.blocks[position_idx] = block;
+    pub fn set_block_at_position(&mut self, world_position: BlockPosition, block: Block) {
+        self.blocks.set(Chunk::block_index(world_position), block);
     }
 
     /// Gets the block at the position, if one exists.
@@ -75,14 +124,7 @@ impl Chunk {
             return Err(ChunkAccessorError::PositionNotWithinChunk(world_position));
         }
 
-        let chunk_local_position = world_position.to_chunk_local_position(); // 0, 0, 0
-
-        let position_idx = (chunk_local_position.x
-            + (chunk_local_position.y * CHUNK_BLOCK_WIDTH as i32)
-            + (chunk_local_position.z * CHUNK_BLOCK_WIDTH as i32 * CHUNK_BLOCK_HEIGHT as i32))
-            as usize;
-
-        let block = self.blocks[position_idx];
+        let block = self.blocks.get(Chunk::block_index(world_position));
         if block.is_air() {
             return Ok(None);
         }
@@ -90,6 +132,87 @@ impl Chunk {
         Ok(Some(block))
     }
 
+    /// Gets the light level of the channel at the position.
+    pub fn get_light_at_position(
+        &self,
+        world_position: BlockPosition,
+        channel: LightChannel,
+    ) -> Result<u8, ChunkAccessorError> {
+        if !self.is_world_position_within(world_position) {
+            return Err(ChunkAccessorError::PositionNotWithinChunk(world_position));
+        }
+
+        Ok(self.light(channel).get(Chunk::block_index(world_position)))
+    }
+
+    pub fn set_light_at_position(
+        &mut self,
+        world_position: BlockPosition,
+        channel: LightChannel,
+        level: u8,
+    ) {
+        let light = match channel {
+            LightChannel::Block => &mut self.block_light,
+            LightChannel::Sky => &mut self.sky_light,
+        };
+        light.set(Chunk::block_index(world_position), level);
+    }
+
+    fn light(&self, channel: LightChannel) -> &LightArray {
+        match channel {
+            LightChannel::Block => &self.block_light,
+            LightChannel::Sky => &self.sky_light,
+        }
+    }
+
+    /// The tick each block with a scheduled tick is due in, by world position.
+    pub fn scheduled_ticks(&self) -> &HashMap<BlockPosition, u64> {
+        &self.scheduled_ticks
+    }
+
+    /// Schedules a tick for the block at the position, unless it already has one due sooner.
+    pub fn schedule_tick(&mut self, world_position: BlockPosition, due_tick: u64) {
+        self.scheduled_ticks
+            .entry(world_position)
+            .and_modify(|scheduled_tick| *scheduled_tick = due_tick.min(*scheduled_tick))
+            .or_insert(due_tick);
+    }
+
+    /// Removes the scheduled ticks due by the tick, returning their positions.
+    pub fn take_due_ticks(&mut self, tick: u64) -> Vec<BlockPosition> {
+        let due_positions: Vec<BlockPosition> = self
+            .scheduled_ticks
+            .iter()
+            .filter(|(_, due_tick)| **due_tick <= tick)
+            .map(|(position, _)| *position)
+            .collect();
+
+        for position in &due_positions {
+            self.scheduled_ticks.remove(position);
+        }
+
+        due_positions
+    }
+
+    /// The biome of every column in the chunk, indexed by `x + z * CHUNK_BLOCK_WIDTH`.
+    pub fn biomes(&self) -> &[Biome; CHUNK_COLUMN_COUNT] {
+        &self.biomes
+    }
+
+    pub fn set_biomes(&mut self, biomes: [Biome; CHUNK_COLUMN_COUNT]) {
+        self.biomes = biomes;
+    }
+
+    /// Gets the biome of the column the position is in.
+    /// Only the x and z of the position are used.
+    pub fn get_biome_at_position(&self, world_position: BlockPosition) -> Biome {
+        self.biomes[Chunk::column_index(world_position)]
+    }
+
+    pub fn set_biome_at_position(&mut self, world_position: BlockPosition, biome: Biome) {
+        self.biomes[Chunk::column_index(world_position)] = biome;
+    }
+
     pub fn is_world_position_within(&self, world_position: BlockPosition) -> bool {
         self.origin_position == world_position.to_chunk_origin()
     }
@@ -0,0 +1,161 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    block_position::BlockPosition,
+    chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    world::World,
+    world_generation_system::ChunkGenerator,
+};
+
+/// Chunks below this height are never loaded, there is nothing under the bottom of the world.
+const MIN_CHUNK_Y: i32 = 0;
+
+/// Chunks are only unloaded once they are this many chunks past the render distance,
+/// so moving back and forth over a chunk border doesn't keep loading and unloading the same chunks.
+const UNLOAD_MARGIN: i32 = 1;
+
+pub struct ChunkManagerSettings {
+    /// How many chunks around the view position are loaded horizontally.
+    pub render_distance: i32,
+    /// How many chunks above and below the view position are loaded.
+    pub vertical_render_distance: i32,
+    /// The most chunks loaded or generated in a single [ChunkManager::update],
+    /// so that moving quickly doesn't stall a frame.
+    pub max_chunks_loaded_per_update: usize,
+}
+
+/// Keeps the chunks around a view position loaded.
+///
+/// Chunks coming into range are loaded from the world's save, or generated if they were never saved,
+/// closest first. Chunks going out of range are saved and unloaded.
+/// The chunks in range are only looked over again once the view position moves into another chunk.
+pub struct ChunkManager {
+    chunk_generator: ChunkGenerator,
+    settings: ChunkManagerSettings,
+
+    /// Chunk offsets (in chunks) within the render distance, sorted closest first
+    /// so chunks load in a spiral out from the view position.
+    spiral_offsets: Vec<(i32, i32, i32)>,
+    /// The origin of the chunk the view position was in at the last update.
+    center_chunk_origin: Option<BlockPosition>,
+    /// How far through [ChunkManager::spiral_offsets] the chunks around the center chunk are loaded,
+    /// the chunks at every offset before it are loaded already.
+    next_spiral_index: usize,
+}
+
+impl ChunkManager {
+    pub fn new(chunk_generator: ChunkGenerator, settings: ChunkManagerSettings) -> Self {
+        let render_distance = settings.render_distance;
+        let vertical_render_distance = settings.vertical_render_distance;
+
+        let mut spiral_offsets = Vec::new();
+        for x in -render_distance..=render_distance {
+            for z in -render_distance..=render_distance {
+                if x * x + z * z > render_distance * render_distance {
+                    continue;
+                }
+
+                for y in -vertical_render_distance..=vertical_render_distance {
+                    spiral_offsets.push((x, y, z));
+                }
+            }
+        }
+        spiral_offsets.sort_by_key(|&(x, y, z)| (x * x + z * z, y.abs()));
+
+        ChunkManager {
+            chunk_generator,
+            settings,
+            spiral_offsets,
+            center_chunk_origin: None,
+            next_spiral_index: 0,
+        }
+    }
+
+    /// Unloads the chunks that are out of range of the view position, and loads or generates
+    /// up to [ChunkManagerSettings::max_chunks_loaded_per_update] of the missing chunks in range.
+    /// Unloaded chunks are saved if the world has somewhere to save to.
+    ///
+    /// Chunks that fail to save stay loaded, and chunks that fail to load are skipped rather than
+    /// generated over their save, so neither loses what was saved.
+    pub fn update(&mut self, world: &mut World, view_position: glam::Vec3) {
+        let view_position = view_position.floor().as_ivec3();
+        let center_chunk_origin =
+            BlockPosition::new(view_position.x, view_position.y, view_position.z).to_chunk_origin();
+
+        if self.center_chunk_origin != Some(center_chunk_origin) {
+            self.center_chunk_origin = Some(center_chunk_origin);
+            self.next_spiral_index = 0;
+
+            let unloaded: Vec<BlockPosition> = world
+                .chunks
+                .keys()
+                .filter(|chunk_origin| {
+                    !self.is_in_unload_range(center_chunk_origin, **chunk_origin)
+                })
+                .copied()
+                .collect();
+            if let Err(err) = world.unload_chunks(&unloaded) {
+                eprintln!("failed to save the chunks out of range: {:?}", err);
+            }
+        }
+
+        let mut loaded_count = 0;
+        let mut chunks_to_generate = Vec::new();
+        while let Some(&offset) = self.spiral_offsets.get(self.next_spiral_index) {
+            if loaded_count + chunks_to_generate.len() >= self.settings.max_chunks_loaded_per_update
+            {
+                break;
+            }
+            self.next_spiral_index += 1;
+
+            let chunk_origin = ChunkManager::chunk_origin(center_chunk_origin, offset);
+            if chunk_origin.y < MIN_CHUNK_Y || world.chunks.contains_key(&chunk_origin) {
+                continue;
+            }
+
+            match world.load_chunk(chunk_origin) {
+                Ok(true) => loaded_count += 1,
+                Ok(false) => chunks_to_generate.push(chunk_origin),
+                Err(err) => {
+                    eprintln!("failed to load the chunk at {:?}: {:?}", chunk_origin, err);
+                }
+            }
+        }
+
+        let generated_chunks: Vec<Chunk> = chunks_to_generate
+            .into_par_iter()
+            .map(|chunk_origin| self.chunk_generator.generate_chunk(chunk_origin))
+            .collect();
+
+        for chunk in generated_chunks {
+            world.insert_chunk(chunk);
+        }
+    }
+
+    /// The origin of the chunk at the offset (in chunks) from the center chunk.
+    fn chunk_origin(
+        center_chunk_origin: BlockPosition,
+        (x, y, z): (i32, i32, i32),
+    ) -> BlockPosition {
+        center_chunk_origin.offset(
+            x * CHUNK_BLOCK_WIDTH as i32,
+            y * CHUNK_BLOCK_HEIGHT as i32,
+            z * CHUNK_BLOCK_DEPTH as i32,
+        )
+    }
+
+    fn is_in_unload_range(
+        &self,
+        center_chunk_origin: BlockPosition,
+        chunk_origin: BlockPosition,
+    ) -> bool {
+        let x = (chunk_origin.x - center_chunk_origin.x) / CHUNK_BLOCK_WIDTH as i32;
+        let y = (chunk_origin.y - center_chunk_origin.y) / CHUNK_BLOCK_HEIGHT as i32;
+        let z = (chunk_origin.z - center_chunk_origin.z) / CHUNK_BLOCK_DEPTH as i32;
+
+        let render_distance = self.settings.render_distance + UNLOAD_MARGIN;
+        let vertical_render_distance = self.settings.vertical_render_distance + UNLOAD_MARGIN;
+
+        x * x + z * z <= render_distance * render_distance && y.abs() <= vertical_render_distance
+    }
+}
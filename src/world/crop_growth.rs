@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use super::{
+    block::{Block, GrowthStageProperty},
+    block_position::BlockPosition,
+    block_registry::BlockRegistry,
+    world::World,
+    world_ticker::{BlockTicks, TickHandler},
+};
+
+/// Grows crops, the block types with a [GrowthStageProperty] that take random ticks,
+/// by one stage every time they are randomly ticked until they are fully grown.
+pub struct CropGrowth {
+    block_registry: Arc<BlockRegistry>,
+}
+
+impl CropGrowth {
+    pub fn new(block_registry: Arc<BlockRegistry>) -> Self {
+        CropGrowth { block_registry }
+    }
+}
+
+impl TickHandler for CropGrowth {
+    fn tick(&mut self, world: &mut World, block_ticks: &BlockTicks) {
+        let grown_blocks: Vec<(BlockPosition, Block)> = block_ticks
+            .random
+            .iter()
+            .filter_map(|(position, block)| {
+                let block_type = self.block_registry.block_type_of(block);
+                let growth_stage = block_type.growth_stage(block)?;
+                // Fails once the crop is fully grown, as the next stage is out of range.
+                let grown_block = block_type
+                    .with_state::<GrowthStageProperty>(*block, growth_stage + 1)
+                    .ok()?;
+
+                Some((*position, grown_block))
+            })
+            .collect();
+
+        world.set_blocks(&grown_blocks);
+    }
+}
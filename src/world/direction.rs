@@ -1,4 +1,8 @@
-#[derive(Debug, Clone, Copy)]
+use serde::Deserialize;
+use strum_macros::{EnumIter, FromRepr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, FromRepr, Deserialize)]
+#[repr(u8)]
 pub enum Direction {
     North = 0,
     South = 1,
@@ -7,3 +11,28 @@ pub enum Direction {
     Up = 4,
     Down = 5,
 }
+
+impl Direction {
+    /// The offset to the neighbouring block in this direction.
+    pub fn offset(&self) -> (i32, i32, i32) {
+        match self {
+            Direction::North => (0, 0, 1),
+            Direction::South => (0, 0, -1),
+            Direction::East => (-1, 0, 0),
+            Direction::West => (1, 0, 0),
+            Direction::Up => (0, 1, 0),
+            Direction::Down => (0, -1, 0),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+        }
+    }
+}
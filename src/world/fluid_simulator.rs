@@ -0,0 +1,589 @@
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+};
+
+use strum::IntoEnumIterator;
+
+use super::{
+    block::{Block, FluidFallingProperty, FluidLevelProperty},
+    block_position::BlockPosition,
+    block_registry::{BlockRegistry, FluidProperties},
+    chunk::Chunk,
+    direction::Direction,
+    world::World,
+    world_ticker::{BlockTicks, TickHandler},
+};
+
+/// The level of a liquid source block, see [FluidProperties].
+/// Falling liquid has this level too, so it spreads like a source once it lands.
+pub const SOURCE_LEVEL: u8 = 0;
+
+const HORIZONTAL_DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::South,
+    Direction::East,
+    Direction::West,
+];
+
+/// A block changed by a fluid tick.
+#[derive(Clone, Copy, Debug)]
+pub struct FluidChange {
+    pub position: BlockPosition,
+    /// The block the tick saw at the position, air if it was empty.
+    /// The change is dropped if something else changed the block while the tick was running.
+    pub old_block: Block,
+    pub new_block: Block,
+}
+
+/// Flows the liquids in a world, as a [TickHandler] of the world's [WorldTicker](super::world_ticker::WorldTicker).
+///
+/// Liquids only flow when the blocks around them change, each liquid next to a changed block schedules
+/// a tick for once its [FluidProperties::tick_delay] has passed. The chunks around the liquids due in a tick
+/// are copied out of the world and flowed on the thread pool, and the changes are written back into
+/// the world all at once in a later tick, so every changed chunk is only remeshed once per tick.
+pub struct FluidSimulator {
+    block_registry: Arc<BlockRegistry>,
+    /// Whether a tick is flowing on the thread pool, only one tick is flowed at a time.
+    is_flowing: bool,
+    fluid_changes_tx: Sender<Vec<FluidChange>>,
+    fluid_changes_rx: Receiver<Vec<FluidChange>>,
+}
+
+impl TickHandler for FluidSimulator {
+    /// Writes the changes of the last tick that finished flowing into the world, schedules the liquids
+    /// around the blocks that changed, and then starts flowing the liquids that are due.
+    /// If the last tick is still flowing, the liquids due are scheduled again for the next tick.
+    fn tick(&mut self, world: &mut World, block_ticks: &BlockTicks) {
+        self.write_fluid_changes(world);
+
+        for position in &block_ticks.changed {
+            self.schedule(world, *position);
+            for direction in Direction::iter() {
+                let (x, y, z) = direction.offset();
+                self.schedule(world, position.offset(x, y, z));
+            }
+        }
+
+        let liquid_positions: Vec<BlockPosition> = block_ticks
+            .scheduled
+            .iter()
+            .copied()
+            .filter(|position| self.fluid_at(world, *position).is_some())
+            .collect();
+        if liquid_positions.is_empty() {
+            return;
+        }
+
+        if self.is_flowing {
+            for position in liquid_positions {
+                world.schedule_tick(position, 1);
+            }
+            return;
+        }
+
+        self.begin_flowing(world, liquid_positions);
+    }
+}
+
+impl FluidSimulator {
+    pub fn new(block_registry: Arc<BlockRegistry>) -> Self {
+        let (fluid_changes_tx, fluid_changes_rx) = channel();
+
+        FluidSimulator {
+            block_registry,
+            is_flowing: false,
+            fluid_changes_tx,
+            fluid_changes_rx,
+        }
+    }
+
+    fn fluid_at(&self, world: &World, position: BlockPosition) -> Option<&FluidProperties> {
+        let block = world.get_block_at_position(position)?;
+        self.block_registry.block_type_of(&block).fluid.as_ref()
+    }
+
+    /// Schedules a tick for the liquid at the position once its tick delay has passed.
+    /// Does nothing if the block isn't a liquid.
+    pub fn schedule(&self, world: &mut World, position: BlockPosition) {
+        if let Some(fluid) = self.fluid_at(world, position) {
+            world.schedule_tick(position, fluid.tick_delay);
+        }
+    }
+
+    /// Writes the changes of the flowed tick into the world, if it has finished.
+    fn write_fluid_changes(&mut self, world: &mut World) {
+        // The simulator holds a sender itself, so the channel can only ever be empty.
+        let Ok(fluid_changes) = self.fluid_changes_rx.try_recv() else {
+            return;
+        };
+        self.is_flowing = false;
+
+        let blocks: Vec<(BlockPosition, Block)> = fluid_changes
+            .into_iter()
+            .filter(|fluid_change| {
+                world
+                    .get_block_at_position(fluid_change.position)
+                    .unwrap_or_default()
+                    == fluid_change.old_block
+            })
+            .map(|fluid_change| (fluid_change.position, fluid_change.new_block))
+            .collect();
+        world.set_blocks(&blocks);
+    }
+
+    /// Copies the chunks around the liquids and starts flowing them on the thread pool.
+    fn begin_flowing(&mut self, world: &World, positions: Vec<BlockPosition>) {
+        // Liquids flow into the blocks next to them, which can be in the neighbouring chunks.
+        let mut chunks = HashMap::new();
+        for position in &positions {
+            for (x, y, z) in Direction::iter().map(|direction| direction.offset()) {
+                let chunk_origin = position.offset(x, y, z).to_chunk_origin();
+                if let Some(chunk) = world.chunks.get(&chunk_origin) {
+                    chunks.entry(chunk_origin).or_insert_with(|| chunk.clone());
+                }
+            }
+        }
+
+        let tx = self.fluid_changes_tx.clone();
+        let block_registry = self.block_registry.clone();
+        self.is_flowing = true;
+        rayon::spawn(move || {
+            // The changes are always sent, even if flowing panics, so the simulator doesn't
+            // wait on them forever.
+            let fluid_changes = panic::catch_unwind(AssertUnwindSafe(|| {
+                FluidSimulator::flow(&positions, chunks, &block_registry)
+            }))
+            .unwrap_or_else(|_| {
+                eprintln!("the liquids at {:?} failed to flow", positions);
+                Vec::new()
+            });
+            let _ = tx.send(fluid_changes);
+        });
+    }
+
+    /// Flows the liquids at the positions through the chunks, returning the blocks that changed.
+    /// Liquids don't flow into chunks that weren't passed in.
+    ///
+    /// The liquids flow one after another, each seeing the changes made by the ones before it,
+    /// in order of position so the same blocks always flow the same way.
+    pub fn flow(
+        positions: &[BlockPosition],
+        chunks: HashMap<BlockPosition, Chunk>,
+        block_registry: &BlockRegistry,
+    ) -> Vec<FluidChange> {
+        let mut positions = positions.to_vec();
+        positions.sort_by_key(|position| (position.x, position.y, position.z));
+        positions.dedup();
+
+        let mut fluid_tick = FluidTick {
+            chunks,
+            changes: HashMap::new(),
+            block_registry,
+        };
+        for position in positions {
+            fluid_tick.flow_block(position);
+        }
+
+        fluid_tick
+            .changes
+            .into_values()
+            .filter(|fluid_change| fluid_change.old_block != fluid_change.new_block)
+            .collect()
+    }
+}
+
+/// The blocks a tick flows liquids through, along with the changes the tick has made to them so far.
+struct FluidTick<'a> {
+    chunks: HashMap<BlockPosition, Chunk>,
+    changes: HashMap<BlockPosition, FluidChange>,
+    block_registry: &'a BlockRegistry,
+}
+
+impl FluidTick<'_> {
+    /// Gets the block at the position, air included, or `None` if its chunk isn't there.
+    fn get(&self, position: BlockPosition) -> Option<Block> {
+        if let Some(fluid_change) = self.changes.get(&position) {
+            return Some(fluid_change.new_block);
+        }
+
+        let chunk = self.chunks.get(&position.to_chunk_origin())?;
+        let block = chunk.get_block_at_position(position).ok()?;
+        Some(block.unwrap_or_default())
+    }
+
+    fn set(&mut self, position: BlockPosition, block: Block) {
+        let Some(old_block) = self.get(position) else {
+            return;
+        };
+
+        self.changes
+            .entry(position)
+            .and_modify(|fluid_change| fluid_change.new_block = block)
+            .or_insert(FluidChange {
+                position,
+                old_block,
+                new_block: block,
+            });
+    }
+
+    fn is_air(&self, position: BlockPosition) -> bool {
+        self.get(position).is_some_and(|block| block.is_air())
+    }
+
+    /// The level of the liquid at the position, if it is the same liquid as `liquid`.
+    fn level_of(&self, position: BlockPosition, liquid: Block) -> Option<u8> {
+        self.get(position)
+            .filter(|block| block.id == liquid.id)
+            .and_then(|block| {
+                self.block_registry
+                    .block_type_of(&block)
+                    .fluid_level(&block)
+            })
+    }
+
+    /// Whether the block at the position is a source of the same liquid as `liquid`.
+    fn is_source_at(&self, position: BlockPosition, liquid: Block) -> bool {
+        self.get(position)
+            .filter(|block| block.id == liquid.id)
+            .is_some_and(|block| self.is_source(block))
+    }
+
+    fn is_source(&self, liquid: Block) -> bool {
+        let block_type = self.block_registry.block_type_of(&liquid);
+        block_type.fluid_level(&liquid) == Some(SOURCE_LEVEL)
+            && block_type.is_fluid_falling(&liquid) == Some(false)
+    }
+
+    /// The liquid with its level and falling flag set.
+    fn with_level(&self, liquid: Block, level: u8, is_falling: bool) -> Option<Block> {
+        let block_type = self.block_registry.block_type_of(&liquid);
+        let liquid = block_type
+            .with_state::<FluidLevelProperty>(liquid, level)
+            .ok()?;
+        block_type
+            .with_state::<FluidFallingProperty>(liquid, is_falling)
+            .ok()
+    }
+
+    /// Hardens the liquid if it touches a different liquid, otherwise settles it to the level the
+    /// liquid around it feeds it, and then flows it down, or sideways if it can't flow down.
+    fn flow_block(&mut self, position: BlockPosition) {
+        let block_registry = self.block_registry;
+        let Some(block) = self.get(position) else {
+            return;
+        };
+        let block_type = block_registry.block_type_of(&block);
+        let Some(fluid) = &block_type.fluid else {
+            return;
+        };
+
+        if let Some(hardened_block) = self.hardened_block(position, block, fluid) {
+            self.set(position, hardened_block);
+            return;
+        }
+
+        let settled_block = self
+            .settled_level(position, block, fluid)
+            .and_then(|(level, is_falling)| self.with_level(block, level, is_falling));
+        let Some(block) = settled_block else {
+            self.set(position, Block::default());
+            return;
+        };
+        self.set(position, block);
+
+        let below_position = position.offset_y(-1);
+        if self.is_air(below_position) {
+            if let Some(falling_block) = self.with_level(block, SOURCE_LEVEL, true) {
+                self.set(below_position, falling_block);
+            }
+        }
+
+        // Liquid running down doesn't spread out until it lands, unless it is a source.
+        let is_running_down =
+            self.is_air(below_position) || self.level_of(below_position, block).is_some();
+        if is_running_down && !self.is_source(block) {
+            return;
+        }
+
+        let level = block_type.fluid_level(&block).unwrap_or(SOURCE_LEVEL);
+        let spread_level = level + 1;
+        if spread_level > fluid.flow_distance {
+            return;
+        }
+        let Some(spread_block) = self.with_level(block, spread_level, false) else {
+            return;
+        };
+
+        for direction in HORIZONTAL_DIRECTIONS {
+            let (x, y, z) = direction.offset();
+            let neighbor_position = position.offset(x, y, z);
+            if self.is_air(neighbor_position) {
+                self.set(neighbor_position, spread_block);
+            }
+        }
+    }
+
+    /// The block the liquid hardens into, if it has one and touches a different liquid.
+    fn hardened_block(
+        &self,
+        position: BlockPosition,
+        liquid: Block,
+        fluid: &FluidProperties,
+    ) -> Option<Block> {
+        let hardened_block = self.block_registry.block(fluid.hardens_into.as_deref()?)?;
+
+        let touches_other_liquid = Direction::iter().any(|direction| {
+            let (x, y, z) = direction.offset();
+            self.get(position.offset(x, y, z)).is_some_and(|block| {
+                block.id != liquid.id && self.block_registry.block_type_of(&block).fluid.is_some()
+            })
+        });
+
+        touches_other_liquid.then_some(hardened_block)
+    }
+
+    /// The level the liquid around the liquid feeds it and whether it is falling, or `None` if it
+    /// isn't fed and dries up.
+    ///
+    /// Sources stay sources. Flowing liquid falls below more of the same liquid, and otherwise is one
+    /// level past the lowest level next to it, up to the liquid's flow distance. Between two sources,
+    /// liquids that form sources become one if they rest on something solid or another source.
+    fn settled_level(
+        &self,
+        position: BlockPosition,
+        liquid: Block,
+        fluid: &FluidProperties,
+    ) -> Option<(u8, bool)> {
+        if self.is_source(liquid) {
+            return Some((SOURCE_LEVEL, false));
+        }
+
+        if self.level_of(position.offset_y(1), liquid).is_some() {
+            return Some((SOURCE_LEVEL, true));
+        }
+
+        let neighbor_levels: Vec<u8> = HORIZONTAL_DIRECTIONS
+            .iter()
+            .filter_map(|direction| {
+                let (x, y, z) = direction.offset();
+                self.level_of(position.offset(x, y, z), liquid)
+            })
+            .collect();
+
+        let source_count = HORIZONTAL_DIRECTIONS
+            .iter()
+            .filter(|direction| {
+                let (x, y, z) = direction.offset();
+                self.is_source_at(position.offset(x, y, z), liquid)
+            })
+            .count();
+        let below_position = position.offset_y(-1);
+        let is_resting = self
+            .get(below_position)
+            .is_some_and(|block| block.is_solid())
+            || self.is_source_at(below_position, liquid);
+        if fluid.forms_sources && source_count >= 2 && is_resting {
+            return Some((SOURCE_LEVEL, false));
+        }
+
+        let level = neighbor_levels.iter().min()? + 1;
+        (level <= fluid.flow_distance).then_some((level, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        block_registry::test_registry,
+        chunk::{CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    };
+
+    const LIQUIDS: &str = r#"
+        (
+            identifier: "test:water",
+            display_name: "Water",
+            solid: false,
+            transparent: true,
+            state_properties: [FluidLevel, FluidFalling],
+            fluid: (flow_distance: 3, tick_delay: 5, forms_sources: true),
+        ),
+        (
+            identifier: "test:lava",
+            display_name: "Lava",
+            solid: false,
+            transparent: true,
+            state_properties: [FluidLevel, FluidFalling],
+            fluid: (flow_distance: 2, tick_delay: 30, hardens_into: "test:stone"),
+        ),"#;
+
+    /// An empty chunk with a floor of stone at y 0.
+    fn test_chunk(block_registry: &BlockRegistry) -> Chunk {
+        let stone = block_registry.block("test:stone").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                chunk.set_block_at_position(BlockPosition::new(x, 0, z), stone);
+            }
+        }
+        chunk
+    }
+
+    fn liquid(block_registry: &BlockRegistry, identifier: &str, level: u8, falling: bool) -> Block {
+        let liquid = block_registry.block(identifier).unwrap();
+        let block_type = block_registry.block_type_of(&liquid);
+        let liquid = block_type
+            .with_state::<FluidLevelProperty>(liquid, level)
+            .unwrap();
+        block_type
+            .with_state::<FluidFallingProperty>(liquid, falling)
+            .unwrap()
+    }
+
+    /// Flows the liquids at the positions and writes the changes into the chunk.
+    fn flow(chunk: &mut Chunk, positions: &[BlockPosition], block_registry: &BlockRegistry) {
+        let chunks = HashMap::from([(chunk.origin_position(), chunk.clone())]);
+        for fluid_change in FluidSimulator::flow(positions, chunks, block_registry) {
+            chunk.set_block_at_position(fluid_change.position, fluid_change.new_block);
+        }
+    }
+
+    /// Flows every liquid in the chunk until nothing changes.
+    fn flow_until_settled(chunk: &mut Chunk, block_registry: &BlockRegistry) {
+        for _ in 0..64 {
+            let mut positions = Vec::new();
+            for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+                for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                    for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                        let position = BlockPosition::new(x, y, z);
+                        if let Ok(Some(block)) = chunk.get_block_at_position(position) {
+                            if block_registry.block_type_of(&block).fluid.is_some() {
+                                positions.push(position);
+                            }
+                        }
+                    }
+                }
+            }
+
+            let chunks = HashMap::from([(chunk.origin_position(), chunk.clone())]);
+            let fluid_changes = FluidSimulator::flow(&positions, chunks, block_registry);
+            if fluid_changes.is_empty() {
+                return;
+            }
+            for fluid_change in fluid_changes {
+                chunk.set_block_at_position(fluid_change.position, fluid_change.new_block);
+            }
+        }
+        panic!("liquids never settled");
+    }
+
+    fn block_at(chunk: &Chunk, x: i32, y: i32, z: i32) -> Option<Block> {
+        chunk
+            .get_block_at_position(BlockPosition::new(x, y, z))
+            .unwrap()
+    }
+
+    #[test]
+    fn liquids_fall_into_the_air_below_them() {
+        let block_registry = test_registry(LIQUIDS);
+        let mut chunk = test_chunk(&block_registry);
+        let source = liquid(&block_registry, "test:water", SOURCE_LEVEL, false);
+        let falling = liquid(&block_registry, "test:water", SOURCE_LEVEL, true);
+        chunk.set_block_at_position(BlockPosition::new(5, 8, 5), source);
+
+        flow(&mut chunk, &[BlockPosition::new(5, 8, 5)], &block_registry);
+        assert_eq!(block_at(&chunk, 5, 8, 5), Some(source));
+        assert_eq!(block_at(&chunk, 5, 7, 5), Some(falling));
+        // Sources spread sideways even while they run down.
+        assert_eq!(
+            block_at(&chunk, 6, 8, 5),
+            Some(liquid(&block_registry, "test:water", 1, false))
+        );
+
+        // Falling liquid only spreads once it lands.
+        flow(&mut chunk, &[BlockPosition::new(5, 7, 5)], &block_registry);
+        assert_eq!(block_at(&chunk, 5, 7, 5), Some(falling));
+        assert_eq!(block_at(&chunk, 5, 6, 5), Some(falling));
+        assert_eq!(block_at(&chunk, 6, 7, 5), None);
+    }
+
+    #[test]
+    fn landed_liquid_spreads_like_a_source() {
+        let block_registry = test_registry(LIQUIDS);
+        let mut chunk = test_chunk(&block_registry);
+        let source = liquid(&block_registry, "test:water", SOURCE_LEVEL, false);
+        let falling = liquid(&block_registry, "test:water", SOURCE_LEVEL, true);
+        chunk.set_block_at_position(BlockPosition::new(5, 2, 5), source);
+        chunk.set_block_at_position(BlockPosition::new(5, 1, 5), falling);
+
+        flow(&mut chunk, &[BlockPosition::new(5, 1, 5)], &block_registry);
+        assert_eq!(block_at(&chunk, 5, 1, 5), Some(falling));
+        assert_eq!(
+            block_at(&chunk, 4, 1, 5),
+            Some(liquid(&block_registry, "test:water", 1, false))
+        );
+    }
+
+    #[test]
+    fn liquids_spread_up_to_their_flow_distance() {
+        let block_registry = test_registry(LIQUIDS);
+        let mut chunk = test_chunk(&block_registry);
+        let source = liquid(&block_registry, "test:water", SOURCE_LEVEL, false);
+        chunk.set_block_at_position(BlockPosition::new(5, 1, 5), source);
+
+        flow_until_settled(&mut chunk, &block_registry);
+        for level in 1..=3 {
+            let expected = liquid(&block_registry, "test:water", level, false);
+            assert_eq!(block_at(&chunk, 5 + level as i32, 1, 5), Some(expected));
+            assert_eq!(block_at(&chunk, 5, 1, 5 - level as i32), Some(expected));
+        }
+        assert_eq!(block_at(&chunk, 9, 1, 5), None);
+
+        // Without the source the liquid flowing from it dries up.
+        chunk.set_block_at_position(BlockPosition::new(5, 1, 5), Block::default());
+        flow_until_settled(&mut chunk, &block_registry);
+        assert_eq!(block_at(&chunk, 6, 1, 5), None);
+        assert_eq!(block_at(&chunk, 8, 1, 5), None);
+    }
+
+    #[test]
+    fn sources_form_between_two_sources() {
+        let block_registry = test_registry(LIQUIDS);
+
+        for (identifier, expected_level) in [("test:water", SOURCE_LEVEL), ("test:lava", 1)] {
+            let mut chunk = test_chunk(&block_registry);
+            let source = liquid(&block_registry, identifier, SOURCE_LEVEL, false);
+            chunk.set_block_at_position(BlockPosition::new(4, 1, 5), source);
+            chunk.set_block_at_position(BlockPosition::new(6, 1, 5), source);
+
+            flow_until_settled(&mut chunk, &block_registry);
+            assert_eq!(
+                block_at(&chunk, 5, 1, 5),
+                Some(liquid(&block_registry, identifier, expected_level, false)),
+                "{identifier}"
+            );
+        }
+    }
+
+    #[test]
+    fn lava_touching_water_hardens_into_stone() {
+        let block_registry = test_registry(LIQUIDS);
+        let mut chunk = test_chunk(&block_registry);
+        let water = liquid(&block_registry, "test:water", SOURCE_LEVEL, false);
+        let lava = liquid(&block_registry, "test:lava", SOURCE_LEVEL, false);
+        chunk.set_block_at_position(BlockPosition::new(5, 1, 5), lava);
+        chunk.set_block_at_position(BlockPosition::new(6, 1, 5), water);
+
+        flow(&mut chunk, &[BlockPosition::new(5, 1, 5)], &block_registry);
+        assert_eq!(
+            block_at(&chunk, 5, 1, 5),
+            block_registry.block("test:stone")
+        );
+        assert_eq!(block_at(&chunk, 6, 1, 5), Some(water));
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::BTreeSet;
+
+use super::{
+    block_position::BlockPosition,
+    chunk::{CHUNK_BLOCK_WIDTH, CHUNK_COLUMN_COUNT},
+};
+
+/// The y of the topmost opaque block of every column in a column of chunks,
+/// out of the blocks in the column's loaded chunks.
+pub struct Heightmap {
+    heights: [Option<i32>; CHUNK_COLUMN_COUNT],
+    /// The y of the origin of every loaded chunk in the column of chunks.
+    chunk_ys: BTreeSet<i32>,
+}
+
+impl Heightmap {
+    pub fn new() -> Self {
+        Heightmap {
+            heights: [None; CHUNK_COLUMN_COUNT],
+            chunk_ys: BTreeSet::new(),
+        }
+    }
+
+    /// The position heightmaps are keyed by, the origin of the position's chunk moved to a y of 0.
+    pub fn column_origin(position: BlockPosition) -> BlockPosition {
+        let chunk_origin = position.to_chunk_origin();
+        BlockPosition::new(chunk_origin.x, 0, chunk_origin.z)
+    }
+
+    /// Gets the height of the column the position is in, `None` if it has no opaque blocks.
+    /// Only the x and z of the position are used.
+    pub fn get(&self, position: BlockPosition) -> Option<i32> {
+        self.heights[Heightmap::column_index(position)]
+    }
+
+    pub fn set(&mut self, position: BlockPosition, height: Option<i32>) {
+        self.heights[Heightmap::column_index(position)] = height;
+    }
+
+    pub fn chunk_ys(&self) -> &BTreeSet<i32> {
+        &self.chunk_ys
+    }
+
+    pub fn add_chunk(&mut self, chunk_origin: BlockPosition) {
+        self.chunk_ys.insert(chunk_origin.y);
+    }
+
+    /// Returns whether the column of chunks has no loaded chunks left.
+    pub fn remove_chunk(&mut self, chunk_origin: BlockPosition) -> bool {
+        self.chunk_ys.remove(&chunk_origin.y);
+        self.chunk_ys.is_empty()
+    }
+
+    fn column_index(position: BlockPosition) -> usize {
+        let chunk_local_position = position.to_chunk_local_position();
+
+        (chunk_local_position.x + (chunk_local_position.z * CHUNK_BLOCK_WIDTH as i32)) as usize
+    }
+}
+
+impl Default for Heightmap {
+    fn default() -> Self {
+        Heightmap::new()
+    }
+}
@@ -0,0 +1,576 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use strum::IntoEnumIterator;
+
+use super::{
+    block::Block,
+    block_position::BlockPosition,
+    block_registry::BlockRegistry,
+    chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    direction::Direction,
+    heightmap::Heightmap,
+};
+
+/// The brightest a block can be lit.
+pub const MAX_LIGHT_LEVEL: u8 = 15;
+
+/// A light level from 0 to [MAX_LIGHT_LEVEL] for every block of a chunk, packed two to a byte.
+#[derive(Clone)]
+pub struct LightArray {
+    nibbles: Vec<u8>,
+}
+
+impl LightArray {
+    /// Creates an array of `len` unlit blocks.
+    pub fn new(len: usize) -> Self {
+        LightArray {
+            nibbles: vec![0; len.div_ceil(2)],
+        }
+    }
+
+    pub fn get(&self, index: usize) -> u8 {
+        let byte = self.nibbles[index / 2];
+        match index % 2 {
+            0 => byte & 0x0F,
+            _ => byte >> 4,
+        }
+    }
+
+    /// Sets the light level at the index, levels above [MAX_LIGHT_LEVEL] are clamped to it.
+    pub fn set(&mut self, index: usize, level: u8) {
+        let level = level.min(MAX_LIGHT_LEVEL);
+        let byte = &mut self.nibbles[index / 2];
+        *byte = match index % 2 {
+            0 => (*byte & 0xF0) | level,
+            _ => (*byte & 0x0F) | (level << 4),
+        };
+    }
+}
+
+/// The kinds of light a block is lit by, each stored and spread separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightChannel {
+    /// Light given off by emissive blocks.
+    Block,
+    /// Light from the sky. Every block above the topmost opaque block of its column
+    /// is fully lit, and that light spreads sideways into overhangs and caves.
+    Sky,
+}
+
+/// Spreads the light of a channel out from its sources through the loaded chunks,
+/// emissive blocks for block light and the blocks open to the sky for sky light.
+/// Light passes through every block that isn't opaque, losing a level for each block it passes,
+/// and crosses into any loaded chunk. Blocks in chunks that aren't loaded are unlit.
+///
+/// Changes are made incrementally, removing light clears it from the blocks it reached
+/// and then fills the hole back in from the light around it, so only the blocks whose light
+/// changes are visited.
+pub struct LightPropagator<'a> {
+    chunks: &'a mut HashMap<BlockPosition, Chunk>,
+    block_registry: &'a BlockRegistry,
+    /// The heightmaps of the loaded columns of chunks, keyed by [Heightmap::column_origin].
+    /// Only sky light uses them.
+    heightmaps: &'a HashMap<BlockPosition, Heightmap>,
+    channel: LightChannel,
+    /// Lit blocks whose light still has to spread to the blocks around them.
+    spread_queue: VecDeque<BlockPosition>,
+    /// Blocks whose light was cleared, along with the light they had.
+    removal_queue: VecDeque<(BlockPosition, u8)>,
+    changed_chunk_origins: HashSet<BlockPosition>,
+}
+
+impl<'a> LightPropagator<'a> {
+    pub fn new(
+        chunks: &'a mut HashMap<BlockPosition, Chunk>,
+        block_registry: &'a BlockRegistry,
+        heightmaps: &'a HashMap<BlockPosition, Heightmap>,
+        channel: LightChannel,
+    ) -> Self {
+        LightPropagator {
+            chunks,
+            block_registry,
+            heightmaps,
+            channel,
+            spread_queue: VecDeque::new(),
+            removal_queue: VecDeque::new(),
+            changed_chunk_origins: HashSet::new(),
+        }
+    }
+
+    /// The chunks whose light was changed, by origin.
+    pub fn changed_chunk_origins(self) -> HashSet<BlockPosition> {
+        self.changed_chunk_origins
+    }
+
+    /// Lights a newly loaded chunk from the light sources in it, and from the light of the
+    /// loaded chunks around it. Its own light spreads out into those chunks too.
+    ///
+    /// For sky light the chunk's column of chunks has to be in the heightmaps already.
+    pub fn light_chunk(&mut self, chunk_origin: BlockPosition) {
+        self.light_chunks(&[chunk_origin]);
+    }
+
+    /// Lights newly loaded chunks like [LightPropagator::light_chunk]. Every chunk's light
+    /// sources are found before any light spreads, so light doesn't spread into a chunk
+    /// that is then lit over again.
+    pub fn light_chunks(&mut self, chunk_origins: &[BlockPosition]) {
+        let chunk_origins: HashSet<BlockPosition> = chunk_origins
+            .iter()
+            .copied()
+            .filter(|chunk_origin| self.chunks.contains_key(chunk_origin))
+            .collect();
+
+        for chunk_origin in &chunk_origins {
+            match self.channel {
+                LightChannel::Block => self.seed_emissive_blocks(*chunk_origin),
+                LightChannel::Sky => self.seed_sky_light(*chunk_origin),
+            }
+        }
+
+        // The blocks just outside of each side of the chunks, the light of the chunks that were
+        // already lit spreads in over the border.
+        let chunk_size =
+            [CHUNK_BLOCK_WIDTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_DEPTH].map(|size| size as i32);
+        for chunk_origin in &chunk_origins {
+            for x in -1..=chunk_size[0] {
+                for y in -1..=chunk_size[1] {
+                    for z in -1..=chunk_size[2] {
+                        let outside_axis_count = [x, y, z]
+                            .iter()
+                            .zip(chunk_size)
+                            .filter(|(local, size)| **local < 0 || **local >= *size)
+                            .count();
+                        let position = chunk_origin.offset(x, y, z);
+                        if outside_axis_count == 1
+                            && !chunk_origins.contains(&position.to_chunk_origin())
+                            && self.light(position) > 1
+                        {
+                            self.spread_queue.push_back(position);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.spread();
+    }
+
+    /// Lights the emissive blocks in the chunk.
+    fn seed_emissive_blocks(&mut self, chunk_origin: BlockPosition) {
+        let Some(chunk) = self.chunks.get(&chunk_origin) else {
+            return;
+        };
+
+        // Most chunks have nothing emissive in them, which the palette shows without visiting every block.
+        let has_emissive_blocks = chunk
+            .blocks()
+            .palette()
+            .iter()
+            .any(|block| self.emission(block) > 0);
+        if !has_emissive_blocks {
+            return;
+        }
+
+        let mut emissive_blocks = Vec::new();
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                    let position = chunk_origin.offset(x, y, z);
+                    if let Ok(Some(block)) = chunk.get_block_at_position(position) {
+                        let emission = self.emission(&block);
+                        if emission > 0 {
+                            emissive_blocks.push((position, emission));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (position, emission) in emissive_blocks {
+            self.set_light(position, emission);
+            self.spread_queue.push_back(position);
+        }
+    }
+
+    /// Fully lights the blocks in the chunk that are open to the sky.
+    /// Only the ones beside a taller column are queued to spread, the rest only have
+    /// blocks that are as bright as them around them.
+    fn seed_sky_light(&mut self, chunk_origin: BlockPosition) {
+        let chunk_top = chunk_origin.y + CHUNK_BLOCK_HEIGHT as i32 - 1;
+
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                let column_position = chunk_origin.offset(x, 0, z);
+                let Some(heightmap) = self
+                    .heightmaps
+                    .get(&Heightmap::column_origin(column_position))
+                else {
+                    continue;
+                };
+
+                let lowest_sky_y = heightmap
+                    .get(column_position)
+                    .map_or(chunk_origin.y, |height| (height + 1).max(chunk_origin.y));
+                let highest_spreading_y = [
+                    Direction::North,
+                    Direction::South,
+                    Direction::East,
+                    Direction::West,
+                ]
+                .iter()
+                .filter_map(|direction| {
+                    let (x, _, z) = direction.offset();
+                    self.height(column_position.offset(x, 0, z))
+                })
+                .max()
+                .unwrap_or(i32::MIN);
+
+                for y in lowest_sky_y..=chunk_top {
+                    let position = BlockPosition::new(column_position.x, y, column_position.z);
+                    self.set_light(position, MAX_LIGHT_LEVEL);
+                    if y <= highest_spreading_y {
+                        self.spread_queue.push_back(position);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Relights the blocks around the position after the block there was changed.
+    /// For sky light the heightmaps have to be updated for the block first.
+    pub fn update_block(&mut self, position: BlockPosition) {
+        let light = self.light(position);
+        if light > 0 {
+            self.set_light(position, 0);
+            self.removal_queue.push_back((position, light));
+        }
+        self.remove();
+
+        let source_light = self.source_light(position);
+        if source_light > 0 {
+            self.set_light(position, source_light);
+            self.spread_queue.push_back(position);
+        }
+
+        // If the new block lets light through, the light around it spreads back in.
+        for direction in Direction::iter() {
+            let (x, y, z) = direction.offset();
+            let neighbor_position = position.offset(x, y, z);
+            if self.light(neighbor_position) > 1 {
+                self.spread_queue.push_back(neighbor_position);
+            }
+        }
+
+        self.spread();
+    }
+
+    /// Relights the column the position is in after the height of its topmost opaque block
+    /// changed from `old_height` to `new_height`. The blocks a taller column now shades lose
+    /// their sky light, and the blocks a shorter column uncovers are fully lit.
+    /// Does nothing for block light.
+    pub fn update_sky_column(
+        &mut self,
+        position: BlockPosition,
+        old_height: Option<i32>,
+        new_height: Option<i32>,
+    ) {
+        if self.channel != LightChannel::Sky {
+            return;
+        }
+
+        if new_height > old_height {
+            let Some(new_height) = new_height else {
+                return;
+            };
+            let lowest_y = old_height.map_or(i32::MIN, |height| height + 1);
+            let shaded_positions = self.loaded_column_positions(position, lowest_y, new_height - 1);
+            for shaded_position in shaded_positions {
+                let light = self.light(shaded_position);
+                if light > 0 {
+                    self.set_light(shaded_position, 0);
+                    self.removal_queue.push_back((shaded_position, light));
+                }
+            }
+            self.remove();
+        } else if new_height < old_height {
+            let Some(old_height) = old_height else {
+                return;
+            };
+            let lowest_y = new_height.map_or(i32::MIN, |height| height + 1);
+            let uncovered_positions = self.loaded_column_positions(position, lowest_y, old_height);
+            for uncovered_position in uncovered_positions {
+                self.set_light(uncovered_position, MAX_LIGHT_LEVEL);
+                self.spread_queue.push_back(uncovered_position);
+            }
+        }
+
+        self.spread();
+    }
+
+    /// Spreads the light of every block in the spread queue, until it has reached every block it can.
+    fn spread(&mut self) {
+        while let Some(position) = self.spread_queue.pop_front() {
+            let light = self.light(position);
+            if light <= 1 {
+                continue;
+            }
+
+            for direction in Direction::iter() {
+                let (x, y, z) = direction.offset();
+                let neighbor_position = position.offset(x, y, z);
+                if !self.is_loaded(neighbor_position)
+                    || self.light(neighbor_position) >= light - 1
+                    || self.is_opaque(neighbor_position)
+                {
+                    continue;
+                }
+
+                self.set_light(neighbor_position, light - 1);
+                self.spread_queue.push_back(neighbor_position);
+            }
+        }
+    }
+
+    /// Clears the light that came from the blocks in the removal queue.
+    /// Neighbours that are at least as bright as a cleared block were lit from somewhere else,
+    /// they are queued to spread their light back into the cleared blocks.
+    fn remove(&mut self) {
+        let mut light_sources = Vec::new();
+
+        while let Some((position, light)) = self.removal_queue.pop_front() {
+            for direction in Direction::iter() {
+                let (x, y, z) = direction.offset();
+                let neighbor_position = position.offset(x, y, z);
+                let neighbor_light = self.light(neighbor_position);
+                if neighbor_light == 0 {
+                    continue;
+                }
+
+                if neighbor_light < light {
+                    self.set_light(neighbor_position, 0);
+                    self.removal_queue
+                        .push_back((neighbor_position, neighbor_light));
+                    let source_light = self.source_light(neighbor_position);
+                    if source_light > 0 {
+                        light_sources.push((neighbor_position, source_light));
+                    }
+                } else {
+                    self.spread_queue.push_back(neighbor_position);
+                }
+            }
+        }
+
+        // Light sources caught up in the removal still give off their own light.
+        for (position, source_light) in light_sources {
+            self.set_light(position, source_light);
+            self.spread_queue.push_back(position);
+        }
+    }
+
+    /// The light the position gives off by itself, before any light spreads to it.
+    fn source_light(&self, position: BlockPosition) -> u8 {
+        match self.channel {
+            LightChannel::Block => self
+                .block_at(position)
+                .map_or(0, |block| self.emission(&block)),
+            LightChannel::Sky => {
+                let is_open_to_sky = self
+                    .heightmaps
+                    .get(&Heightmap::column_origin(position))
+                    .is_some_and(|heightmap| {
+                        heightmap
+                            .get(position)
+                            .is_none_or(|height| position.y > height)
+                    });
+                match is_open_to_sky {
+                    true => MAX_LIGHT_LEVEL,
+                    false => 0,
+                }
+            }
+        }
+    }
+
+    fn emission(&self, block: &Block) -> u8 {
+        self.block_registry
+            .block_type_of(block)
+            .light_emission
+            .min(MAX_LIGHT_LEVEL)
+    }
+
+    /// The height of the column the position is in, `None` if it isn't loaded or has no opaque blocks.
+    fn height(&self, position: BlockPosition) -> Option<i32> {
+        self.heightmaps
+            .get(&Heightmap::column_origin(position))
+            .and_then(|heightmap| heightmap.get(position))
+    }
+
+    /// The positions in the column the position is in, from `highest_y` down to `lowest_y`,
+    /// that are in loaded chunks.
+    fn loaded_column_positions(
+        &self,
+        position: BlockPosition,
+        lowest_y: i32,
+        highest_y: i32,
+    ) -> Vec<BlockPosition> {
+        let Some(heightmap) = self.heightmaps.get(&Heightmap::column_origin(position)) else {
+            return Vec::new();
+        };
+
+        heightmap
+            .chunk_ys()
+            .range(..=highest_y)
+            .rev()
+            .flat_map(|&chunk_y| {
+                let chunk_top = chunk_y + CHUNK_BLOCK_HEIGHT as i32 - 1;
+                (chunk_y.max(lowest_y)..=chunk_top.min(highest_y)).rev()
+            })
+            .map(|y| BlockPosition::new(position.x, y, position.z))
+            .collect()
+    }
+
+    fn is_loaded(&self, position: BlockPosition) -> bool {
+        self.chunks.contains_key(&position.to_chunk_origin())
+    }
+
+    fn block_at(&self, position: BlockPosition) -> Option<Block> {
+        self.chunks
+            .get(&position.to_chunk_origin())
+            .and_then(|chunk| chunk.get_block_at_position(position).unwrap_or(None))
+    }
+
+    fn is_opaque(&self, position: BlockPosition) -> bool {
+        self.block_at(position)
+            .is_some_and(|block| self.block_registry.block_type_of(&block).is_opaque(&block))
+    }
+
+    fn light(&self, position: BlockPosition) -> u8 {
+        self.chunks
+            .get(&position.to_chunk_origin())
+            .map_or(0, |chunk| {
+                chunk
+                    .get_light_at_position(position, self.channel)
+                    .unwrap_or(0)
+            })
+    }
+
+    fn set_light(&mut self, position: BlockPosition, level: u8) {
+        let chunk_origin = position.to_chunk_origin();
+        if let Some(chunk) = self.chunks.get_mut(&chunk_origin) {
+            chunk.set_light_at_position(position, self.channel, level);
+            self.changed_chunk_origins.insert(chunk_origin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::world::{
+        block_registry::{test_registry, BlockRegistry},
+        world::World,
+    };
+
+    /// A world of two empty chunks side by side, meeting between x 15 and 16.
+    fn test_world() -> (World, Arc<BlockRegistry>) {
+        let block_registry = Arc::new(test_registry(
+            r#"(identifier: "test:glowstone", display_name: "Glowstone", light_emission: 15),"#,
+        ));
+        let chunks = [BlockPosition::new(0, 0, 0), BlockPosition::new(16, 0, 0)]
+            .into_iter()
+            .map(|chunk_origin| (chunk_origin, Chunk::empty(chunk_origin)))
+            .collect();
+        (World::new(chunks, block_registry.clone()), block_registry)
+    }
+
+    fn block_light(world: &World, x: i32) -> u8 {
+        world.get_light_at_position(BlockPosition::new(x, 5, 5), LightChannel::Block)
+    }
+
+    #[test]
+    fn block_light_spreads_across_chunk_borders() {
+        let (mut world, block_registry) = test_world();
+        let glowstone = block_registry.block("test:glowstone").unwrap();
+
+        world.set_block_at_position(BlockPosition::new(13, 5, 5), glowstone);
+        assert_eq!(block_light(&world, 13), 15);
+        assert_eq!(block_light(&world, 15), 13);
+        assert_eq!(block_light(&world, 16), 12);
+        assert_eq!(block_light(&world, 20), 8);
+        assert_eq!(
+            world.get_light_at_position(BlockPosition::new(16, 6, 4), LightChannel::Block),
+            10
+        );
+
+        world.set_block_at_position(BlockPosition::new(13, 5, 5), Block::default());
+        for x in 10..=20 {
+            assert_eq!(block_light(&world, x), 0);
+        }
+    }
+
+    #[test]
+    fn removing_a_light_source_keeps_the_light_of_the_others() {
+        let (mut world, block_registry) = test_world();
+        let glowstone = block_registry.block("test:glowstone").unwrap();
+
+        world.set_block_at_position(BlockPosition::new(13, 5, 5), glowstone);
+        world.set_block_at_position(BlockPosition::new(19, 5, 5), glowstone);
+        assert_eq!(block_light(&world, 16), 12);
+
+        world.set_block_at_position(BlockPosition::new(19, 5, 5), Block::default());
+        assert_eq!(block_light(&world, 16), 12);
+        assert_eq!(block_light(&world, 19), 9);
+
+        world.set_block_at_position(BlockPosition::new(19, 5, 5), glowstone);
+        world.set_block_at_position(BlockPosition::new(13, 5, 5), Block::default());
+        assert_eq!(block_light(&world, 13), 9);
+        assert_eq!(block_light(&world, 16), 12);
+    }
+
+    #[test]
+    fn opaque_blocks_stop_block_light() {
+        let (mut world, block_registry) = test_world();
+        let glowstone = block_registry.block("test:glowstone").unwrap();
+        let stone = block_registry.block("test:stone").unwrap();
+
+        world.set_block_at_position(BlockPosition::new(13, 5, 5), glowstone);
+        assert_eq!(block_light(&world, 14), 14);
+
+        world.set_block_at_position(BlockPosition::new(14, 5, 5), stone);
+        assert_eq!(block_light(&world, 14), 0);
+        // The light now has to go around the stone.
+        assert_eq!(block_light(&world, 15), 11);
+    }
+
+    #[test]
+    fn covering_and_uncovering_a_column_relights_it() {
+        let (mut world, block_registry) = test_world();
+        let stone = block_registry.block("test:stone").unwrap();
+        let sky_light = |world: &World, x: i32, y: i32| {
+            world.get_light_at_position(BlockPosition::new(x, y, 5), LightChannel::Sky)
+        };
+        assert_eq!(sky_light(&world, 5, 5), 15);
+
+        // A roof over the first chunk, the sky light under it only comes in from the second chunk.
+        let roof: Vec<(BlockPosition, Block)> = (0..16)
+            .flat_map(|x| (0..16).map(move |z| (BlockPosition::new(x, 10, z), stone)))
+            .collect();
+        world.set_blocks(&roof);
+        assert_eq!(sky_light(&world, 5, 11), 15);
+        assert_eq!(sky_light(&world, 16, 5), 15);
+        assert_eq!(sky_light(&world, 15, 5), 14);
+        assert_eq!(sky_light(&world, 10, 5), 9);
+        assert_eq!(sky_light(&world, 5, 0), 4);
+
+        world.set_block_at_position(BlockPosition::new(10, 10, 5), Block::default());
+        assert_eq!(sky_light(&world, 10, 5), 15);
+        assert_eq!(sky_light(&world, 10, 0), 15);
+        assert_eq!(sky_light(&world, 9, 5), 14);
+        assert_eq!(sky_light(&world, 5, 0), 10);
+
+        world.set_block_at_position(BlockPosition::new(10, 10, 5), stone);
+        assert_eq!(sky_light(&world, 10, 5), 9);
+        assert_eq!(sky_light(&world, 5, 0), 4);
+    }
+}
@@ -1,7 +1,21 @@
+pub mod biome;
 pub mod block;
+pub mod block_model;
+pub mod block_registry;
 pub mod block_position;
+pub mod block_storage;
+pub mod cave_carver;
 pub mod chunk;
+pub mod chunk_manager;
+pub mod crop_growth;
 pub mod direction;
+pub mod fluid_simulator;
+pub mod heightmap;
+pub mod light;
+pub mod region;
+pub mod seeded_random;
+pub mod structure_placer;
 pub mod world;
 pub mod world_generation_system;
+pub mod world_ticker;
 mod block_position_range;
@@ -0,0 +1,889 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use super::{
+    biome::Biome,
+    block::Block,
+    block_position::BlockPosition,
+    block_registry::BlockRegistry,
+    block_storage::BlockStorage,
+    chunk::{
+        Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH, CHUNK_COLUMN_COUNT,
+        CHUNK_SIZE,
+    },
+};
+
+/// The number of chunks along each axis of a region.
+pub const REGION_CHUNK_WIDTH: i32 = 8;
+const REGION_CHUNK_COUNT: usize =
+    (REGION_CHUNK_WIDTH * REGION_CHUNK_WIDTH * REGION_CHUNK_WIDTH) as usize;
+
+const REGION_MAGIC: &[u8; 4] = b"VOXR";
+/// Region files saved with any other version are rejected rather than read.
+const REGION_VERSION: u32 = 2;
+const REGION_HEADER_ENTRY_SIZE: u64 = 16;
+const REGION_HEADER_SIZE: u64 = 8 + REGION_CHUNK_COUNT as u64 * REGION_HEADER_ENTRY_SIZE;
+
+// The positions of scheduled ticks are saved as one byte per axis, relative to their chunk.
+const _: () =
+    assert!(CHUNK_BLOCK_WIDTH <= 256 && CHUNK_BLOCK_HEIGHT <= 256 && CHUNK_BLOCK_DEPTH <= 256);
+
+#[derive(Debug)]
+pub enum RegionError {
+    Io(std::io::Error),
+    InvalidHeader(PathBuf),
+    CorruptChunk(BlockPosition),
+    /// The chunk's palette has a block type that isn't registered.
+    UnknownBlock(BlockPosition, String),
+    /// The region file would grow past the offsets its header entries can hold.
+    TooLarge(PathBuf),
+}
+
+impl From<std::io::Error> for RegionError {
+    fn from(err: std::io::Error) -> Self {
+        RegionError::Io(err)
+    }
+}
+
+/// Where a chunk's compressed payload lives within a region file.
+/// An offset of 0 means the chunk has never been saved.
+#[derive(Clone, Copy, Default, Debug)]
+struct RegionEntry {
+    offset: u32,
+    length: u32,
+    timestamp: u64,
+}
+
+impl RegionEntry {
+    /// Returns `None` when the payload doesn't fit where the header entries can point.
+    fn new(offset: u64, length: usize, timestamp: u64) -> Option<Self> {
+        Some(RegionEntry {
+            offset: u32::try_from(offset).ok()?,
+            length: u32::try_from(length).ok()?,
+            timestamp,
+        })
+    }
+
+    fn is_present(&self) -> bool {
+        self.offset != 0
+    }
+
+    fn to_bytes(self) -> [u8; REGION_HEADER_ENTRY_SIZE as usize] {
+        let mut bytes = [0u8; REGION_HEADER_ENTRY_SIZE as usize];
+        bytes[0..4].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.length.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes
+    }
+}
+
+/// Identifies a region by the position of its lowest chunk in region units.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub struct RegionPosition {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl RegionPosition {
+    pub fn from_chunk_origin(chunk_origin: BlockPosition) -> Self {
+        let chunk_x = chunk_origin.x.div_euclid(CHUNK_BLOCK_WIDTH as i32);
+        let chunk_y = chunk_origin.y.div_euclid(CHUNK_BLOCK_HEIGHT as i32);
+        let chunk_z = chunk_origin.z.div_euclid(CHUNK_BLOCK_DEPTH as i32);
+
+        RegionPosition {
+            x: chunk_x.div_euclid(REGION_CHUNK_WIDTH),
+            y: chunk_y.div_euclid(REGION_CHUNK_WIDTH),
+            z: chunk_z.div_euclid(REGION_CHUNK_WIDTH),
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("r.{}.{}.{}.vxr", self.x, self.y, self.z)
+    }
+
+    fn chunk_index(chunk_origin: BlockPosition) -> usize {
+        let local_x = chunk_origin
+            .x
+            .div_euclid(CHUNK_BLOCK_WIDTH as i32)
+            .rem_euclid(REGION_CHUNK_WIDTH);
+        let local_y = chunk_origin
+            .y
+            .div_euclid(CHUNK_BLOCK_HEIGHT as i32)
+            .rem_euclid(REGION_CHUNK_WIDTH);
+        let local_z = chunk_origin
+            .z
+            .div_euclid(CHUNK_BLOCK_DEPTH as i32)
+            .rem_euclid(REGION_CHUNK_WIDTH);
+
+        (local_x + local_y * REGION_CHUNK_WIDTH + local_z * REGION_CHUNK_WIDTH * REGION_CHUNK_WIDTH)
+            as usize
+    }
+
+    fn chunk_origin(&self, chunk_index: usize) -> BlockPosition {
+        let chunk_index = chunk_index as i32;
+        let local_z = chunk_index / (REGION_CHUNK_WIDTH * REGION_CHUNK_WIDTH);
+        let local_y = (chunk_index / REGION_CHUNK_WIDTH) % REGION_CHUNK_WIDTH;
+        let local_x = chunk_index % REGION_CHUNK_WIDTH;
+
+        BlockPosition::new(
+            (self.x * REGION_CHUNK_WIDTH + local_x) * CHUNK_BLOCK_WIDTH as i32,
+            (self.y * REGION_CHUNK_WIDTH + local_y) * CHUNK_BLOCK_HEIGHT as i32,
+            (self.z * REGION_CHUNK_WIDTH + local_z) * CHUNK_BLOCK_DEPTH as i32,
+        )
+    }
+}
+
+/// A file holding the compressed chunks of one region.
+///
+/// The file starts with a header of the magic, the format version and one [RegionEntry]
+/// per chunk in the region, followed by the zlib compressed chunk payloads.
+///
+/// Saved chunks are appended to the end of the file and then pointed to from the header,
+/// so saving a few chunks doesn't rewrite the whole region. The payloads they replace are left
+/// behind as unused space until it outgrows the payloads in use, and then the file is compacted.
+pub struct RegionFile {
+    file: File,
+    path: PathBuf,
+    position: RegionPosition,
+    entries: Vec<RegionEntry>,
+    /// The length of the file, where the next payload is appended.
+    len: u64,
+    /// The bytes taken up by payloads that were replaced by a later save.
+    unused_len: u64,
+}
+
+impl RegionFile {
+    /// Opens the region file and reads its header. The chunk payloads are only read on demand.
+    pub fn open(path: impl AsRef<Path>, position: RegionPosition) -> Result<Self, RegionError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::options().read(true).write(true).open(&path)?;
+
+        let mut header = vec![0u8; REGION_HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| RegionError::InvalidHeader(path.clone()))?;
+
+        if &header[0..4] != REGION_MAGIC || read_u32(&header[4..8]) != REGION_VERSION {
+            return Err(RegionError::InvalidHeader(path));
+        }
+
+        let entries: Vec<RegionEntry> = header[8..]
+            .chunks_exact(REGION_HEADER_ENTRY_SIZE as usize)
+            .map(|entry| RegionEntry {
+                offset: read_u32(&entry[0..4]),
+                length: read_u32(&entry[4..8]),
+                timestamp: read_u64(&entry[8..16]),
+            })
+            .collect();
+
+        if entries
+            .iter()
+            .any(|entry| !entry.is_present() && entry.length != 0)
+        {
+            return Err(RegionError::InvalidHeader(path));
+        }
+
+        // The payloads must all lie after the header and within the file, without overlapping.
+        let mut present_entries: Vec<RegionEntry> = entries
+            .iter()
+            .copied()
+            .filter(RegionEntry::is_present)
+            .collect();
+        present_entries.sort_by_key(|entry| entry.offset);
+        let mut payloads_end = REGION_HEADER_SIZE;
+        for entry in &present_entries {
+            if (entry.offset as u64) < payloads_end {
+                return Err(RegionError::InvalidHeader(path));
+            }
+            payloads_end = entry.offset as u64 + entry.length as u64;
+        }
+
+        let len = file.metadata()?.len();
+        let used_len: u64 = present_entries
+            .iter()
+            .map(|entry| entry.length as u64)
+            .sum();
+        let Some(unused_len) = len
+            .checked_sub(REGION_HEADER_SIZE + used_len)
+            .filter(|_| payloads_end <= len)
+        else {
+            return Err(RegionError::InvalidHeader(path));
+        };
+
+        Ok(RegionFile {
+            file,
+            path,
+            position,
+            entries,
+            len,
+            unused_len,
+        })
+    }
+
+    /// Creates an empty region file at the path, replacing any file already there.
+    pub fn create(path: impl AsRef<Path>, position: RegionPosition) -> Result<Self, RegionError> {
+        let path = path.as_ref();
+        RegionFile::write_file(path, &vec![RegionEntry::default(); REGION_CHUNK_COUNT], &[])?;
+
+        RegionFile::open(path, position)
+    }
+
+    pub fn position(&self) -> RegionPosition {
+        self.position
+    }
+
+    /// The origin positions of every chunk stored in this region.
+    pub fn chunk_positions(&self) -> Vec<BlockPosition> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_present())
+            .map(|(chunk_index, _)| self.position.chunk_origin(chunk_index))
+            .collect()
+    }
+
+    /// Reads the chunk, with its scheduled ticks due relative to `current_tick`.
+    pub fn read_chunk(
+        &mut self,
+        chunk_origin: BlockPosition,
+        block_registry: &BlockRegistry,
+        current_tick: u64,
+    ) -> Result<Option<Chunk>, RegionError> {
+        let entry = self.entries[RegionPosition::chunk_index(chunk_origin)];
+        if !entry.is_present() {
+            return Ok(None);
+        }
+
+        let payload = self.read_payload(entry)?;
+        decode_chunk(chunk_origin, &payload, block_registry, current_tick).map(Some)
+    }
+
+    fn read_payload(&mut self, entry: RegionEntry) -> Result<Vec<u8>, RegionError> {
+        self.file.seek(SeekFrom::Start(entry.offset as u64))?;
+
+        let mut payload = vec![0u8; entry.length as usize];
+        self.file.read_exact(&mut payload)?;
+
+        Ok(payload)
+    }
+
+    /// Writes the chunks into the region file, keeping any chunks already stored in the file
+    /// that are not being overwritten.
+    ///
+    /// The payloads are written before the header entries pointing to them, so a failed save
+    /// leaves the chunks it didn't get to as they were.
+    ///
+    /// Scheduled ticks are written as the number of ticks left after `current_tick` until they are due,
+    /// so the world's tick count doesn't have to be saved along with them.
+    pub fn write_chunks(
+        &mut self,
+        chunks: &[&Chunk],
+        block_registry: &BlockRegistry,
+        current_tick: u64,
+    ) -> Result<(), RegionError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        let mut body = Vec::new();
+        let mut written_entries = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let payload = encode_chunk(chunk, block_registry, current_tick)?;
+            let chunk_index = RegionPosition::chunk_index(chunk.origin_position());
+            let entry = RegionEntry::new(self.len + body.len() as u64, payload.len(), timestamp)
+                .ok_or_else(|| RegionError::TooLarge(self.path.clone()))?;
+            body.extend_from_slice(&payload);
+            written_entries.push((chunk_index, entry));
+        }
+
+        self.file.seek(SeekFrom::Start(self.len))?;
+        self.file.write_all(&body)?;
+        self.file.sync_data()?;
+        self.len += body.len() as u64;
+
+        for (chunk_index, entry) in written_entries {
+            self.unused_len += self.entries[chunk_index].length as u64;
+            self.entries[chunk_index] = entry;
+
+            let entry_offset = 8 + chunk_index as u64 * REGION_HEADER_ENTRY_SIZE;
+            self.file.seek(SeekFrom::Start(entry_offset))?;
+            self.file.write_all(&entry.to_bytes())?;
+        }
+        self.file.sync_data()?;
+
+        let used_len = self
+            .len
+            .checked_sub(REGION_HEADER_SIZE + self.unused_len)
+            .ok_or_else(|| RegionError::InvalidHeader(self.path.clone()))?;
+        if self.unused_len > used_len {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites the file without the payloads no longer in use.
+    ///
+    /// The region is written to a temporary file first and then moved over the old file,
+    /// so a failed compaction never leaves a half written region behind.
+    fn compact(&mut self) -> Result<(), RegionError> {
+        let mut entries = vec![RegionEntry::default(); REGION_CHUNK_COUNT];
+        let mut body = Vec::new();
+        for (chunk_index, entry) in self.entries.clone().into_iter().enumerate() {
+            if entry.is_present() {
+                let payload = self.read_payload(entry)?;
+                entries[chunk_index] = RegionEntry::new(
+                    REGION_HEADER_SIZE + body.len() as u64,
+                    payload.len(),
+                    entry.timestamp,
+                )
+                .ok_or_else(|| RegionError::TooLarge(self.path.clone()))?;
+                body.extend_from_slice(&payload);
+            }
+        }
+
+        RegionFile::write_file(&self.path, &entries, &body)?;
+        *self = RegionFile::open(&self.path, self.position)?;
+
+        Ok(())
+    }
+
+    /// Writes a whole region file through a temporary file.
+    fn write_file(path: &Path, entries: &[RegionEntry], body: &[u8]) -> Result<(), RegionError> {
+        let mut header = Vec::with_capacity(REGION_HEADER_SIZE as usize);
+        header.extend_from_slice(REGION_MAGIC);
+        header.extend_from_slice(&REGION_VERSION.to_le_bytes());
+        for entry in entries {
+            header.extend_from_slice(&entry.to_bytes());
+        }
+
+        let temporary_path = path.with_extension("vxr.tmp");
+        {
+            let mut file = File::create(&temporary_path)?;
+            file.write_all(&header)?;
+            file.write_all(body)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temporary_path, path)?;
+
+        Ok(())
+    }
+}
+
+/// The most region files kept open by a [RegionStorage] at once.
+const MAX_OPEN_REGION_FILES: usize = 64;
+
+/// A directory of region files that chunks can be lazily loaded from and saved to.
+///
+/// Region files are kept open once they are first used, so loading or saving
+/// the chunks of a region doesn't reopen and reread its header every time.
+pub struct RegionStorage {
+    directory: PathBuf,
+    /// Maps the block identifiers saved in the chunk palettes to and from block ids.
+    block_registry: Arc<BlockRegistry>,
+    /// The region files opened so far, `None` for regions that don't have a file yet.
+    region_files: HashMap<RegionPosition, Option<RegionFile>>,
+}
+
+impl RegionStorage {
+    pub fn new(directory: impl AsRef<Path>, block_registry: Arc<BlockRegistry>) -> Self {
+        RegionStorage {
+            directory: directory.as_ref().to_path_buf(),
+            block_registry,
+            region_files: HashMap::new(),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn region_path(&self, position: RegionPosition) -> PathBuf {
+        self.directory.join(position.file_name())
+    }
+
+    /// The open region file of the region, opening it if it exists and isn't open yet.
+    fn region_file(
+        &mut self,
+        position: RegionPosition,
+    ) -> Result<Option<&mut RegionFile>, RegionError> {
+        if !self.region_files.contains_key(&position) {
+            // Closes a region file to make room, the region is reopened if it is used again.
+            if self.region_files.len() >= MAX_OPEN_REGION_FILES {
+                let closed_position = *self.region_files.keys().next().unwrap();
+                self.region_files.remove(&closed_position);
+            }
+
+            let path = self.region_path(position);
+            let region_file = match path.exists() {
+                true => Some(RegionFile::open(path, position)?),
+                false => None,
+            };
+            self.region_files.insert(position, region_file);
+        }
+
+        Ok(self.region_files.get_mut(&position).unwrap().as_mut())
+    }
+
+    /// Loads a single chunk, returning `None` if it has never been saved.
+    /// Its scheduled ticks are due relative to `current_tick`.
+    pub fn load_chunk(
+        &mut self,
+        chunk_origin: BlockPosition,
+        current_tick: u64,
+    ) -> Result<Option<Chunk>, RegionError> {
+        let block_registry = self.block_registry.clone();
+        match self.region_file(RegionPosition::from_chunk_origin(chunk_origin))? {
+            Some(region_file) => {
+                region_file.read_chunk(chunk_origin, &block_registry, current_tick)
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_chunks<'a>(
+        &mut self,
+        chunks: impl IntoIterator<Item = &'a Chunk>,
+        current_tick: u64,
+    ) -> Result<(), RegionError> {
+        let mut chunks_by_region: HashMap<RegionPosition, Vec<&Chunk>> = HashMap::new();
+        for chunk in chunks {
+            chunks_by_region
+                .entry(RegionPosition::from_chunk_origin(chunk.origin_position()))
+                .or_default()
+                .push(chunk);
+        }
+
+        if !chunks_by_region.is_empty() {
+            fs::create_dir_all(&self.directory)?;
+        }
+
+        let block_registry = self.block_registry.clone();
+        for (position, chunks) in chunks_by_region {
+            if self.region_file(position)?.is_none() {
+                let region_file = RegionFile::create(self.region_path(position), position)?;
+                self.region_files.insert(position, Some(region_file));
+            }
+
+            let region_file = self.region_file(position)?.unwrap();
+            region_file.write_chunks(&chunks, &block_registry, current_tick)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Serializes the chunk's palette, block indices, biomes and scheduled ticks and compresses them.
+///
+/// Palette entries are saved by their block type's identifier rather than its id,
+/// so saves keep working when block types are added to the registry.
+fn encode_chunk(
+    chunk: &Chunk,
+    block_registry: &BlockRegistry,
+    current_tick: u64,
+) -> Result<Vec<u8>, RegionError> {
+    let mut bytes = Vec::new();
+
+    let origin = chunk.origin_position();
+    bytes.extend_from_slice(&origin.x.to_le_bytes());
+    bytes.extend_from_slice(&origin.y.to_le_bytes());
+    bytes.extend_from_slice(&origin.z.to_le_bytes());
+
+    let palette = chunk.blocks().palette();
+    bytes.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+    for block in palette {
+        let identifier = block_registry.block_type_of(block).identifier.as_bytes();
+        bytes.extend_from_slice(&(identifier.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(identifier);
+        bytes.extend_from_slice(&block.state.to_le_bytes());
+    }
+
+    match chunk.blocks() {
+        BlockStorage::Single { .. } => bytes.push(0),
+        BlockStorage::Paletted {
+            bits_per_index,
+            data,
+            ..
+        } => {
+            bytes.push(*bits_per_index as u8);
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            for word in data {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+    }
+
+    bytes.extend(chunk.biomes().iter().map(|biome| *biome as u8));
+
+    bytes.extend_from_slice(&(chunk.scheduled_ticks().len() as u32).to_le_bytes());
+    for (position, due_tick) in chunk.scheduled_ticks() {
+        let local_position = position.to_chunk_local_position();
+        bytes.push(local_position.x as u8);
+        bytes.push(local_position.y as u8);
+        bytes.push(local_position.z as u8);
+
+        let delay = due_tick.saturating_sub(current_tick).min(u32::MAX as u64) as u32;
+        bytes.extend_from_slice(&delay.to_le_bytes());
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&bytes)?;
+
+    Ok(encoder.finish()?)
+}
+
+fn decode_chunk(
+    chunk_origin: BlockPosition,
+    payload: &[u8],
+    block_registry: &BlockRegistry,
+    current_tick: u64,
+) -> Result<Chunk, RegionError> {
+    let mut bytes = Vec::new();
+    ZlibDecoder::new(payload)
+        .read_to_end(&mut bytes)
+        .map_err(|_| RegionError::CorruptChunk(chunk_origin))?;
+
+    let mut reader = PayloadReader {
+        bytes: &bytes,
+        chunk_origin,
+    };
+
+    let origin = BlockPosition::new(
+        reader.read_u32()? as i32,
+        reader.read_u32()? as i32,
+        reader.read_u32()? as i32,
+    );
+    if origin != chunk_origin {
+        return Err(RegionError::CorruptChunk(chunk_origin));
+    }
+
+    let palette_len = reader.read_u32()? as usize;
+    let mut palette = Vec::with_capacity(palette_len);
+    for _ in 0..palette_len {
+        let identifier_len = reader.read_u16()? as usize;
+        let identifier = std::str::from_utf8(reader.take(identifier_len)?)
+            .map_err(|_| RegionError::CorruptChunk(chunk_origin))?;
+        let block = block_registry
+            .block(identifier)
+            .ok_or_else(|| RegionError::UnknownBlock(chunk_origin, identifier.to_owned()))?;
+
+        palette.push(Block {
+            state: reader.read_u32()?,
+            ..block
+        });
+    }
+
+    let bits_per_index = reader.read_u8()? as usize;
+    let blocks = if bits_per_index == 0 {
+        let block = *palette
+            .first()
+            .ok_or(RegionError::CorruptChunk(chunk_origin))?;
+        BlockStorage::filled(block, CHUNK_SIZE)
+    } else {
+        let data_len = reader.read_u32()? as usize;
+        let mut data = Vec::with_capacity(data_len);
+        for _ in 0..data_len {
+            data.push(reader.read_u64()?);
+        }
+
+        BlockStorage::from_parts(palette, bits_per_index, data, CHUNK_SIZE)
+            .ok_or(RegionError::CorruptChunk(chunk_origin))?
+    };
+
+    let mut chunk = Chunk::with_blocks(chunk_origin, blocks);
+
+    let mut biomes = [Biome::default(); CHUNK_COLUMN_COUNT];
+    for biome in biomes.iter_mut() {
+        *biome =
+            Biome::from_repr(reader.read_u8()?).ok_or(RegionError::CorruptChunk(chunk_origin))?;
+    }
+    chunk.set_biomes(biomes);
+
+    let scheduled_tick_count = reader.read_u32()?;
+    for _ in 0..scheduled_tick_count {
+        let local_position = BlockPosition::new(
+            reader.read_u8()? as i32,
+            reader.read_u8()? as i32,
+            reader.read_u8()? as i32,
+        );
+        let is_within_chunk = local_position.x < CHUNK_BLOCK_WIDTH as i32
+            && local_position.y < CHUNK_BLOCK_HEIGHT as i32
+            && local_position.z < CHUNK_BLOCK_DEPTH as i32;
+        if !is_within_chunk {
+            return Err(RegionError::CorruptChunk(chunk_origin));
+        }
+
+        let delay = reader.read_u32()? as u64;
+        chunk.schedule_tick(chunk_origin + local_position, current_tick + delay);
+    }
+
+    Ok(chunk)
+}
+
+struct PayloadReader<'a> {
+    bytes: &'a [u8],
+    chunk_origin: BlockPosition,
+}
+
+impl<'a> PayloadReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RegionError> {
+        if self.bytes.len() < len {
+            return Err(RegionError::CorruptChunk(self.chunk_origin));
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, RegionError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, RegionError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, RegionError> {
+        Ok(read_u32(self.take(4)?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, RegionError> {
+        Ok(read_u64(self.take(8)?))
+    }
+}
+
+#[inline]
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[0..4].try_into().unwrap())
+}
+
+#[inline]
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[0..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        block::{Axis, AxisProperty},
+        block_registry::test_registry,
+    };
+
+    const LOG: &str = r#"(identifier: "test:log", display_name: "Log", state_properties: [Axis]),"#;
+
+    /// The same blocks with another block type registered before the log, which shifts its id.
+    fn shifted_test_registry() -> BlockRegistry {
+        test_registry(&format!(
+            r#"(identifier: "test:dirt", display_name: "Dirt"),
+            {LOG}"#
+        ))
+    }
+
+    fn test_chunk(block_registry: &BlockRegistry) -> Chunk {
+        let stone = block_registry.block("test:stone").unwrap();
+        let log = block_registry.block("test:log").unwrap();
+        let log = block_registry
+            .block_type_of(&log)
+            .with_state::<AxisProperty>(log, Axis::Z)
+            .unwrap();
+
+        let mut chunk = Chunk::empty(BlockPosition::new(-16, 32, 0));
+        chunk.set_block_at_position(BlockPosition::new(-16, 32, 0), stone);
+        chunk.set_block_at_position(BlockPosition::new(-1, 47, 15), log);
+        chunk.set_biome_at_position(BlockPosition::new(-10, 32, 4), Biome::Desert);
+        chunk.schedule_tick(BlockPosition::new(-1, 47, 15), 105);
+        chunk
+    }
+
+    fn assert_same_blocks(
+        chunk: &Chunk,
+        block_registry: &BlockRegistry,
+        decoded: &Chunk,
+        decoded_block_registry: &BlockRegistry,
+    ) {
+        for index in 0..CHUNK_SIZE {
+            let block = chunk.blocks().get(index);
+            let decoded_block = decoded.blocks().get(index);
+            assert_eq!(
+                block_registry.block_type_of(&block).identifier,
+                decoded_block_registry
+                    .block_type_of(&decoded_block)
+                    .identifier
+            );
+            assert_eq!(block.state, decoded_block.state);
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let block_registry = test_registry(LOG);
+        let chunk = test_chunk(&block_registry);
+
+        let payload = encode_chunk(&chunk, &block_registry, 100).unwrap();
+        // Loaded in a later tick, the scheduled tick is still 5 ticks away.
+        let decoded =
+            decode_chunk(chunk.origin_position(), &payload, &block_registry, 200).unwrap();
+
+        assert_same_blocks(&chunk, &block_registry, &decoded, &block_registry);
+        assert_eq!(chunk.biomes(), decoded.biomes());
+        assert_eq!(
+            decoded
+                .scheduled_ticks()
+                .get(&BlockPosition::new(-1, 47, 15)),
+            Some(&205)
+        );
+    }
+
+    #[test]
+    fn chunks_load_after_block_ids_change() {
+        let block_registry = test_registry(LOG);
+        let shifted_block_registry = shifted_test_registry();
+        let chunk = test_chunk(&block_registry);
+
+        let payload = encode_chunk(&chunk, &block_registry, 0).unwrap();
+        let decoded = decode_chunk(
+            chunk.origin_position(),
+            &payload,
+            &shifted_block_registry,
+            0,
+        )
+        .unwrap();
+
+        assert_same_blocks(&chunk, &block_registry, &decoded, &shifted_block_registry);
+    }
+
+    #[test]
+    fn unregistered_blocks_fail_to_load() {
+        let block_registry = shifted_test_registry();
+        let dirt = block_registry.block("test:dirt").unwrap();
+        let mut chunk = Chunk::empty(BlockPosition::new(0, 0, 0));
+        chunk.set_block_at_position(BlockPosition::new(1, 2, 3), dirt);
+
+        let payload = encode_chunk(&chunk, &block_registry, 0).unwrap();
+        let result = decode_chunk(chunk.origin_position(), &payload, &test_registry(LOG), 0);
+
+        assert!(matches!(
+            result,
+            Err(RegionError::UnknownBlock(_, identifier)) if identifier == "test:dirt"
+        ));
+    }
+
+    #[test]
+    fn region_files_keep_the_chunks_they_were_not_given() {
+        let block_registry = test_registry(LOG);
+        let directory =
+            std::env::temp_dir().join(format!("vox-region-test-{}", std::process::id()));
+        let path = directory.join("r.0.0.0.vxr");
+        fs::create_dir_all(&directory).unwrap();
+
+        let stone = block_registry.block("test:stone").unwrap();
+        let first = Chunk::filled(BlockPosition::new(0, 0, 0), stone);
+        let second = Chunk::filled(BlockPosition::new(16, 0, 32), stone);
+        let position = RegionPosition::from_chunk_origin(first.origin_position());
+        let mut region_file = RegionFile::create(&path, position).unwrap();
+        region_file
+            .write_chunks(&[&first], &block_registry, 0)
+            .unwrap();
+        region_file
+            .write_chunks(&[&second], &block_registry, 0)
+            .unwrap();
+
+        // Saving the same chunk over and over compacts the file instead of growing it.
+        let len = fs::metadata(&path).unwrap().len();
+        for _ in 0..10 {
+            region_file
+                .write_chunks(&[&first], &block_registry, 0)
+                .unwrap();
+        }
+        assert!(fs::metadata(&path).unwrap().len() <= 2 * len);
+
+        let mut region_file = RegionFile::open(&path, position).unwrap();
+        let mut chunk_positions = region_file.chunk_positions();
+        chunk_positions.sort_by_key(|position| (position.x, position.y, position.z));
+        assert_eq!(
+            chunk_positions,
+            [first.origin_position(), second.origin_position()]
+        );
+        for chunk in [&first, &second] {
+            let read = region_file
+                .read_chunk(chunk.origin_position(), &block_registry, 0)
+                .unwrap()
+                .unwrap();
+            assert_same_blocks(chunk, &block_registry, &read, &block_registry);
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn region_files_of_other_versions_are_rejected() {
+        let directory =
+            std::env::temp_dir().join(format!("vox-region-version-test-{}", std::process::id()));
+        let path = directory.join("r.0.0.0.vxr");
+        fs::create_dir_all(&directory).unwrap();
+
+        let mut header = vec![0u8; REGION_HEADER_SIZE as usize];
+        header[0..4].copy_from_slice(REGION_MAGIC);
+        header[4..8].copy_from_slice(&1u32.to_le_bytes());
+        fs::write(&path, header).unwrap();
+
+        let result = RegionFile::open(&path, RegionPosition { x: 0, y: 0, z: 0 });
+        assert!(matches!(result, Err(RegionError::InvalidHeader(_))));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn region_files_with_corrupt_entries_are_rejected() {
+        let directory =
+            std::env::temp_dir().join(format!("vox-region-corrupt-test-{}", std::process::id()));
+        let path = directory.join("r.0.0.0.vxr");
+        fs::create_dir_all(&directory).unwrap();
+
+        let payload_offset = REGION_HEADER_SIZE as u32;
+        let corrupt_entries = [
+            // A payload inside the header.
+            vec![(0, 8, 4)],
+            // Two payloads overlapping each other.
+            vec![(0, payload_offset, 64), (1, payload_offset + 32, 64)],
+            // An entry for a chunk that was never saved, with a length.
+            vec![(0, 0, 64)],
+            // A payload past the end of the file.
+            vec![(0, payload_offset + 64, 128)],
+        ];
+        for entries in corrupt_entries {
+            let mut file = Vec::with_capacity(REGION_HEADER_SIZE as usize + 128);
+            file.extend_from_slice(REGION_MAGIC);
+            file.extend_from_slice(&REGION_VERSION.to_le_bytes());
+            file.resize(REGION_HEADER_SIZE as usize + 128, 0);
+            for (chunk_index, offset, length) in entries {
+                let entry = RegionEntry {
+                    offset,
+                    length,
+                    timestamp: 0,
+                };
+                let entry_offset = 8 + chunk_index * REGION_HEADER_ENTRY_SIZE as usize;
+                file[entry_offset..entry_offset + REGION_HEADER_ENTRY_SIZE as usize]
+                    .copy_from_slice(&entry.to_bytes());
+            }
+            fs::write(&path, file).unwrap();
+
+            let result = RegionFile::open(&path, RegionPosition { x: 0, y: 0, z: 0 });
+            assert!(matches!(result, Err(RegionError::InvalidHeader(_))));
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
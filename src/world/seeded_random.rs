@@ -0,0 +1,92 @@
+use super::block_position::BlockPosition;
+
+/// A small, fast pseudo random number generator (SplitMix64).
+///
+/// World generation seeds one of these from the world seed and a position with
+/// [SeededRandom::at_position], so the numbers drawn for a position never depend on
+/// the order positions are generated in.
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom { state: seed }
+    }
+
+    /// Creates a generator unique to the seed, position and salt.
+    /// Different salts give independent streams for different generation features.
+    pub fn at_position(seed: u64, position: BlockPosition, salt: u64) -> Self {
+        let mut hash = seed ^ salt.wrapping_mul(0xD6E8_FEB8_6659_FD93);
+        for coordinate in [position.x, position.y, position.z] {
+            hash = SeededRandom::mix(hash ^ coordinate as u32 as u64);
+        }
+
+        SeededRandom::new(hash)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        SeededRandom::mix(self.state)
+    }
+
+    /// A float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A float in `[min, max)`.
+    pub fn range_f64(&mut self, min: f64, max: f64) -> f64 {
+        min + self.next_f64() * (max - min)
+    }
+
+    /// An integer in `[min, max)`, or `min` if the range is empty.
+    /// A number is drawn either way, so an empty range doesn't shift the numbers drawn after it.
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        let value = self.next_u64();
+        if max <= min {
+            return min;
+        }
+
+        let range = (max as i64 - min as i64) as u64;
+        (min as i64 + (value % range) as i64) as i32
+    }
+
+    fn mix(mut value: u64) -> u64 {
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        value ^ (value >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_i32_stays_in_range() {
+        let mut random = SeededRandom::new(7);
+        for _ in 0..1000 {
+            let value = random.range_i32(-3, 4);
+            assert!((-3..4).contains(&value));
+        }
+
+        for _ in 0..1000 {
+            let value = random.range_i32(i32::MIN, i32::MAX);
+            assert!(value < i32::MAX);
+        }
+    }
+
+    #[test]
+    fn range_i32_returns_min_for_empty_ranges() {
+        let mut random = SeededRandom::new(7);
+        assert_eq!(random.range_i32(5, 5), 5);
+        assert_eq!(random.range_i32(5, 2), 5);
+
+        // Empty ranges still draw a number, so the numbers after them are the same.
+        let mut other = SeededRandom::new(7);
+        other.next_u64();
+        other.next_u64();
+        assert_eq!(random.next_u64(), other.next_u64());
+    }
+}
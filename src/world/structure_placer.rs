@@ -0,0 +1,370 @@
+use std::ops::{Range, RangeInclusive};
+
+use super::{
+    biome::{Biome, DecorationKind},
+    block::{Axis, AxisProperty, Block},
+    block_position::BlockPosition,
+    block_registry::BlockRegistry,
+    chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    seeded_random::SeededRandom,
+};
+
+const STRUCTURE_SALT: u64 = 0x57C0_C7E5;
+
+/// How far a structure reaches sideways from the column it stands on, the widest ruins.
+const STRUCTURE_HORIZONTAL_REACH: i32 = 6;
+/// How far a structure reaches above its surface, the tallest trees.
+const STRUCTURE_MAX_HEIGHT: i32 = 9;
+/// How far a structure reaches below its surface, the boulders sunk into the ground.
+const STRUCTURE_MAX_DEPTH: i32 = 3;
+
+/// A block written by a structure, in world space.
+pub type StructureBlock = (BlockPosition, Block);
+
+/// The terrain surface of a column, before caves are carved.
+pub struct SurfaceColumn {
+    pub height: i32,
+    pub biome: Biome,
+    /// The topmost terrain block of the column.
+    pub block: Block,
+}
+
+/// Decides where the biome decorations (trees, boulders, ruins, ...) go and builds them.
+///
+/// Whether a column holds a structure, and what the structure looks like, only depends on
+/// the seed and the column's terrain surface. Structures are allowed to reach into neighbouring
+/// chunks, so every chunk builds the structures of the columns around it too and keeps the blocks
+/// that fall inside of it. This way a structure is the same whichever chunk is generated first,
+/// and nothing has to be kept around for chunks that aren't generated yet.
+pub struct StructurePlacer {
+    seed: u64,
+    sea_level: i32,
+
+    log: Block,
+    leaves: Block,
+    cactus: Block,
+    cobblestone: Block,
+    stone: Block,
+    grass: Block,
+    sand: Block,
+    water: Block,
+}
+
+impl StructurePlacer {
+    pub fn new(seed: u64, sea_level: i32, block_registry: &BlockRegistry) -> Self {
+        let block = |identifier: &str| {
+            block_registry
+                .block(identifier)
+                .unwrap_or_else(|| panic!("{identifier} is not registered"))
+        };
+
+        let log = block("vox:log");
+        let log = block_registry
+            .block_type(log.id)
+            .and_then(|log_type| log_type.with_state::<AxisProperty>(log, Axis::Y).ok())
+            .unwrap_or(log);
+
+        StructurePlacer {
+            seed,
+            sea_level,
+            log,
+            leaves: block("vox:leaves"),
+            cactus: block("vox:cactus"),
+            cobblestone: block("vox:cobblestone"),
+            stone: block("vox:stone"),
+            grass: block("vox:grass"),
+            sand: block("vox:sand"),
+            water: block("vox:water"),
+        }
+    }
+
+    /// The surface heights of the columns whose structures can reach into the chunk.
+    /// Columns below sea level are under water and never hold a structure.
+    fn reaching_surface_heights(&self, chunk_origin: BlockPosition) -> Range<i32> {
+        let min_surface_height = (chunk_origin.y - STRUCTURE_MAX_HEIGHT).max(self.sea_level);
+        min_surface_height..chunk_origin.y + CHUNK_BLOCK_HEIGHT as i32 + STRUCTURE_MAX_DEPTH
+    }
+
+    /// Whether any structure can reach into the chunk when the terrain surface stays within
+    /// `surface_heights`, so chunks far above or below the surface can skip placing structures.
+    pub fn can_reach_chunk(
+        &self,
+        chunk_origin: BlockPosition,
+        surface_heights: &RangeInclusive<i32>,
+    ) -> bool {
+        let reaching_surface_heights = self.reaching_surface_heights(chunk_origin);
+        reaching_surface_heights.start <= *surface_heights.end()
+            && *surface_heights.start() < reaching_surface_heights.end
+    }
+
+    /// Writes the blocks of every structure that reaches into the chunk, standing on a column
+    /// in or around it. `surface_column` gives the terrain surface of the column at an x and z position.
+    ///
+    /// Structures only ever replace air, so they never cut into terrain or into each other.
+    /// They are built in order of the column they stand on, so where two structures overlap
+    /// the same one always wins.
+    pub fn place_structures(
+        &self,
+        chunk: &mut Chunk,
+        surface_column: impl Fn(i32, i32) -> SurfaceColumn,
+    ) {
+        let origin_position = chunk.origin_position();
+        let reaching_surface_heights = self.reaching_surface_heights(origin_position);
+
+        let mut structure_blocks = Vec::new();
+        for x in -STRUCTURE_HORIZONTAL_REACH..CHUNK_BLOCK_WIDTH as i32 + STRUCTURE_HORIZONTAL_REACH
+        {
+            for z in
+                -STRUCTURE_HORIZONTAL_REACH..CHUNK_BLOCK_DEPTH as i32 + STRUCTURE_HORIZONTAL_REACH
+            {
+                let column = surface_column(origin_position.x + x, origin_position.z + z);
+                if !reaching_surface_heights.contains(&column.height) {
+                    continue;
+                }
+
+                let surface_position =
+                    BlockPosition::new(origin_position.x + x, column.height, origin_position.z + z);
+                self.build_structure(surface_position, &column, &mut structure_blocks);
+            }
+        }
+
+        for (position, block) in structure_blocks {
+            if let Ok(None) = chunk.get_block_at_position(position) {
+                chunk.set_block_at_position(position, block);
+            }
+        }
+    }
+
+    /// Builds the structure standing on the surface of the column, if it has one.
+    fn build_structure(
+        &self,
+        surface_position: BlockPosition,
+        column: &SurfaceColumn,
+        structure_blocks: &mut Vec<StructureBlock>,
+    ) {
+        let Some(kind) = self.decoration_at(surface_position, column.biome) else {
+            return;
+        };
+        if !self.can_stand_on(kind, column.block) {
+            return;
+        }
+
+        let mut random = SeededRandom::at_position(self.seed, surface_position, STRUCTURE_SALT + 1);
+        let structure_origin = surface_position.offset_y(1);
+        match kind {
+            DecorationKind::Tree => {
+                self.build_tree(structure_origin, &mut random, structure_blocks)
+            }
+            DecorationKind::Boulder => {
+                self.build_boulder(structure_origin, &mut random, structure_blocks)
+            }
+            DecorationKind::Ruin => {
+                self.build_ruin(structure_origin, &mut random, structure_blocks)
+            }
+            DecorationKind::Cactus => {
+                self.build_cactus(structure_origin, &mut random, structure_blocks)
+            }
+        }
+    }
+
+    /// Rolls each of the biome's decorations for the column, a column holds at most one.
+    fn decoration_at(
+        &self,
+        surface_position: BlockPosition,
+        biome: Biome,
+    ) -> Option<DecorationKind> {
+        let column_position = BlockPosition::new(surface_position.x, 0, surface_position.z);
+        let mut random = SeededRandom::at_position(self.seed, column_position, STRUCTURE_SALT);
+
+        biome
+            .parameters()
+            .decorations
+            .iter()
+            .find(|decoration| random.next_f64() < decoration.chance_per_column)
+            .map(|decoration| decoration.kind)
+    }
+
+    fn can_stand_on(&self, kind: DecorationKind, surface_block: Block) -> bool {
+        match kind {
+            DecorationKind::Tree => surface_block.id == self.grass.id,
+            DecorationKind::Cactus => surface_block.id == self.sand.id,
+            DecorationKind::Boulder | DecorationKind::Ruin => surface_block.id != self.water.id,
+        }
+    }
+
+    fn build_tree(
+        &self,
+        origin: BlockPosition,
+        random: &mut SeededRandom,
+        structure_blocks: &mut Vec<StructureBlock>,
+    ) {
+        let trunk_height = random.range_i32(4, 7);
+        let canopy_radius = random.range_i32(2, 4);
+
+        // The trunk goes first so the canopy doesn't replace it, structures only write into air.
+        for y in 0..trunk_height {
+            structure_blocks.push((origin.offset_y(y), self.log));
+        }
+
+        let canopy_center = origin.offset_y(trunk_height - 1);
+        for x in -canopy_radius..=canopy_radius {
+            for y in -1..=canopy_radius {
+                for z in -canopy_radius..=canopy_radius {
+                    let distance_squared = x * x + y * y * 2 + z * z;
+                    if distance_squared <= canopy_radius * canopy_radius + 1 {
+                        structure_blocks.push((canopy_center.offset(x, y, z), self.leaves));
+                    }
+                }
+            }
+        }
+    }
+
+    fn build_boulder(
+        &self,
+        origin: BlockPosition,
+        random: &mut SeededRandom,
+        structure_blocks: &mut Vec<StructureBlock>,
+    ) {
+        let radius = random.range_f64(1.5, 3.0);
+        let extent = radius.ceil() as i32;
+
+        for x in -extent..=extent {
+            for y in -extent..=extent {
+                for z in -extent..=extent {
+                    let roughness = random.range_f64(-0.5, 0.5);
+                    let distance = ((x * x + y * y + z * z) as f64).sqrt();
+                    if distance + roughness <= radius {
+                        let block = if random.next_f64() < 0.3 {
+                            self.cobblestone
+                        } else {
+                            self.stone
+                        };
+                        structure_blocks.push((origin.offset(x, y - 1, z), block));
+                    }
+                }
+            }
+        }
+    }
+
+    fn build_ruin(
+        &self,
+        origin: BlockPosition,
+        random: &mut SeededRandom,
+        structure_blocks: &mut Vec<StructureBlock>,
+    ) {
+        let half_width = random.range_i32(3, 7);
+        let half_depth = random.range_i32(3, 7);
+        let wall_height = random.range_i32(2, 5);
+
+        for x in -half_width..=half_width {
+            for z in -half_depth..=half_depth {
+                let is_wall = x.abs() == half_width || z.abs() == half_depth;
+                if !is_wall {
+                    continue;
+                }
+
+                // Ruined walls crumble towards the top.
+                let height = random.range_i32(0, wall_height + 1);
+                for y in 0..height {
+                    structure_blocks.push((origin.offset(x, y, z), self.cobblestone));
+                }
+            }
+        }
+    }
+
+    fn build_cactus(
+        &self,
+        origin: BlockPosition,
+        random: &mut SeededRandom,
+        structure_blocks: &mut Vec<StructureBlock>,
+    ) {
+        for y in 0..random.range_i32(1, 4) {
+            structure_blocks.push((origin.offset_y(y), self.cactus));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::world::block_registry::game_registry;
+
+    const SEA_LEVEL: i32 = 64;
+    const SURFACE_HEIGHT: i32 = 74;
+    const CHUNKS: i32 = 3;
+
+    #[test]
+    fn structures_are_built_into_every_chunk_they_reach() {
+        let registry = game_registry();
+        let structure_placer = StructurePlacer::new(5, SEA_LEVEL, &registry);
+        let grass = registry.block("vox:grass").unwrap();
+        let surface_column = |_, _| SurfaceColumn {
+            height: SURFACE_HEIGHT,
+            biome: Biome::Forest,
+            block: grass,
+        };
+
+        // Builds every structure once, in the order of the columns they stand on.
+        let mut expected_blocks = HashMap::new();
+        let area = 0..CHUNKS * CHUNK_BLOCK_WIDTH as i32;
+        for x in -STRUCTURE_HORIZONTAL_REACH..area.end + STRUCTURE_HORIZONTAL_REACH {
+            for z in -STRUCTURE_HORIZONTAL_REACH..area.end + STRUCTURE_HORIZONTAL_REACH {
+                let mut structure_blocks = Vec::new();
+                structure_placer.build_structure(
+                    BlockPosition::new(x, SURFACE_HEIGHT, z),
+                    &surface_column(x, z),
+                    &mut structure_blocks,
+                );
+
+                for (position, block) in structure_blocks {
+                    if area.contains(&position.x) && area.contains(&position.z) {
+                        expected_blocks.entry(position).or_insert(block);
+                    }
+                }
+            }
+        }
+
+        let mut placed_blocks = HashMap::new();
+        for chunk_x in 0..CHUNKS {
+            for chunk_y in [64, 80] {
+                for chunk_z in 0..CHUNKS {
+                    let chunk_origin = BlockPosition::new(
+                        chunk_x * CHUNK_BLOCK_WIDTH as i32,
+                        chunk_y,
+                        chunk_z * CHUNK_BLOCK_DEPTH as i32,
+                    );
+                    let mut chunk = Chunk::empty(chunk_origin);
+                    structure_placer.place_structures(&mut chunk, surface_column);
+
+                    for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+                        for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                                let position = chunk_origin.offset(x, y, z);
+                                if let Ok(Some(block)) = chunk.get_block_at_position(position) {
+                                    placed_blocks.insert(position, block);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        assert!(!placed_blocks.is_empty());
+        assert_eq!(placed_blocks, expected_blocks);
+
+        // Some structures cross from one chunk into the next, sideways and upwards.
+        let crosses_border = |offset: BlockPosition| {
+            placed_blocks.keys().any(|position| {
+                let next_position = *position + offset;
+                position.to_chunk_origin() != next_position.to_chunk_origin()
+                    && placed_blocks.contains_key(&next_position)
+            })
+        };
+        assert!(crosses_border(BlockPosition::new(1, 0, 0)));
+        assert!(crosses_border(BlockPosition::new(0, 0, 1)));
+        assert!(crosses_border(BlockPosition::new(0, 1, 0)));
+    }
+}
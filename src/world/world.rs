@@ -1,19 +1,447 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
 
+use super::{
+    biome::Biome,
+    block::Block,
+    block_position::BlockPosition,
+    block_registry::BlockRegistry,
+    chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    direction::Direction,
+    heightmap::Heightmap,
+    light::{LightChannel, LightPropagator},
+    region::{RegionError, RegionStorage},
+};
 
-
-use super::{block::Block, block_position::BlockPosition, chunk::Chunk};
+/// Where a ray cast with [World::raycast] hit a block.
+#[derive(Clone, Copy, Debug)]
+pub struct RaycastHit {
+    pub block_position: BlockPosition,
+    pub block: Block,
+    /// The face of the block the ray entered through.
+    /// If the ray started inside the block, this is the face pointing back along the ray.
+    pub face: Direction,
+    pub point: glam::Vec3,
+    pub distance: f32,
+}
 
 pub struct World {
-    // TODO (Michael): Later we want to move this to a chunk pool when we do loading / unloading
+    /// The loaded chunks, see [ChunkManager](super::chunk_manager::ChunkManager) for loading
+    /// and unloading them around the camera.
     pub chunks: HashMap<BlockPosition, Chunk>,
+    block_registry: Arc<BlockRegistry>,
+    /// The heightmap of every column of chunks with a chunk loaded, keyed by [Heightmap::column_origin].
+    /// Heights only drop when blocks are removed, not when the chunk holding the top block unloads,
+    /// so unloading chunks above the ground doesn't open the ground up to the sky.
+    heightmaps: HashMap<BlockPosition, Heightmap>,
+    region_storage: Option<RegionStorage>,
+    /// Chunks that were loaded, unloaded or had blocks changed since the last
+    /// [World::take_dirty_chunks], keyed by chunk origin.
+    /// Changes made directly through [World::chunks] are not tracked.
+    dirty_chunks: HashSet<BlockPosition>,
+    /// Positions whose blocks were set with [World::set_blocks] since the last [World::take_changed_blocks].
+    changed_blocks: HashSet<BlockPosition>,
+    /// Chunks that were generated or changed since they were last saved, keyed by chunk origin.
+    /// Only these are saved when they are unloaded, the rest are already saved as they are.
+    /// Like [World::dirty_chunks], changes made directly through [World::chunks] are not tracked.
+    unsaved_chunks: HashSet<BlockPosition>,
+    /// The number of ticks the [WorldTicker](super::world_ticker::WorldTicker) has run.
+    /// It isn't saved, scheduled ticks are saved relative to it instead.
+    current_tick: u64,
 }
 
 impl World {
+    pub fn new(chunks: HashMap<BlockPosition, Chunk>, block_registry: Arc<BlockRegistry>) -> Self {
+        let chunk_origins: Vec<BlockPosition> = chunks.keys().copied().collect();
+        let mut world = World {
+            dirty_chunks: chunk_origins.iter().copied().collect(),
+            unsaved_chunks: chunk_origins.iter().copied().collect(),
+            chunks,
+            block_registry,
+            heightmaps: HashMap::new(),
+            region_storage: None,
+            changed_blocks: HashSet::new(),
+            current_tick: 0,
+        };
+
+        // Every height is known before any chunk is lit, so no column has to be relit.
+        for chunk_origin in &chunk_origins {
+            world.raise_heights(*chunk_origin);
+        }
+
+        for channel in [LightChannel::Block, LightChannel::Sky] {
+            let mut light_propagator = LightPropagator::new(
+                &mut world.chunks,
+                &world.block_registry,
+                &world.heightmaps,
+                channel,
+            );
+            light_propagator.light_chunks(&chunk_origins);
+        }
+
+        world
+    }
+
+    /// Creates an empty world that loads from and saves to the directory.
+    /// Unlike [World::load] the directory does not need to exist yet.
+    pub fn open(directory: impl AsRef<Path>, block_registry: Arc<BlockRegistry>) -> Self {
+        World {
+            chunks: HashMap::new(),
+            block_registry: block_registry.clone(),
+            heightmaps: HashMap::new(),
+            region_storage: Some(RegionStorage::new(directory, block_registry)),
+            dirty_chunks: HashSet::new(),
+            changed_blocks: HashSet::new(),
+            unsaved_chunks: HashSet::new(),
+            current_tick: 0,
+        }
+    }
+
+    /// Opens the world saved in the directory.
+    /// No chunks are loaded until they are requested with [World::load_chunk].
+    pub fn load(
+        directory: impl AsRef<Path>,
+        block_registry: Arc<BlockRegistry>,
+    ) -> Result<Self, RegionError> {
+        let directory = directory.as_ref();
+        if !directory.is_dir() {
+            return Err(RegionError::Io(std::io::ErrorKind::NotFound.into()));
+        }
+
+        Ok(World::open(directory, block_registry))
+    }
+
+    /// Loads the chunk from the world's save, if it has one.
+    /// Returns whether the chunk is loaded afterwards.
+    pub fn load_chunk(&mut self, chunk_origin: BlockPosition) -> Result<bool, RegionError> {
+        if self.chunks.contains_key(&chunk_origin) {
+            return Ok(true);
+        }
+
+        let Some(region_storage) = &mut self.region_storage else {
+            return Ok(false);
+        };
+
+        match region_storage.load_chunk(chunk_origin, self.current_tick)? {
+            Some(chunk) => {
+                self.insert_chunk(chunk);
+                // The chunk is as it was saved, so it doesn't need saving again until it changes.
+                self.unsaved_chunks.remove(&chunk_origin);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Adds the chunk to the world, replacing any chunk already loaded at its origin, and lights it.
+    /// The chunks around it that its light spreads into, or that it shades from the sky,
+    /// are marked dirty too. The chunk is saved when it is unloaded.
+    ///
+    /// Heights only rise, if the chunk replaces one the heights of its columns are kept
+    /// even if it is shorter.
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        let chunk_origin = chunk.origin_position();
+
+        self.chunks.insert(chunk_origin, chunk);
+        self.dirty_chunks.insert(chunk_origin);
+        self.unsaved_chunks.insert(chunk_origin);
+        let height_changes = self.raise_heights(chunk_origin);
+
+        let mut light_propagator = LightPropagator::new(
+            &mut self.chunks,
+            &self.block_registry,
+            &self.heightmaps,
+            LightChannel::Block,
+        );
+        light_propagator.light_chunk(chunk_origin);
+        let changed_chunk_origins = light_propagator.changed_chunk_origins();
+        self.dirty_chunks.extend(changed_chunk_origins);
+
+        let mut light_propagator = LightPropagator::new(
+            &mut self.chunks,
+            &self.block_registry,
+            &self.heightmaps,
+            LightChannel::Sky,
+        );
+        for (column_position, old_height, new_height) in height_changes {
+            light_propagator.update_sky_column(column_position, old_height, new_height);
+        }
+        light_propagator.light_chunk(chunk_origin);
+        let changed_chunk_origins = light_propagator.changed_chunk_origins();
+        self.dirty_chunks.extend(changed_chunk_origins);
+    }
+
+    /// Adds the loaded chunk to its column's heightmap, raising the height of each column
+    /// whose topmost opaque block is in the chunk.
+    /// Returns the columns that changed along with their heights before and after.
+    fn raise_heights(
+        &mut self,
+        chunk_origin: BlockPosition,
+    ) -> Vec<(BlockPosition, Option<i32>, Option<i32>)> {
+        let Some(chunk) = self.chunks.get(&chunk_origin) else {
+            return Vec::new();
+        };
+        let heightmap = self
+            .heightmaps
+            .entry(Heightmap::column_origin(chunk_origin))
+            .or_default();
+        heightmap.add_chunk(chunk_origin);
+
+        let mut height_changes = Vec::new();
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                let column_position = chunk_origin.offset(x, 0, z);
+                let top_y = (0..CHUNK_BLOCK_HEIGHT as i32).rev().find_map(|y| {
+                    let position = column_position.offset(0, y, 0);
+                    let block = chunk.get_block_at_position(position).unwrap_or(None)?;
+                    match self.block_registry.block_type_of(&block).is_opaque(&block) {
+                        true => Some(position.y),
+                        false => None,
+                    }
+                });
+
+                let height = heightmap.get(column_position);
+                if top_y.is_some() && top_y > height {
+                    heightmap.set(column_position, top_y);
+                    height_changes.push((column_position, height, top_y));
+                }
+            }
+        }
+
+        height_changes
+    }
+
+    /// Updates the height of the position's column after the block there was changed,
+    /// returning the column's height before and after.
+    fn update_height(&mut self, position: BlockPosition) -> (Option<i32>, Option<i32>) {
+        let column_origin = Heightmap::column_origin(position);
+        let Some(height) = self
+            .heightmaps
+            .get(&column_origin)
+            .map(|heightmap| heightmap.get(position))
+        else {
+            return (None, None);
+        };
+
+        let new_height = if self.is_opaque_at(position) {
+            height.max(Some(position.y))
+        } else if height == Some(position.y) {
+            self.highest_opaque_block_below(position)
+        } else {
+            height
+        };
+
+        if let Some(heightmap) = self.heightmaps.get_mut(&column_origin) {
+            heightmap.set(position, new_height);
+        }
+
+        (height, new_height)
+    }
+
+    /// The y of the highest opaque block below the position, out of the loaded chunks.
+    fn highest_opaque_block_below(&self, position: BlockPosition) -> Option<i32> {
+        let heightmap = self.heightmaps.get(&Heightmap::column_origin(position))?;
+
+        heightmap
+            .chunk_ys()
+            .range(..=position.y)
+            .rev()
+            .flat_map(|&chunk_y| {
+                let chunk_top = chunk_y + CHUNK_BLOCK_HEIGHT as i32 - 1;
+                (chunk_y..=chunk_top.min(position.y - 1)).rev()
+            })
+            .find(|y| self.is_opaque_at(BlockPosition::new(position.x, *y, position.z)))
+    }
+
+    fn is_opaque_at(&self, position: BlockPosition) -> bool {
+        self.get_block_at_position(position)
+            .is_some_and(|block| self.block_registry.block_type_of(&block).is_opaque(&block))
+    }
+
+    /// Updates the heights and relights the blocks around each of the positions after the
+    /// blocks there were changed, marking the chunks whose light changed dirty.
+    fn relight_blocks(&mut self, positions: &[BlockPosition]) {
+        let height_changes: Vec<(BlockPosition, Option<i32>, Option<i32>)> = positions
+            .iter()
+            .map(|position| {
+                let (old_height, new_height) = self.update_height(*position);
+                (*position, old_height, new_height)
+            })
+            .collect();
+
+        let mut light_propagator = LightPropagator::new(
+            &mut self.chunks,
+            &self.block_registry,
+            &self.heightmaps,
+            LightChannel::Block,
+        );
+        for position in positions {
+            light_propagator.update_block(*position);
+        }
+        let changed_chunk_origins = light_propagator.changed_chunk_origins();
+        self.dirty_chunks.extend(changed_chunk_origins);
+
+        let mut light_propagator = LightPropagator::new(
+            &mut self.chunks,
+            &self.block_registry,
+            &self.heightmaps,
+            LightChannel::Sky,
+        );
+        for (position, old_height, new_height) in height_changes {
+            light_propagator.update_block(position);
+            if old_height != new_height {
+                light_propagator.update_sky_column(position, old_height, new_height);
+            }
+        }
+        let changed_chunk_origins = light_propagator.changed_chunk_origins();
+        self.dirty_chunks.extend(changed_chunk_origins);
+    }
+
+    /// Saves the chunks that changed since they were loaded to the world's save, if it has one,
+    /// and then unloads them.
+    /// The chunks are compacted first so blocks removed from them aren't saved in their palettes.
+    pub fn unload_chunks(&mut self, chunk_origins: &[BlockPosition]) -> Result<(), RegionError> {
+        let unsaved_chunk_origins: Vec<BlockPosition> = chunk_origins
+            .iter()
+            .copied()
+            .filter(|chunk_origin| self.unsaved_chunks.contains(chunk_origin))
+            .collect();
+        for chunk_origin in &unsaved_chunk_origins {
+            if let Some(chunk) = self.chunks.get_mut(chunk_origin) {
+                chunk.compact();
+            }
+        }
+
+        if let Some(region_storage) = &mut self.region_storage {
+            region_storage.save_chunks(
+                unsaved_chunk_origins
+                    .iter()
+                    .filter_map(|chunk_origin| self.chunks.get(chunk_origin)),
+                self.current_tick,
+            )?;
+        }
+
+        for chunk_origin in chunk_origins {
+            self.unsaved_chunks.remove(chunk_origin);
+            if self.chunks.remove(chunk_origin).is_some() {
+                self.dirty_chunks.insert(*chunk_origin);
+
+                let column_origin = Heightmap::column_origin(*chunk_origin);
+                let is_column_unloaded = self
+                    .heightmaps
+                    .get_mut(&column_origin)
+                    .is_some_and(|heightmap| heightmap.remove_chunk(*chunk_origin));
+                if is_column_unloaded {
+                    self.heightmaps.remove(&column_origin);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Saves every loaded chunk into region files in the directory.
+    /// Chunks already saved in the directory that are not loaded are kept as they are.
+    /// The chunks are compacted first so blocks removed from them aren't saved in their palettes.
+    pub fn save(&mut self, directory: impl AsRef<Path>) -> Result<(), RegionError> {
+        for chunk in self.chunks.values_mut() {
+            chunk.compact();
+        }
+
+        let mut region_storage = RegionStorage::new(directory, self.block_registry.clone());
+        region_storage.save_chunks(self.chunks.values(), self.current_tick)?;
+        self.region_storage = Some(region_storage);
+        self.unsaved_chunks.clear();
+
+        Ok(())
+    }
+
+    /// Sets the block at the position, if its chunk is loaded, and relights the blocks around it.
+    /// See [World::set_blocks] for the chunks that are marked dirty.
     pub fn set_block_at_position(&mut self, position: BlockPosition, block: Block) {
-        self.chunks
-            .get_mut(&position.to_chunk_origin())
-            .and_then(|chunk| Some(chunk.set_block_at_position(position, block)));
+        self.set_blocks(&[(position, block)]);
+    }
+
+    /// Sets each block whose chunk is loaded, and then relights them all at once.
+    /// The chunks of the blocks, any neighbouring chunk that touches a block (even only by an edge
+    /// or corner, as the block shades the ambient occlusion of the faces around it), and any chunk
+    /// whose light changed are marked dirty, so each is remeshed once however many of its blocks changed.
+    pub fn set_blocks(&mut self, blocks: &[(BlockPosition, Block)]) {
+        let mut set_positions = Vec::new();
+
+        for (position, block) in blocks {
+            let chunk_origin = position.to_chunk_origin();
+            let Some(chunk) = self.chunks.get_mut(&chunk_origin) else {
+                continue;
+            };
+
+            chunk.set_block_at_position(*position, *block);
+            set_positions.push(*position);
+            self.changed_blocks.insert(*position);
+            self.dirty_chunks.insert(chunk_origin);
+            self.unsaved_chunks.insert(chunk_origin);
+
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        let neighbor_chunk_origin = position.offset(x, y, z).to_chunk_origin();
+                        if self.chunks.contains_key(&neighbor_chunk_origin) {
+                            self.dirty_chunks.insert(neighbor_chunk_origin);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.relight_blocks(&set_positions);
+    }
+
+    /// Returns the chunks that were loaded, unloaded or changed since this was last called.
+    pub fn take_dirty_chunks(&mut self) -> HashSet<BlockPosition> {
+        std::mem::take(&mut self.dirty_chunks)
+    }
+
+    /// Returns the positions of the blocks set since this was last called.
+    pub fn take_changed_blocks(&mut self) -> HashSet<BlockPosition> {
+        std::mem::take(&mut self.changed_blocks)
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    pub fn advance_tick(&mut self) {
+        self.current_tick += 1;
+    }
+
+    /// Schedules a tick for the block at the position in `delay` ticks (at least 1), if its chunk is loaded.
+    /// If the block already has a tick scheduled, the sooner of the two is kept.
+    /// Scheduled ticks are saved and unloaded along with their chunk.
+    pub fn schedule_tick(&mut self, position: BlockPosition, delay: u32) {
+        let due_tick = self.current_tick + delay.max(1) as u64;
+        let chunk_origin = position.to_chunk_origin();
+        if let Some(chunk) = self.chunks.get_mut(&chunk_origin) {
+            chunk.schedule_tick(position, due_tick);
+            self.unsaved_chunks.insert(chunk_origin);
+        }
+    }
+
+    /// Removes the scheduled ticks that are due in the current tick from the loaded chunks,
+    /// returning their positions.
+    pub fn take_due_ticks(&mut self) -> Vec<BlockPosition> {
+        let mut due_positions = Vec::new();
+        for (chunk_origin, chunk) in self.chunks.iter_mut() {
+            let chunk_due_positions = chunk.take_due_ticks(self.current_tick);
+            if !chunk_due_positions.is_empty() {
+                self.unsaved_chunks.insert(*chunk_origin);
+                due_positions.extend(chunk_due_positions);
+            }
+        }
+
+        due_positions
     }
 
     /// Get the block at the position from the loaded chunks.
@@ -26,4 +454,273 @@ impl World {
             .and_then(|chunk| chunk.get_block_at_position(position).unwrap_or(None))
     }
 
+    /// Gets the biome of the column the position is in, if its chunk is loaded.
+    pub fn get_biome_at_position(&self, position: BlockPosition) -> Option<Biome> {
+        self.chunks
+            .get(&position.to_chunk_origin())
+            .map(|chunk| chunk.get_biome_at_position(position))
+    }
+
+    /// Gets the y of the topmost opaque block in the column the position is in,
+    /// out of the blocks in loaded chunks. Only the x and z of the position are used.
+    /// Returns `None` if no chunk in the column is loaded, or the column has no opaque blocks.
+    pub fn get_height_at_position(&self, position: BlockPosition) -> Option<i32> {
+        self.heightmaps
+            .get(&Heightmap::column_origin(position))
+            .and_then(|heightmap| heightmap.get(position))
+    }
+
+    /// Gets the light level of the channel at the position, 0 if its chunk isn't loaded.
+    pub fn get_light_at_position(&self, position: BlockPosition, channel: LightChannel) -> u8 {
+        self.chunks
+            .get(&position.to_chunk_origin())
+            .map_or(0, |chunk| {
+                chunk.get_light_at_position(position, channel).unwrap_or(0)
+            })
+    }
+
+    /// Finds the first non air block along the ray, up to `max_distance` away from the origin.
+    ///
+    /// The ray steps through the grid one block boundary at a time (a DDA traversal),
+    /// so no block along the ray is skipped. Blocks in chunks that are not loaded are treated as air.
+    pub fn raycast(
+        &self,
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == glam::Vec3::ZERO {
+            return None;
+        }
+
+        let mut position = origin.floor().as_ivec3();
+        let step = glam::IVec3::new(
+            World::step_towards(direction.x),
+            World::step_towards(direction.y),
+            World::step_towards(direction.z),
+        );
+        // How far along the ray it is between block boundaries on each axis.
+        // The ray never crosses a boundary on axes it doesn't move along.
+        let is_stationary = direction.cmpeq(glam::Vec3::ZERO);
+        let boundary_distance =
+            glam::Vec3::select(is_stationary, glam::Vec3::INFINITY, direction.abs().recip());
+        // How far along the ray the next block boundary is on each axis.
+        // Set directly on stationary axes, a whole number origin would multiply 0 by infinity.
+        let mut next_boundary_distance = glam::Vec3::select(
+            is_stationary,
+            glam::Vec3::INFINITY,
+            glam::Vec3::select(
+                direction.cmpgt(glam::Vec3::ZERO),
+                (position.as_vec3() + 1.0 - origin) * boundary_distance,
+                (origin - position.as_vec3()) * boundary_distance,
+            ),
+        );
+
+        let mut distance = 0.0;
+        let mut face = World::entered_face(World::major_axis(direction), step);
+
+        loop {
+            let block_position = BlockPosition::new(position.x, position.y, position.z);
+            if let Some(block) = self.get_block_at_position(block_position) {
+                return Some(RaycastHit {
+                    block_position,
+                    block,
+                    face,
+                    point: origin + direction * distance,
+                    distance,
+                });
+            }
+
+            let axis = World::nearest_axis(next_boundary_distance);
+            distance = next_boundary_distance[axis];
+            if distance > max_distance {
+                return None;
+            }
+
+            next_boundary_distance[axis] += boundary_distance[axis];
+            position[axis] += step[axis];
+            face = World::entered_face(axis, step);
+        }
+    }
+
+    fn step_towards(direction: f32) -> i32 {
+        if direction > 0.0 {
+            1
+        } else if direction < 0.0 {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// The axis the direction moves along the most.
+    fn major_axis(direction: glam::Vec3) -> usize {
+        let direction = direction.abs();
+        if direction.x >= direction.y && direction.x >= direction.z {
+            0
+        } else if direction.y >= direction.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The axis with the smallest distance.
+    fn nearest_axis(distances: glam::Vec3) -> usize {
+        if distances.x <= distances.y && distances.x <= distances.z {
+            0
+        } else if distances.y <= distances.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The face of a block that a ray stepping along the axis enters it through.
+    fn entered_face(axis: usize, step: glam::IVec3) -> Direction {
+        match (axis, step[axis] > 0) {
+            (0, true) => Direction::East,
+            (0, false) => Direction::West,
+            (1, true) => Direction::Down,
+            (1, false) => Direction::Up,
+            (_, true) => Direction::South,
+            (_, false) => Direction::North,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block_registry::test_registry;
+
+    /// A world of empty chunks with stone at each of the positions.
+    fn test_world(chunk_origins: &[BlockPosition], stone_positions: &[BlockPosition]) -> World {
+        let block_registry = Arc::new(test_registry(""));
+        let stone = block_registry.block("test:stone").unwrap();
+
+        let mut chunks: HashMap<BlockPosition, Chunk> = chunk_origins
+            .iter()
+            .map(|chunk_origin| (*chunk_origin, Chunk::empty(*chunk_origin)))
+            .collect();
+        for position in stone_positions {
+            chunks
+                .get_mut(&position.to_chunk_origin())
+                .unwrap()
+                .set_block_at_position(*position, stone);
+        }
+
+        World::new(chunks, block_registry)
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_blocks() {
+        let world = test_world(
+            &[BlockPosition::new(0, 96, 0)],
+            &[
+                BlockPosition::new(5, 100, 0),
+                BlockPosition::new(0, 104, 0),
+                BlockPosition::new(0, 100, 7),
+            ],
+        );
+
+        // The origin sits on a block boundary on the axes the ray doesn't move along.
+        let origin = glam::Vec3::new(0.5, 100.0, 0.5);
+        let rays = [
+            (
+                glam::Vec3::X,
+                BlockPosition::new(5, 100, 0),
+                Direction::East,
+                4.5,
+            ),
+            (
+                glam::Vec3::Y,
+                BlockPosition::new(0, 104, 0),
+                Direction::Down,
+                4.0,
+            ),
+            (
+                glam::Vec3::Z,
+                BlockPosition::new(0, 100, 7),
+                Direction::South,
+                6.5,
+            ),
+        ];
+        for (direction, block_position, face, distance) in rays {
+            let hit = world.raycast(origin, direction, 10.0).unwrap();
+            assert_eq!(hit.block_position, block_position);
+            assert_eq!(hit.face, face);
+            assert_eq!(hit.distance, distance);
+        }
+
+        assert!(world.raycast(origin, -glam::Vec3::X, 10.0).is_none());
+    }
+
+    #[test]
+    fn rays_cross_chunk_borders() {
+        let world = test_world(
+            &[BlockPosition::new(0, 0, 0), BlockPosition::new(16, 0, 0)],
+            &[BlockPosition::new(18, 3, 3)],
+        );
+
+        let hit = world
+            .raycast(glam::Vec3::new(14.5, 3.5, 3.5), glam::Vec3::X, 10.0)
+            .unwrap();
+        assert_eq!(hit.block_position, BlockPosition::new(18, 3, 3));
+        assert_eq!(hit.face, Direction::East);
+        assert_eq!(hit.distance, 3.5);
+
+        assert!(world
+            .raycast(glam::Vec3::new(14.5, 3.5, 3.5), glam::Vec3::X, 3.0)
+            .is_none());
+    }
+
+    #[test]
+    fn rays_hit_blocks_at_negative_coordinates() {
+        let world = test_world(
+            &[
+                BlockPosition::new(-16, -16, -16),
+                BlockPosition::new(-16, 0, -16),
+            ],
+            &[BlockPosition::new(-3, -1, -3)],
+        );
+
+        let hit = world
+            .raycast(
+                glam::Vec3::new(-2.5, 1.5, -2.5),
+                glam::Vec3::new(-0.2, -2.0, -0.2),
+                10.0,
+            )
+            .unwrap();
+        assert_eq!(hit.block_position, BlockPosition::new(-3, -1, -3));
+        assert_eq!(hit.face, Direction::Up);
+    }
+
+    #[test]
+    fn heights_are_the_topmost_opaque_block_of_the_loaded_chunks() {
+        let mut world = test_world(
+            &[BlockPosition::new(0, 0, 0), BlockPosition::new(0, 16, 0)],
+            &[BlockPosition::new(3, 2, 3), BlockPosition::new(3, 20, 3)],
+        );
+
+        assert_eq!(
+            world.get_height_at_position(BlockPosition::new(3, 0, 3)),
+            Some(20)
+        );
+        assert_eq!(
+            world.get_height_at_position(BlockPosition::new(4, 0, 3)),
+            None
+        );
+        assert_eq!(
+            world.get_height_at_position(BlockPosition::new(40, 0, 3)),
+            None
+        );
+
+        world.set_block_at_position(BlockPosition::new(3, 20, 3), Block::default());
+        assert_eq!(
+            world.get_height_at_position(BlockPosition::new(3, 100, 3)),
+            Some(2)
+        );
+    }
 }
@@ -1,46 +1,357 @@
-use std::collections::HashMap;
-
+use std::ops::RangeInclusive;
 
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use strum::IntoEnumIterator;
 
 use crate::world::{
+    biome::{Biome, BiomeSource},
+    block::Block,
     block_position::BlockPosition,
-    chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    block_registry::BlockRegistry,
+    cave_carver::CaveCarver,
+    chunk::{Chunk, CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH, CHUNK_COLUMN_COUNT},
+    structure_placer::{StructurePlacer, SurfaceColumn},
 };
 
-use super::world::World;
-
-pub struct WorldGenerationSettings {
-    pub max_width: i32,
-    pub max_height: i32,
-    pub max_length: i32,
-}
-
-pub fn generate_world(_seed: u64, settings: WorldGenerationSettings) -> World {
-    let mut chunks: HashMap<BlockPosition, Chunk> = HashMap::new();
-    for x in -settings.max_width..settings.max_width {
-        for y in 0..settings.max_height {
-            for z in -settings.max_length..settings.max_length {
-                let origin_position = BlockPosition::new(
-                    x as i32 * CHUNK_BLOCK_WIDTH as i32,
-                    y as i32 * CHUNK_BLOCK_HEIGHT as i32,
-                    z as i32 * CHUNK_BLOCK_DEPTH as i32,
-                );
-                chunks.insert(origin_position, generate_chunk(origin_position));
-            }
+const TERRAIN_NOISE_FREQUENCY: f64 = 1.0 / 256.0;
+const TERRAIN_NOISE_OCTAVES: usize = 5;
+
+/// How many blocks of dirt sit between the surface block and the stone below it.
+const DIRT_DEPTH: i32 = 3;
+/// Surfaces this close to sea level become beaches.
+const BEACH_HEIGHT: i32 = 1;
+
+const TERRAIN_HEIGHT_NOISE_SALT: u32 = 0;
+const TEMPERATURE_NOISE_SALT: u32 = 1;
+const HUMIDITY_NOISE_SALT: u32 = 2;
+const CAVE_NOISE_SALT: u32 = 3;
+
+/// Runs every generation pass over a chunk.
+pub struct ChunkGenerator {
+    terrain_generator: TerrainGenerator,
+    cave_carver: CaveCarver,
+    structure_placer: StructurePlacer,
+}
+
+impl ChunkGenerator {
+    pub fn new(seed: u64, sea_level: i32, block_registry: &BlockRegistry) -> Self {
+        let terrain_generator = TerrainGenerator::new(seed, sea_level, block_registry);
+        let cave_carver = CaveCarver::new(
+            seed,
+            noise_seed(seed, CAVE_NOISE_SALT),
+            sea_level,
+            terrain_generator.water,
+        );
+
+        ChunkGenerator {
+            terrain_generator,
+            cave_carver,
+            structure_placer: StructurePlacer::new(seed, sea_level, block_registry),
+        }
+    }
+
+    /// Generates the chunk, along with the parts of the structures around it that reach into it.
+    pub fn generate_chunk(&self, origin_position: BlockPosition) -> Chunk {
+        // For each block in the chunk
+        // Apply generation rules to determine what block to place
+        // TODO (Michael): We need a way to determine the rules
+        // Probably something like generate biome from noise
+        // The biome is a set of parameters that determine things like hilliness, water density, etc
+        // Then do a decoration pass for things like trees, caves, etc
+        // For now we can just add some basic noise
+        let (mut chunk, surface_heights) = self.terrain_generator.generate_chunk(origin_position);
+        self.cave_carver.carve_chunk(&mut chunk, &surface_heights);
+        if self
+            .structure_placer
+            .can_reach_chunk(origin_position, &self.terrain_generator.surface_heights)
+        {
+            self.structure_placer.place_structures(&mut chunk, |x, z| {
+                self.terrain_generator.surface_column(x, z)
+            });
+        }
+
+        chunk.compact();
+        chunk
+    }
+}
+
+/// Derives the seed of a single noise layer from the world seed,
+/// so that every layer is different but still only depends on the world seed.
+fn noise_seed(seed: u64, salt: u32) -> u32 {
+    ((seed ^ (seed >> 32)) as u32).wrapping_add(salt.wrapping_mul(0x9E37_79B9))
+}
+
+/// The surface and subsurface blocks of a biome, looked up once from the registry.
+struct BiomeBlocks {
+    surface: Block,
+    subsurface: Block,
+}
+
+/// Generates the base terrain of a chunk from a fractal noise heightmap shaped by the biomes.
+/// The output only depends on the seed and the chunk's position,
+/// so chunks can be generated in any order and on any thread.
+struct TerrainGenerator {
+    height_noise: Fbm<Perlin>,
+    biome_source: BiomeSource,
+    sea_level: i32,
+    /// The lowest and highest the surface of any column can be.
+    surface_heights: RangeInclusive<i32>,
+
+    /// Indexed by the [Biome] discriminant.
+    biome_blocks: Vec<BiomeBlocks>,
+    stone: Block,
+    sand: Block,
+    water: Block,
+}
+
+/// The terrain generation inputs of a single column.
+struct Column {
+    biome: Biome,
+    surface_height: i32,
+}
+
+impl TerrainGenerator {
+    fn new(seed: u64, sea_level: i32, block_registry: &BlockRegistry) -> Self {
+        let block = |identifier: &str| {
+            block_registry
+                .block(identifier)
+                .unwrap_or_else(|| panic!("{identifier} is not registered"))
+        };
+
+        let height_noise = Fbm::<Perlin>::new(noise_seed(seed, TERRAIN_HEIGHT_NOISE_SALT))
+            .set_octaves(TERRAIN_NOISE_OCTAVES)
+            .set_frequency(TERRAIN_NOISE_FREQUENCY);
+        let surface_heights = TerrainGenerator::surface_heights(&height_noise);
+
+        TerrainGenerator {
+            height_noise,
+            biome_source: BiomeSource::new(
+                noise_seed(seed, TEMPERATURE_NOISE_SALT),
+                noise_seed(seed, HUMIDITY_NOISE_SALT),
+            ),
+            sea_level,
+            surface_heights,
+            biome_blocks: Biome::iter()
+                .map(|biome| {
+                    let parameters = biome.parameters();
+                    BiomeBlocks {
+                        surface: block(parameters.surface_block),
+                        subsurface: block(parameters.subsurface_block),
+                    }
+                })
+                .collect(),
+            stone: block("vox:stone"),
+            sand: block("vox:sand"),
+            water: block("vox:water"),
+        }
+    }
+
+    /// The range the surface height of every column falls within.
+    ///
+    /// The biome weights sum to 1, so a column's height is a blend of the biomes' heights and
+    /// can't leave the range spanned by them.
+    fn surface_heights(height_noise: &Fbm<Perlin>) -> RangeInclusive<i32> {
+        // Each octave of Perlin noise stays within [-1, 1], and Fbm divides their weighted sum
+        // by `1 - persistence^octaves` rather than by the sum of the weights.
+        let octaves = height_noise.octaves as i32;
+        let persistence = height_noise.persistence;
+        let max_noise = (0..octaves)
+            .map(|octave| persistence.powi(octave))
+            .sum::<f64>()
+            / (1.0 - persistence.powi(octaves));
+
+        let (min_height, max_height) = Biome::iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(min_height, max_height), biome| {
+                let parameters = biome.parameters();
+                let amplitude = parameters.height_amplitude * max_noise;
+                (
+                    min_height.min(parameters.base_height - amplitude),
+                    max_height.max(parameters.base_height + amplitude),
+                )
+            },
+        );
+
+        min_height.floor() as i32..=max_height.floor() as i32
+    }
+
+    /// Finds the biome of the column and the y position of its topmost terrain block.
+    ///
+    /// The height parameters of every biome are blended by how much influence the biome has
+    /// over the column, so the terrain height changes smoothly across biome borders.
+    fn column(&self, x: i32, z: i32) -> Column {
+        let biome_weights = self.biome_source.biome_weights(x, z);
+
+        let (base_height, height_amplitude) = biome_weights.iter().fold(
+            (0.0, 0.0),
+            |(base_height, height_amplitude), (biome, weight)| {
+                let parameters = biome.parameters();
+                (
+                    base_height + parameters.base_height * weight,
+                    height_amplitude + parameters.height_amplitude * weight,
+                )
+            },
+        );
+
+        let noise = self.height_noise.get([x as f64, z as f64]);
+
+        Column {
+            biome: BiomeSource::dominant_biome(&biome_weights),
+            surface_height: (base_height + noise * height_amplitude).floor() as i32,
         }
     }
 
-    World { chunks }
+    /// The terrain surface of the column at the position, for placing structures on.
+    fn surface_column(&self, x: i32, z: i32) -> SurfaceColumn {
+        let column = self.column(x, z);
+        let block = self
+            .block_in_column(column.surface_height, &column)
+            .unwrap_or_default();
+
+        SurfaceColumn {
+            height: column.surface_height,
+            biome: column.biome,
+            block,
+        }
+    }
+
+    fn block_in_column(&self, y: i32, column: &Column) -> Option<Block> {
+        let surface_height = column.surface_height;
+        let biome_blocks = &self.biome_blocks[column.biome as usize];
+        let is_beach = surface_height <= self.sea_level + BEACH_HEIGHT;
+
+        if y > surface_height {
+            return (y <= self.sea_level).then_some(self.water);
+        }
+
+        if y == surface_height {
+            return Some(if is_beach {
+                self.sand
+            } else {
+                biome_blocks.surface
+            });
+        }
+
+        if y > surface_height - 1 - DIRT_DEPTH {
+            return Some(if is_beach {
+                self.sand
+            } else {
+                biome_blocks.subsurface
+            });
+        }
+
+        Some(self.stone)
+    }
+
+    /// Fills the chunk with terrain, returning it along with the surface height of every column
+    /// indexed by `x + z * CHUNK_BLOCK_WIDTH`.
+    fn generate_chunk(&self, origin_position: BlockPosition) -> (Chunk, Vec<i32>) {
+        let mut chunk = Chunk::empty(origin_position);
+        let mut surface_heights = vec![0; CHUNK_COLUMN_COUNT];
+
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                let column_position = origin_position.offset(x, 0, z);
+                let column = self.column(column_position.x, column_position.z);
+                chunk.set_biome_at_position(column_position, column.biome);
+                surface_heights[(x + z * CHUNK_BLOCK_WIDTH as i32) as usize] =
+                    column.surface_height;
+
+                for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                    let block_position = column_position.offset_y(y);
+                    if let Some(block) = self.block_in_column(block_position.y, &column) {
+                        chunk.set_block_at_position(block_position, block);
+                    }
+                }
+            }
+        }
+
+        (chunk, surface_heights)
+    }
 }
 
-fn generate_chunk(origin_position: BlockPosition) -> Chunk {
-    // For each block in the chunk
-    // Apply generation rules to determine what block to place
-    // TODO (Michael): We need a way to determine the rules
-    // Probably something like generate biome from noise
-    // The biome is a set of parameters that determine things like hilliness, water density, etc
-    // Then do a decoration pass for things like trees, caves, etc
-    // For now we can just add some basic noise
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block_registry::game_registry;
 
-    Chunk::new(origin_position)
+    const SEED: u64 = 10;
+    const SEA_LEVEL: i32 = 64;
+
+    fn assert_same_chunks(chunk: &Chunk, other: &Chunk) {
+        for x in 0..CHUNK_BLOCK_WIDTH as i32 {
+            for y in 0..CHUNK_BLOCK_HEIGHT as i32 {
+                for z in 0..CHUNK_BLOCK_DEPTH as i32 {
+                    let position = chunk.origin_position().offset(x, y, z);
+                    assert_eq!(
+                        chunk.get_block_at_position(position).unwrap(),
+                        other.get_block_at_position(position).unwrap(),
+                        "blocks differ at {position:?}"
+                    );
+                }
+            }
+        }
+        assert_eq!(chunk.biomes(), other.biomes());
+    }
+
+    #[test]
+    fn terrain_only_depends_on_the_seed_and_position() {
+        let registry = game_registry();
+        let terrain_generator = TerrainGenerator::new(SEED, SEA_LEVEL, &registry);
+        let other_terrain_generator = TerrainGenerator::new(SEED, SEA_LEVEL, &registry);
+
+        for origin_position in [
+            BlockPosition::new(0, 48, 0),
+            BlockPosition::new(0, 64, 0),
+            BlockPosition::new(-32, 64, 48),
+            BlockPosition::new(160, 80, -96),
+        ] {
+            let (chunk, surface_heights) = terrain_generator.generate_chunk(origin_position);
+            let (again, again_surface_heights) = terrain_generator.generate_chunk(origin_position);
+            let (other, other_surface_heights) =
+                other_terrain_generator.generate_chunk(origin_position);
+
+            assert_same_chunks(&chunk, &again);
+            assert_same_chunks(&chunk, &other);
+            assert_eq!(surface_heights, again_surface_heights);
+            assert_eq!(surface_heights, other_surface_heights);
+        }
+    }
+
+    #[test]
+    fn terrain_changes_with_the_seed() {
+        let registry = game_registry();
+        let terrain_generator = TerrainGenerator::new(SEED, SEA_LEVEL, &registry);
+        let other_terrain_generator = TerrainGenerator::new(SEED + 1, SEA_LEVEL, &registry);
+
+        let origin_position = BlockPosition::new(0, 64, 0);
+        let (_, surface_heights) = terrain_generator.generate_chunk(origin_position);
+        let (_, other_surface_heights) = other_terrain_generator.generate_chunk(origin_position);
+
+        assert_ne!(surface_heights, other_surface_heights);
+    }
+    #[test]
+    fn chunks_far_from_the_surface_skip_structures() {
+        let registry = game_registry();
+        let chunk_generator = ChunkGenerator::new(SEED, SEA_LEVEL, &registry);
+        let surface_heights = &chunk_generator.terrain_generator.surface_heights;
+
+        for x in (-2048..2048).step_by(61) {
+            for z in (-2048..2048).step_by(59) {
+                let surface_height = chunk_generator
+                    .terrain_generator
+                    .column(x, z)
+                    .surface_height;
+                assert!(surface_heights.contains(&surface_height));
+            }
+        }
+
+        let structure_placer = &chunk_generator.structure_placer;
+        let sky_chunk_origin = BlockPosition::new(0, *surface_heights.end() + 16, 0);
+        let underground_chunk_origin = BlockPosition::new(0, *surface_heights.start() - 32, 0);
+        let surface_chunk_origin = BlockPosition::new(0, SEA_LEVEL, 0);
+        assert!(!structure_placer.can_reach_chunk(sky_chunk_origin, surface_heights));
+        assert!(!structure_placer.can_reach_chunk(underground_chunk_origin, surface_heights));
+        assert!(structure_placer.can_reach_chunk(surface_chunk_origin, surface_heights));
+    }
 }
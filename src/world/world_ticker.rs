@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use super::{
+    block::Block,
+    block_position::BlockPosition,
+    block_registry::BlockRegistry,
+    chunk::{CHUNK_BLOCK_DEPTH, CHUNK_BLOCK_HEIGHT, CHUNK_BLOCK_WIDTH},
+    seeded_random::SeededRandom,
+    world::World,
+};
+
+/// The most ticks run in a single [WorldTicker::update], so that a long frame is skipped
+/// rather than caught up on all at once.
+const MAX_TICKS_PER_UPDATE: u32 = 5;
+
+/// Salts the random tick positions, see [SeededRandom::at_position].
+const RANDOM_TICK_SALT: u64 = 0x71C4_5EED;
+
+pub struct WorldTickerSettings {
+    /// How many ticks are run every second.
+    pub ticks_per_second: f32,
+    /// How many blocks are picked from each loaded chunk every tick for random ticks.
+    pub random_ticks_per_chunk: u32,
+}
+
+/// The blocks ticked in a single tick.
+#[derive(Default, Debug)]
+pub struct BlockTicks {
+    /// The blocks set since the last tick, see [World::take_changed_blocks].
+    /// Handlers can schedule ticks for the blocks around them that react to their neighbours changing.
+    pub changed: Vec<BlockPosition>,
+    /// The blocks whose scheduled ticks are due, see [World::schedule_tick].
+    pub scheduled: Vec<BlockPosition>,
+    /// Blocks picked at random from the loaded chunks, out of the blocks whose type has
+    /// [random_ticks](super::block_registry::BlockType::random_ticks) set.
+    pub random: Vec<(BlockPosition, Block)>,
+}
+
+/// Reacts to the blocks ticked every tick, e.g. by flowing liquids or growing crops.
+/// Every handler sees every ticked block, and only reacts to the blocks it is for.
+pub trait TickHandler {
+    fn tick(&mut self, world: &mut World, block_ticks: &BlockTicks);
+}
+
+/// Ticks the world at a fixed rate, independent of the frame rate.
+///
+/// Each tick the blocks set since the last tick, the blocks with a scheduled tick due and a few blocks
+/// picked at random from every loaded chunk are passed to each [TickHandler] in the order they were added.
+pub struct WorldTicker {
+    settings: WorldTickerSettings,
+    block_registry: Arc<BlockRegistry>,
+    tick_handlers: Vec<Box<dyn TickHandler>>,
+    /// Time passed since the last tick, in seconds.
+    unticked_time: f32,
+}
+
+impl WorldTicker {
+    pub fn new(settings: WorldTickerSettings, block_registry: Arc<BlockRegistry>) -> Self {
+        WorldTicker {
+            settings,
+            block_registry,
+            tick_handlers: Vec::new(),
+            unticked_time: 0.0,
+        }
+    }
+
+    pub fn add_tick_handler(&mut self, tick_handler: impl TickHandler + 'static) {
+        self.tick_handlers.push(Box::new(tick_handler));
+    }
+
+    /// Runs the ticks that came due in the `delta_time` seconds since the last update.
+    pub fn update(&mut self, world: &mut World, delta_time: f32) {
+        let tick_duration = 1.0 / self.settings.ticks_per_second;
+        self.unticked_time =
+            (self.unticked_time + delta_time).min(tick_duration * MAX_TICKS_PER_UPDATE as f32);
+
+        while self.unticked_time >= tick_duration {
+            self.tick(world);
+            self.unticked_time -= tick_duration;
+        }
+    }
+
+    /// Runs a single tick, and then advances the world to the next one.
+    pub fn tick(&mut self, world: &mut World) {
+        let block_ticks = BlockTicks {
+            changed: world.take_changed_blocks().into_iter().collect(),
+            scheduled: world.take_due_ticks(),
+            random: self.random_blocks(world),
+        };
+
+        for tick_handler in &mut self.tick_handlers {
+            tick_handler.tick(world, &block_ticks);
+        }
+
+        world.advance_tick();
+    }
+
+    /// Picks [WorldTickerSettings::random_ticks_per_chunk] positions from every loaded chunk,
+    /// returning the ones whose blocks take random ticks.
+    /// Chunks without any blocks that take random ticks are skipped.
+    fn random_blocks(&self, world: &World) -> Vec<(BlockPosition, Block)> {
+        let takes_random_ticks =
+            |block: &Block| self.block_registry.block_type_of(block).random_ticks;
+
+        let mut random_blocks = Vec::new();
+        for chunk in world.chunks.values() {
+            if !chunk.blocks().palette().iter().any(takes_random_ticks) {
+                continue;
+            }
+
+            let chunk_origin = chunk.origin_position();
+            let mut random =
+                SeededRandom::at_position(world.current_tick(), chunk_origin, RANDOM_TICK_SALT);
+            for _ in 0..self.settings.random_ticks_per_chunk {
+                let position = chunk_origin.offset(
+                    random.range_i32(0, CHUNK_BLOCK_WIDTH as i32),
+                    random.range_i32(0, CHUNK_BLOCK_HEIGHT as i32),
+                    random.range_i32(0, CHUNK_BLOCK_DEPTH as i32),
+                );
+
+                if let Ok(Some(block)) = chunk.get_block_at_position(position) {
+                    if takes_random_ticks(&block) {
+                        random_blocks.push((position, block));
+                    }
+                }
+            }
+        }
+
+        random_blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs, rc::Rc};
+
+    use super::*;
+    use crate::world::{
+        block::GrowthStageProperty, block_registry::test_registry, chunk::Chunk,
+        crop_growth::CropGrowth,
+    };
+
+    const WHEAT: &str = r#"
+        (
+            identifier: "test:wheat",
+            display_name: "Wheat",
+            solid: false,
+            transparent: true,
+            random_ticks: true,
+            state_properties: [GrowthStage],
+        ),"#;
+
+    /// Records the tick each scheduled block was ticked in.
+    struct ScheduledTickRecorder {
+        ticked: Rc<RefCell<Vec<(u64, BlockPosition)>>>,
+    }
+
+    impl TickHandler for ScheduledTickRecorder {
+        fn tick(&mut self, world: &mut World, block_ticks: &BlockTicks) {
+            let current_tick = world.current_tick();
+            self.ticked.borrow_mut().extend(
+                block_ticks
+                    .scheduled
+                    .iter()
+                    .map(|position| (current_tick, *position)),
+            );
+        }
+    }
+
+    fn settings(ticks_per_second: f32, random_ticks_per_chunk: u32) -> WorldTickerSettings {
+        WorldTickerSettings {
+            ticks_per_second,
+            random_ticks_per_chunk,
+        }
+    }
+
+    #[test]
+    fn update_catches_up_on_the_ticks_that_came_due() {
+        let block_registry = Arc::new(test_registry(WHEAT));
+        let mut world = World::new(Default::default(), block_registry.clone());
+        let mut world_ticker = WorldTicker::new(settings(4.0, 0), block_registry);
+
+        world_ticker.update(&mut world, 0.125);
+        assert_eq!(world.current_tick(), 0);
+        world_ticker.update(&mut world, 0.125);
+        assert_eq!(world.current_tick(), 1);
+        world_ticker.update(&mut world, 0.5);
+        assert_eq!(world.current_tick(), 3);
+
+        // A long frame only runs the most ticks allowed in one update, the rest are skipped.
+        world_ticker.update(&mut world, 10.0);
+        assert_eq!(world.current_tick(), 3 + MAX_TICKS_PER_UPDATE as u64);
+        world_ticker.update(&mut world, 0.0);
+        assert_eq!(world.current_tick(), 3 + MAX_TICKS_PER_UPDATE as u64);
+    }
+
+    #[test]
+    fn scheduled_ticks_survive_saving_and_loading() {
+        let directory =
+            std::env::temp_dir().join(format!("vox-ticker-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let block_registry = Arc::new(test_registry(WHEAT));
+        let chunk_origin = BlockPosition::new(0, 0, 0);
+        let position = BlockPosition::new(3, 4, 5);
+
+        let mut world = World::open(&directory, block_registry.clone());
+        world.insert_chunk(Chunk::empty(chunk_origin));
+        let mut world_ticker = WorldTicker::new(settings(20.0, 0), block_registry.clone());
+        world.schedule_tick(position, 5);
+        world_ticker.tick(&mut world);
+        world_ticker.tick(&mut world);
+        world.unload_chunks(&[chunk_origin]).unwrap();
+
+        // The tick was 3 ticks away when it was saved, it stays that far away in a world
+        // that has only just started ticking.
+        let mut world = World::open(&directory, block_registry.clone());
+        assert!(world.load_chunk(chunk_origin).unwrap());
+        let ticked = Rc::new(RefCell::new(Vec::new()));
+        let mut world_ticker = WorldTicker::new(settings(20.0, 0), block_registry);
+        world_ticker.add_tick_handler(ScheduledTickRecorder {
+            ticked: ticked.clone(),
+        });
+        for _ in 0..6 {
+            world_ticker.tick(&mut world);
+        }
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(*ticked.borrow(), [(3, position)]);
+    }
+
+    #[test]
+    fn random_ticks_grow_crops() {
+        let block_registry = Arc::new(test_registry(WHEAT));
+        let wheat = block_registry.block("test:wheat").unwrap();
+        let wheat_type = block_registry.block_type_of(&wheat);
+        let stone = block_registry.block("test:stone").unwrap();
+        let wheat_origin = BlockPosition::new(0, 0, 0);
+        let stone_origin = BlockPosition::new(16, 0, 0);
+        let chunks = [
+            (wheat_origin, Chunk::filled(wheat_origin, wheat)),
+            (stone_origin, Chunk::filled(stone_origin, stone)),
+        ]
+        .into();
+        let mut world = World::new(chunks, block_registry.clone());
+        let mut world_ticker = WorldTicker::new(settings(20.0, 3), block_registry.clone());
+        world_ticker.add_tick_handler(CropGrowth::new(block_registry.clone()));
+
+        let growth_stages = |world: &World| -> Vec<u8> {
+            let chunk = &world.chunks[&wheat_origin];
+            (0..CHUNK_BLOCK_WIDTH as i32)
+                .flat_map(|x| (0..CHUNK_BLOCK_HEIGHT as i32).map(move |y| (x, y)))
+                .flat_map(|(x, y)| (0..CHUNK_BLOCK_DEPTH as i32).map(move |z| (x, y, z)))
+                .map(|(x, y, z)| {
+                    let block = chunk
+                        .get_block_at_position(BlockPosition::new(x, y, z))
+                        .unwrap()
+                        .unwrap();
+                    wheat_type.growth_stage(&block).unwrap()
+                })
+                .collect()
+        };
+
+        world_ticker.tick(&mut world);
+        let grown_count = growth_stages(&world)
+            .iter()
+            .filter(|growth_stage| **growth_stage == 1)
+            .count();
+        assert!((1..=3).contains(&grown_count));
+        assert_eq!(
+            growth_stages(&world)
+                .iter()
+                .filter(|stage| **stage > 1)
+                .count(),
+            0
+        );
+
+        // Fully grown crops stop growing.
+        let fully_grown = wheat_type
+            .with_state::<GrowthStageProperty>(wheat, 7)
+            .unwrap();
+        world
+            .chunks
+            .insert(wheat_origin, Chunk::filled(wheat_origin, fully_grown));
+        world_ticker.tick(&mut world);
+        assert!(growth_stages(&world).iter().all(|stage| *stage == 7));
+
+        assert!(world.chunks[&stone_origin]
+            .blocks()
+            .palette()
+            .iter()
+            .all(|block| *block == stone));
+    }
+}